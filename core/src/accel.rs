@@ -0,0 +1,44 @@
+//! Accelerometer data shared by the drivers and the state machine
+
+/// Acceleration in milli-g
+#[derive(Clone, Copy, Debug)]
+pub struct Accel {
+    /// X component
+    pub x: i16,
+    /// Y component
+    pub y: i16,
+    /// Z component
+    pub z: i16,
+}
+
+/// Orientation of the iron derived from gravity
+#[derive(Clone, Copy, PartialEq)]
+pub enum Orientation {
+    PortraitUp,
+    PortraitDown,
+    LandscapeRight,
+    LandscapeLeft,
+    /// Lying (nearly) flat, X and Y can't be told apart
+    Flat,
+}
+
+/// Gestures detected by the sensor's tap engine
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Gesture {
+    Tap,
+    DoubleTap,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    /// Nothing acknowledged any of the known sensor addresses
+    NotPresent,
+    /// A device answered but its `WHO_AM_I` doesn't match
+    UnknownDevice(u8),
+    /// The sensor doesn't provide new samples
+    NoData,
+    /// Enabling self-test didn't move the axes as expected
+    SelfTestFailed,
+    /// The requested configuration isn't supported by the sensor
+    InvalidConfig,
+}
@@ -0,0 +1,258 @@
+//! Sensor independent accelerometer interface
+//!
+//! Different hardware revisions of the TS100 are equipped with different
+//! accelerometers. `probe` finds out which one is present and `Driver` hides
+//! the difference from the rest of the firmware.
+
+use i2c::I2cBus;
+use lis2dh12::{self, LIS2DH12};
+use mma8652fc::{self, MMA8652FC};
+
+pub use accel::{Accel, Error, Gesture, Orientation};
+
+/// Tap detection settings
+#[derive(Clone, Copy)]
+pub struct TapConfig {
+    /// Report single taps
+    pub single: bool,
+    /// Report double taps
+    pub double: bool,
+    /// Minimum acceleration of a tap in milli-g
+    pub threshold: u16,
+    /// Maximum duration of a tap above the threshold in ms
+    pub time_limit: u16,
+    /// Dead time after the first tap of a double tap in ms
+    pub latency: u16,
+    /// Time after the latency the second tap has to occur within in ms
+    pub window: u16,
+}
+
+impl TapConfig {
+    /// Double taps only, firm enough not to trigger while soldering
+    #[cfg_attr(clippy, allow(clippy::new_without_default))]
+    pub fn new() -> Self {
+        TapConfig {
+            single: false,
+            double: true,
+            threshold: 1000,
+            time_limit: 50,
+            latency: 100,
+            window: 300,
+        }
+    }
+}
+
+/// Converts `ms` into register counts of `step` µs, saturating at 255
+pub fn steps(ms: u16, step: u32) -> u8 {
+    let steps = ms as u32 * 1000 / step;
+    if steps > 0xFF { 0xFF } else { steps as u8 }
+}
+
+pub trait Accelerometer {
+    /// Reads the latest sample
+    fn sample(&self) -> Accel;
+    /// Reads all samples buffered by the sensor, oldest first, and returns
+    /// how many were written to `samples`
+    fn drain(&self, samples: &mut [Accel]) -> usize {
+        if samples.is_empty() {
+            return 0;
+        }
+        samples[0] = self.sample();
+        1
+    }
+    /// Raises the motion interrupt on INT1 if any axis exceeds `threshold`
+    /// milli-g for `count` consecutive samples
+    fn configure_motion_wake(&self, threshold: u16, count: u8);
+    /// Reads the current orientation
+    fn orientation(&self) -> Orientation;
+    /// Raises the tap interrupt on INT1 for the gestures enabled in `config`
+    fn configure_taps(&self, config: &TapConfig);
+    /// Reads and clears the last detected gesture
+    fn gesture(&self) -> Option<Gesture>;
+}
+
+/// Accelerometers supported by the firmware
+#[derive(Clone, Copy, PartialEq)]
+pub enum Sensor {
+    Mma8652fc,
+    Lis2dh12,
+}
+
+/// Looks for a supported accelerometer on the bus
+pub fn probe<B: I2cBus>(bus: &B) -> Result<Sensor, Error> {
+    if bus.probe(mma8652fc::I2C_ADDRESS) {
+        let id = MMA8652FC(bus).get_register(mma8652fc::Register::WHO_AM_I);
+        return if id == mma8652fc::DEVICE_ID {
+            Ok(Sensor::Mma8652fc)
+        } else {
+            Err(Error::UnknownDevice(id))
+        };
+    }
+
+    if bus.probe(lis2dh12::I2C_ADDRESS) {
+        let id = LIS2DH12(bus).get_register(lis2dh12::Register::WHO_AM_I);
+        return if id == lis2dh12::DEVICE_ID {
+            Ok(Sensor::Lis2dh12)
+        } else {
+            Err(Error::UnknownDevice(id))
+        };
+    }
+
+    Err(Error::NotPresent)
+}
+
+/// Dispatches to the driver of the probed sensor
+pub enum Driver<'a, B: 'a + I2cBus> {
+    Mma8652fc(MMA8652FC<'a, B>),
+    Lis2dh12(LIS2DH12<'a, B>),
+}
+
+impl<'a, B: I2cBus> Driver<'a, B> {
+    pub fn new(sensor: Sensor, bus: &'a B) -> Self {
+        match sensor {
+            Sensor::Mma8652fc => Driver::Mma8652fc(MMA8652FC(bus)),
+            Sensor::Lis2dh12 => Driver::Lis2dh12(LIS2DH12(bus)),
+        }
+    }
+}
+
+impl<'a, B: I2cBus> Accelerometer for Driver<'a, B> {
+    fn sample(&self) -> Accel {
+        match *self {
+            Driver::Mma8652fc(ref sensor) => sensor.sample(),
+            Driver::Lis2dh12(ref sensor) => sensor.sample(),
+        }
+    }
+
+    fn drain(&self, samples: &mut [Accel]) -> usize {
+        match *self {
+            Driver::Mma8652fc(ref sensor) => sensor.drain(samples),
+            Driver::Lis2dh12(ref sensor) => sensor.drain(samples),
+        }
+    }
+
+    fn configure_motion_wake(&self, threshold: u16, count: u8) {
+        match *self {
+            Driver::Mma8652fc(ref sensor) => sensor.configure_motion_wake(threshold, count),
+            Driver::Lis2dh12(ref sensor) => sensor.configure_motion_wake(threshold, count),
+        }
+    }
+
+    fn orientation(&self) -> Orientation {
+        match *self {
+            Driver::Mma8652fc(ref sensor) => sensor.orientation(),
+            Driver::Lis2dh12(ref sensor) => sensor.orientation(),
+        }
+    }
+
+    fn configure_taps(&self, config: &TapConfig) {
+        match *self {
+            Driver::Mma8652fc(ref sensor) => sensor.configure_taps(config),
+            Driver::Lis2dh12(ref sensor) => sensor.configure_taps(config),
+        }
+    }
+
+    fn gesture(&self) -> Option<Gesture> {
+        match *self {
+            Driver::Mma8652fc(ref sensor) => sensor.gesture(),
+            Driver::Lis2dh12(ref sensor) => sensor.gesture(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::{MockI2c, Transaction};
+
+    fn who_am_i(slave: u8, register: u8) -> Option<Transaction> {
+        Some(Transaction::Read {
+            slave,
+            register,
+            len: 1,
+        })
+    }
+
+    #[test]
+    fn probe_finds_the_mma8652fc() {
+        let bus = MockI2c::new();
+        bus.script_read(&[mma8652fc::DEVICE_ID]);
+
+        assert!(probe(&bus) == Ok(Sensor::Mma8652fc));
+        assert_eq!(bus.transaction(1),
+                   who_am_i(mma8652fc::I2C_ADDRESS, mma8652fc::Register::WHO_AM_I.addr()));
+        // the LIS2DH12 isn't looked for
+        assert_eq!(bus.len(), 2);
+    }
+
+    #[test]
+    fn probe_finds_the_lis2dh12() {
+        let bus = MockI2c::new();
+        bus.set_present(mma8652fc::I2C_ADDRESS, false);
+        bus.script_read(&[lis2dh12::DEVICE_ID]);
+
+        assert!(probe(&bus) == Ok(Sensor::Lis2dh12));
+        assert_eq!(bus.transaction(0), Some(Transaction::Probe { slave: mma8652fc::I2C_ADDRESS }));
+        assert_eq!(bus.transaction(1), Some(Transaction::Probe { slave: lis2dh12::I2C_ADDRESS }));
+        assert_eq!(bus.transaction(2),
+                   who_am_i(lis2dh12::I2C_ADDRESS, lis2dh12::Register::WHO_AM_I.addr()));
+        assert_eq!(bus.len(), 3);
+    }
+
+    #[test]
+    fn probe_reports_unknown_ids() {
+        let bus = MockI2c::new();
+        bus.script_read(&[lis2dh12::DEVICE_ID]);
+        // the LIS2DH12 id at the address of the MMA8652FC
+        assert!(probe(&bus) == Err(Error::UnknownDevice(lis2dh12::DEVICE_ID)));
+
+        let bus = MockI2c::new();
+        bus.set_present(mma8652fc::I2C_ADDRESS, false);
+        bus.script_read(&[0xFF]);
+        assert!(probe(&bus) == Err(Error::UnknownDevice(0xFF)));
+    }
+
+    #[test]
+    fn probe_without_sensor() {
+        let bus = MockI2c::new();
+        bus.set_present(mma8652fc::I2C_ADDRESS, false)
+            .set_present(lis2dh12::I2C_ADDRESS, false);
+
+        assert!(probe(&bus) == Err(Error::NotPresent));
+        assert_eq!(bus.len(), 2);
+    }
+
+    #[test]
+    fn driver_dispatches_to_the_probed_sensor() {
+        let bus = MockI2c::new();
+        bus.script_read(&[0x00, 0x40, 0x00, 0x00, 0x00, 0x00]);
+        let accel = Driver::new(Sensor::Lis2dh12, &bus).sample();
+        assert_eq!(accel.x, 1024);
+        assert_eq!(bus.transaction(0),
+                   Some(Transaction::Read {
+                       slave: lis2dh12::I2C_ADDRESS,
+                       register: lis2dh12::Register::OUT_X_L.addr() | 0x80,
+                       len: 6,
+                   }));
+
+        let bus = MockI2c::new();
+        // XYZ_DATA_CFG, ±2 g, then the sample
+        bus.script_read(&[0x00, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00]);
+        let accel = Driver::new(Sensor::Mma8652fc, &bus).sample();
+        assert_eq!(accel.x, 1000);
+        assert_eq!(bus.transaction(1),
+                   Some(Transaction::Read {
+                       slave: mma8652fc::I2C_ADDRESS,
+                       register: mma8652fc::Register::OUT_X_MSB.addr(),
+                       len: 6,
+                   }));
+    }
+
+    #[test]
+    fn steps_saturate() {
+        assert_eq!(steps(50, 10_000), 5);
+        assert_eq!(steps(9, 10_000), 0);
+        assert_eq!(steps(2550, 10_000), 255);
+        assert_eq!(steps(u16::MAX, 625), 255);
+    }
+}
@@ -0,0 +1,676 @@
+//! Line based command console
+//!
+//! Bytes received over the serial link are assembled into lines, each line
+//! is parsed into a `Command` and executed right away, the reply is queued
+//! for transmission. Lines end with `\r` or `\n`, replies with `\r\n`.
+//! Every reply ends with `ok` or `error: REASON`, the lines of data before
+//! it, so a host knows when the reply is complete. Telemetry records start
+//! with `T`, log entries with `L`, both may come at any time. A line which
+//! arrives while the reply to it wouldn't fit into the buffer isn't
+//! executed, it's answered with `error: busy`.
+//!
+//! ```text
+//! > get motion_sensitivity
+//! motion_sensitivity=6
+//! ok
+//! > set motion_sensitivity 4
+//! ok
+//! > telemetry 100
+//! ok
+//! T 1200 tip=318 set=320 duty=12 vin=19012 ax=3 ay=-8 az=1002
+//! ```
+//!
+//! Values which aren't available are sent as `-`, e.g. the setpoint while
+//! the heater is off.
+//!
+//! | Command          | Effect                                             |
+//! |------------------|----------------------------------------------------|
+//! | `help`           | lists the commands                                 |
+//! | `get NAME`       | prints a setting as `NAME=VALUE`                   |
+//! | `set NAME VALUE` | changes a setting, it's lost unless saved          |
+//! | `dump`           | prints all settings                                |
+//! | `save`           | writes the settings to flash                       |
+//! | `defaults`       | restores the default settings, without saving them |
+//! | `reset`          | restarts the iron                                  |
+//! | `telemetry MS`   | streams telemetry every `MS` ms, `off` stops it    |
+//! | `events`         | prints the journal of events, oldest first         |
+
+use core::fmt::{self, Write};
+use core::str::{self, SplitWhitespace};
+use journal::{Cursor, Flash, Journal};
+use log::Entry;
+use settings::Settings;
+use motion;
+use time;
+
+/// Longest accepted line without terminator
+pub const LINE_MAX: usize = 64;
+/// Bytes of replies buffered until they are sent
+const TX_SIZE: usize = 512;
+/// Bytes of the longest reply, `help`; lines are only executed while their
+/// reply fits into the buffer
+const REPLY_MAX: usize = 160;
+/// Fastest telemetry interval in ms
+pub const TELEMETRY_MIN: u32 = 20;
+
+/// A user setting accessible by name
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Setting {
+    MotionSensitivity,
+    AccelOffsetX,
+    AccelOffsetY,
+    AccelOffsetZ,
+}
+
+/// Every setting with its name, in the order of `dump`
+pub const SETTINGS: [(&str, Setting); 4] = [
+    ("motion_sensitivity", Setting::MotionSensitivity),
+    ("accel_offset_x", Setting::AccelOffsetX),
+    ("accel_offset_y", Setting::AccelOffsetY),
+    ("accel_offset_z", Setting::AccelOffsetZ),
+];
+
+impl Setting {
+    pub fn from_name(name: &str) -> Option<Setting> {
+        SETTINGS.iter().find(|entry| entry.0 == name).map(|entry| entry.1)
+    }
+
+    pub fn name(&self) -> &'static str {
+        SETTINGS.iter().find(|entry| entry.1 == *self).map(|entry| entry.0).unwrap_or("?")
+    }
+
+    /// Smallest and largest accepted value
+    #[cfg_attr(clippy, allow(clippy::legacy_numeric_constants))]
+    pub fn range(&self) -> (i32, i32) {
+        match *self {
+            Setting::MotionSensitivity => {
+                (i32::from(motion::SENSITIVITY_MIN), i32::from(motion::SENSITIVITY_MAX))
+            }
+            Setting::AccelOffsetX | Setting::AccelOffsetY | Setting::AccelOffsetZ => {
+                (i32::from(i8::min_value()), i32::from(i8::max_value()))
+            }
+        }
+    }
+
+    pub fn get(&self, settings: &Settings) -> i32 {
+        match *self {
+            Setting::MotionSensitivity => i32::from(settings.motion_sensitivity),
+            Setting::AccelOffsetX => i32::from(settings.accel_offset[0]),
+            Setting::AccelOffsetY => i32::from(settings.accel_offset[1]),
+            Setting::AccelOffsetZ => i32::from(settings.accel_offset[2]),
+        }
+    }
+
+    /// Changes the setting if `value` is in range
+    pub fn set(&self, settings: &mut Settings, value: i32) -> Result<(), Error> {
+        let (min, max) = self.range();
+        if value < min || value > max {
+            return Err(Error::OutOfRange);
+        }
+
+        match *self {
+            Setting::MotionSensitivity => settings.motion_sensitivity = value as u8,
+            Setting::AccelOffsetX => settings.accel_offset[0] = value as i8,
+            Setting::AccelOffsetY => settings.accel_offset[1] = value as i8,
+            Setting::AccelOffsetZ => settings.accel_offset[2] = value as i8,
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Command {
+    Help,
+    Get(Setting),
+    Set(Setting, i32),
+    Dump,
+    Save,
+    Defaults,
+    Reset,
+    /// Telemetry interval in ms, `None` stops it
+    Telemetry(Option<u32>),
+    Events,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    UnknownCommand,
+    UnknownSetting,
+    MissingArgument,
+    TooManyArguments,
+    InvalidNumber,
+    OutOfRange,
+    LineTooLong,
+    InvalidUtf8,
+    /// The reply wouldn't fit into the buffer, the line wasn't executed
+    Busy,
+}
+
+impl Error {
+    pub fn reason(&self) -> &'static str {
+        match *self {
+            Error::UnknownCommand => "unknown command",
+            Error::UnknownSetting => "unknown setting",
+            Error::MissingArgument => "missing argument",
+            Error::TooManyArguments => "too many arguments",
+            Error::InvalidNumber => "invalid number",
+            Error::OutOfRange => "out of range",
+            Error::LineTooLong => "line too long",
+            Error::InvalidUtf8 => "invalid utf-8",
+            Error::Busy => "busy",
+        }
+    }
+}
+
+/// Parses a line without terminator, `Ok(None)` for an empty line
+pub fn parse(line: &str) -> Result<Option<Command>, Error> {
+    let mut words = line.split_whitespace();
+    let command = match words.next() {
+        Some(command) => command,
+        None => return Ok(None),
+    };
+
+    let parsed = match command {
+        "help" => Command::Help,
+        "get" => Command::Get(setting(argument(&mut words)?)?),
+        "set" => {
+            let name = setting(argument(&mut words)?)?;
+            Command::Set(name, number(argument(&mut words)?)?)
+        }
+        "dump" => Command::Dump,
+        "save" => Command::Save,
+        "defaults" => Command::Defaults,
+        "reset" => Command::Reset,
+        "events" => Command::Events,
+        "telemetry" => match argument(&mut words)? {
+            "off" => Command::Telemetry(None),
+            word => {
+                let interval = number(word)?;
+                if interval < TELEMETRY_MIN as i32 {
+                    return Err(Error::OutOfRange);
+                }
+                Command::Telemetry(Some(interval as u32))
+            }
+        },
+        _ => return Err(Error::UnknownCommand),
+    };
+
+    if words.next().is_some() {
+        return Err(Error::TooManyArguments);
+    }
+    Ok(Some(parsed))
+}
+
+fn argument<'a>(words: &mut SplitWhitespace<'a>) -> Result<&'a str, Error> {
+    words.next().ok_or(Error::MissingArgument)
+}
+
+fn setting(name: &str) -> Result<Setting, Error> {
+    Setting::from_name(name).ok_or(Error::UnknownSetting)
+}
+
+fn number(word: &str) -> Result<i32, Error> {
+    word.parse::<i32>().map_err(|_| Error::InvalidNumber)
+}
+
+/// Work requested over the console which the console can't do itself
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Request {
+    SaveSettings,
+    Reset,
+}
+
+/// A snapshot of the iron streamed while telemetry is on
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Telemetry {
+    /// Milliseconds since boot
+    pub time: u32,
+    /// Measured tip temperature in °C
+    pub tip: i16,
+    /// Setpoint in °C, `None` while the heater is off
+    pub setpoint: Option<i16>,
+    /// Heater duty cycle in percent
+    pub duty: u8,
+    /// Supply voltage in mV
+    pub vin: Option<u16>,
+    /// Acceleration in milli-g
+    pub accel: [i16; 3],
+}
+
+impl Telemetry {
+    /// Parses a record as sent by `Console::send_telemetry`
+    pub fn parse(line: &str) -> Option<Telemetry> {
+        let mut words = line.split_whitespace();
+        if words.next() != Some("T") {
+            return None;
+        }
+        let time = words.next()?.parse().ok()?;
+
+        let mut telemetry = Telemetry {
+            time,
+            tip: 0,
+            setpoint: None,
+            duty: 0,
+            vin: None,
+            accel: [0; 3],
+        };
+        let mut seen = 0;
+        for word in words {
+            let mut parts = word.splitn(2, '=');
+            let (key, value) = (parts.next()?, parts.next()?);
+            match key {
+                "tip" => telemetry.tip = value.parse().ok()?,
+                "set" => telemetry.setpoint = maybe(value)?,
+                "duty" => telemetry.duty = value.parse().ok()?,
+                "vin" => telemetry.vin = maybe(value)?,
+                "ax" => telemetry.accel[0] = value.parse().ok()?,
+                "ay" => telemetry.accel[1] = value.parse().ok()?,
+                "az" => telemetry.accel[2] = value.parse().ok()?,
+                // fields added later are skipped
+                _ => continue,
+            }
+            seen += 1;
+        }
+
+        if seen == 7 {
+            Some(telemetry)
+        } else {
+            None
+        }
+    }
+}
+
+/// Parses an optional value written by `Maybe`, `None` if it's invalid
+fn maybe<T: str::FromStr>(value: &str) -> Option<Option<T>> {
+    if value == "-" {
+        Some(None)
+    } else {
+        value.parse().ok().map(Some)
+    }
+}
+
+/// Displays a missing value as `-`
+struct Maybe<T>(Option<T>);
+
+impl<T: fmt::Display> fmt::Display for Maybe<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.0 {
+            Some(ref value) => value.fmt(f),
+            None => f.write_str("-"),
+        }
+    }
+}
+
+/// Queue of bytes waiting for transmission, new lines are dropped while
+/// it's full
+struct TxBuffer {
+    data: [u8; TX_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl TxBuffer {
+    fn free(&self) -> usize {
+        TX_SIZE - self.len
+    }
+
+    fn push(&mut self, byte: u8) {
+        if self.len < TX_SIZE {
+            self.data[(self.head + self.len) % TX_SIZE] = byte;
+            self.len += 1;
+        }
+    }
+}
+
+/// Formats a single reply line into a fixed buffer, so lines which don't
+/// fit into the `TxBuffer` can be dropped as a whole
+struct Line {
+    data: [u8; LINE_MAX + 32],
+    len: usize,
+}
+
+impl Write for Line {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        let bytes = text.as_bytes();
+        if self.len + bytes.len() > self.data.len() {
+            return Err(fmt::Error);
+        }
+        self.data[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+        Ok(())
+    }
+}
+
+/// Progress of the `events` command
+#[derive(Clone, Copy)]
+enum Export {
+    Idle,
+    /// Records are being sent, behind the last one sent if any
+    Running(Option<Cursor>),
+}
+
+pub struct Console {
+    line: [u8; LINE_MAX],
+    len: usize,
+    /// The current line exceeded `LINE_MAX` and is discarded
+    overflow: bool,
+    /// The last byte was `\r`, a following `\n` belongs to it
+    carriage_return: bool,
+    tx: TxBuffer,
+    /// A line of the current reply didn't fit into `tx`
+    dropped: bool,
+    request: Option<Request>,
+    telemetry: Option<u32>,
+    next_telemetry: u32,
+    export: Export,
+}
+
+impl Console {
+    #[cfg_attr(clippy, allow(clippy::new_without_default))]
+    pub const fn new() -> Self {
+        Console {
+            line: [0; LINE_MAX],
+            len: 0,
+            overflow: false,
+            carriage_return: false,
+            tx: TxBuffer {
+                data: [0; TX_SIZE],
+                head: 0,
+                len: 0,
+            },
+            dropped: false,
+            request: None,
+            telemetry: None,
+            next_telemetry: 0,
+            export: Export::Idle,
+        }
+    }
+
+    /// Handles received bytes, executing every completed line
+    pub fn receive(&mut self, data: &[u8], settings: &mut Settings) {
+        for &byte in data {
+            let carriage_return = self.carriage_return;
+            self.carriage_return = byte == b'\r';
+
+            match byte {
+                b'\n' if carriage_return => {}
+                b'\r' | b'\n' => self.end_line(settings),
+                _ if self.len == LINE_MAX => self.overflow = true,
+                _ => {
+                    self.line[self.len] = byte;
+                    self.len += 1;
+                }
+            }
+        }
+    }
+
+    /// Moves up to `buffer.len()` queued bytes into `buffer`
+    pub fn transmit(&mut self, buffer: &mut [u8]) -> usize {
+        let count = buffer.len().min(self.tx.len);
+        for byte in buffer[..count].iter_mut() {
+            *byte = self.tx.data[self.tx.head];
+            self.tx.head = (self.tx.head + 1) % TX_SIZE;
+        }
+        self.tx.len -= count;
+        count
+    }
+
+    pub fn has_output(&self) -> bool {
+        self.tx.len > 0
+    }
+
+    /// Returns and clears the pending request
+    pub fn take_request(&mut self) -> Option<Request> {
+        self.request.take()
+    }
+
+    /// `true` if a telemetry record is due at `now`, schedules the next one
+    pub fn telemetry_due(&mut self, now: u32) -> bool {
+        match self.telemetry {
+            Some(interval) if time::reached(now, self.next_telemetry) => {
+                self.next_telemetry = now.wrapping_add(interval);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Queues a telemetry record, it's dropped if the host doesn't keep up
+    pub fn send_telemetry(&mut self, telemetry: &Telemetry) {
+        let t = telemetry;
+        self.reply(format_args!("T {} tip={} set={} duty={} vin={} ax={} ay={} az={}",
+                                t.time,
+                                t.tip,
+                                Maybe(t.setpoint),
+                                t.duty,
+                                Maybe(t.vin),
+                                t.accel[0],
+                                t.accel[1],
+                                t.accel[2]));
+    }
+
+    /// Queues the records of a running `events` command as far as they fit,
+    /// call again once the output was sent
+    ///
+    /// The export continues behind the last record sent, so records appended
+    /// in the meantime are sent as well and records erased when the ring
+    /// wraps are skipped.
+    pub fn continue_export<F: Flash>(&mut self, journal: &Journal, flash: &F) {
+        while let Export::Running(after) = self.export {
+            self.dropped = false;
+            match journal.next(flash, after) {
+                Some((record, cursor)) => {
+                    self.reply(format_args!("{}", record));
+                    if self.dropped {
+                        return;
+                    }
+                    self.export = Export::Running(Some(cursor));
+                }
+                None => {
+                    self.reply(format_args!("ok"));
+                    if !self.dropped {
+                        self.export = Export::Idle;
+                    }
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Queues a log entry, the USB backend of the log
+    pub fn send_log(&mut self, entry: &Entry) {
+        self.reply(format_args!("L {}", entry));
+    }
+
+    fn end_line(&mut self, settings: &mut Settings) {
+        let result = if self.overflow {
+            Err(Error::LineTooLong)
+        } else {
+            str::from_utf8(&self.line[..self.len]).map_err(|_| Error::InvalidUtf8)
+                .and_then(parse)
+        };
+        let result = match result {
+            Ok(Some(_)) if self.tx.free() < REPLY_MAX => Err(Error::Busy),
+            result => result,
+        };
+        self.len = 0;
+        self.overflow = false;
+
+        // the reply is dropped as a whole if any line doesn't fit
+        let start = self.tx.len;
+        self.dropped = false;
+        match result {
+            Ok(Some(command)) => self.execute(command, settings),
+            Ok(None) => {}
+            Err(error) => self.reply(format_args!("error: {}", error.reason())),
+        }
+        if self.dropped {
+            self.tx.len = start;
+        }
+    }
+
+    fn execute(&mut self, command: Command, settings: &mut Settings) {
+        match command {
+            Command::Help => {
+                self.reply(format_args!("commands: help get set dump save defaults reset \
+                                         telemetry events"));
+                self.reply(format_args!("settings: {} {} {} {}", SETTINGS[0].0, SETTINGS[1].0,
+                                        SETTINGS[2].0, SETTINGS[3].0));
+                self.reply(format_args!("ok"));
+            }
+            Command::Get(setting) => {
+                self.reply(format_args!("{}={}", setting.name(), setting.get(settings)));
+                self.reply(format_args!("ok"));
+            }
+            Command::Set(setting, value) => match setting.set(settings, value) {
+                Ok(()) => self.reply(format_args!("ok")),
+                Err(error) => self.reply(format_args!("error: {}", error.reason())),
+            },
+            Command::Dump => {
+                for &(name, setting) in SETTINGS.iter() {
+                    self.reply(format_args!("{}={}", name, setting.get(settings)));
+                }
+                self.reply(format_args!("ok"));
+            }
+            Command::Save => {
+                self.request = Some(Request::SaveSettings);
+                self.reply(format_args!("ok"));
+            }
+            Command::Defaults => {
+                *settings = Settings::new();
+                self.reply(format_args!("ok"));
+            }
+            Command::Reset => {
+                self.request = Some(Request::Reset);
+                self.reply(format_args!("ok"));
+            }
+            Command::Telemetry(interval) => {
+                self.telemetry = interval;
+                self.reply(format_args!("ok"));
+            }
+            // the records and `ok` follow with `continue_export`
+            Command::Events => self.export = Export::Running(None),
+        }
+    }
+
+    /// Queues a reply line with terminator, drops it if it doesn't fit
+    fn reply(&mut self, args: fmt::Arguments) {
+        let mut line = Line {
+            data: [0; LINE_MAX + 32],
+            len: 0,
+        };
+        if line.write_fmt(args).is_err() || line.write_str("\r\n").is_err()
+            || line.len > self.tx.free()
+        {
+            self.dropped = true;
+            return;
+        }
+
+        for &byte in &line.data[..line.len] {
+            self.tx.push(byte);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use self::std::string::String;
+    use self::std::vec::Vec;
+    use super::*;
+    use journal::{Event, Record, RECORDS_PER_PAGE};
+    use mock::MockFlash;
+
+    /// Sends the queued output and the rest of a running export
+    fn drain(console: &mut Console, journal: &Journal, flash: &MockFlash) -> String {
+        let mut output = Vec::new();
+        let mut packet = [0; 64];
+        loop {
+            console.continue_export(journal, flash);
+            let count = console.transmit(&mut packet);
+            if count == 0 {
+                break;
+            }
+            output.extend_from_slice(&packet[..count]);
+        }
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn replies_fit_into_reply_max() {
+        let (journal, flash) = (Journal::new(), MockFlash::new());
+        for line in ["help", "dump", "get accel_offset_x", "set accel_offset_x -128"] {
+            let mut console = Console::new();
+            let mut settings = Settings::new();
+            console.receive(line.as_bytes(), &mut settings);
+            console.receive(b"\n", &mut settings);
+            assert!(console.tx.len <= REPLY_MAX, "{}", line);
+            assert!(drain(&mut console, &journal, &flash).ends_with("ok\r\n"), "{}", line);
+        }
+    }
+
+    #[test]
+    fn lines_are_not_executed_while_busy() {
+        let (journal, flash) = (Journal::new(), MockFlash::new());
+        let mut console = Console::new();
+        let mut settings = Settings::new();
+        let telemetry = Telemetry {
+            time: 0,
+            tip: 0,
+            setpoint: None,
+            duty: 0,
+            vin: None,
+            accel: [0; 3],
+        };
+        while console.tx.free() >= REPLY_MAX {
+            console.send_telemetry(&telemetry);
+        }
+
+        console.receive(b"set motion_sensitivity 3\r\n\r\n", &mut settings);
+        assert_eq!(settings.motion_sensitivity, Settings::new().motion_sensitivity);
+        assert!(drain(&mut console, &journal, &flash).ends_with("T 0 tip=0 set=- duty=0 vin=- ax=0 \
+                                                                  ay=0 az=0\r\nerror: busy\r\n"));
+
+        console.receive(b"set motion_sensitivity 3\r\n", &mut settings);
+        assert_eq!(settings.motion_sensitivity, 3);
+        assert_eq!(drain(&mut console, &journal, &flash), "ok\r\n");
+    }
+
+    #[test]
+    fn export_continues_behind_the_last_record_sent() {
+        let mut flash = MockFlash::new();
+        let mut journal = Journal::open(&flash);
+        let mut uptime = 0;
+        for _ in 0..RECORDS_PER_PAGE + 10 {
+            journal.append(&mut flash, Event::Watchdog(0), uptime);
+            uptime += 1;
+        }
+
+        let mut console = Console::new();
+        let mut settings = Settings::new();
+        console.receive(b"events\n", &mut settings);
+        console.continue_export(&journal, &flash);
+        let mut output = Vec::new();
+        let mut packet = [0; 64];
+        let count = console.transmit(&mut packet);
+        output.extend_from_slice(&packet[..count]);
+
+        // the ring wraps, erasing the page being sent
+        for _ in 0..RECORDS_PER_PAGE {
+            journal.append(&mut flash, Event::Watchdog(0), uptime);
+            uptime += 1;
+        }
+        let text = String::from_utf8(output).unwrap() + &drain(&mut console, &journal, &flash);
+
+        let lines: Vec<&str> = text.split("\r\n").collect();
+        assert_eq!(lines[lines.len() - 2..], ["ok", ""]);
+        let uptimes: Vec<u32> = lines[..lines.len() - 2]
+            .iter()
+            .map(|line| Record::parse(line).unwrap().uptime)
+            .collect();
+        assert!(uptimes.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", uptimes);
+        assert_eq!(uptimes[uptimes.len() - 1], uptime - 1);
+        assert!(uptimes.contains(&(RECORDS_PER_PAGE as u32)));
+    }
+}
@@ -0,0 +1,195 @@
+//! Tip temperature control
+//!
+//! A simple on/off controller with hysteresis running after every ADC
+//! sample. Whether and how far the heater may heat is decided by
+//! `allowed_heater_output` alone, the controller never heats beyond it
+//! whatever setpoint is requested.
+
+use state::State;
+
+/// Setpoints in °C
+pub const SOLDERING: i16 = 320;
+pub const BOOST: i16 = 400;
+pub const SLEEP: i16 = 150;
+
+/// Degrees below the setpoint until the heater switches on again
+const HYSTERESIS: i16 = 2;
+/// Readings above this are treated as a broken thermocouple or a runaway
+pub const TIP_MAX: i16 = 450;
+/// Weight of the newest sample in the duty cycle average, 1/16 gives a
+/// time constant of about 300 ms at 50 samples per second
+const DUTY_SHIFT: u32 = 4;
+
+/// Highest tip temperature in °C the heater may drive the tip to in
+/// `state`, `None` if it has to stay off
+///
+/// The heater is off in every state which doesn't heat on purpose, sleep is
+/// limited to the sleep temperature and nothing may exceed `TIP_MAX`.
+pub fn allowed_heater_output(state: State) -> Option<i16> {
+    match state {
+        State::Soldering | State::TemperatureControl | State::Boost => Some(TIP_MAX),
+        State::Sleep => Some(SLEEP),
+        State::Idle | State::Config(_) | State::Cooling | State::Thermometer => None,
+    }
+}
+
+/// Tip setpoint in °C for `state`, `None` if the heater has to be off
+pub fn setpoint(state: State) -> Option<i16> {
+    let requested = match state {
+        State::Soldering | State::TemperatureControl => SOLDERING,
+        State::Boost => BOOST,
+        State::Sleep => SLEEP,
+        State::Idle | State::Config(_) | State::Cooling | State::Thermometer => return None,
+    };
+
+    allowed_heater_output(state).map(|limit| requested.min(limit))
+}
+
+pub struct Control {
+    heating: bool,
+    /// Average duty cycle in percent scaled by 256
+    duty: u32,
+}
+
+impl Control {
+    #[cfg_attr(clippy, allow(clippy::new_without_default))]
+    pub const fn new() -> Self {
+        Control {
+            heating: false,
+            duty: 0,
+        }
+    }
+
+    /// Heater duty cycle in percent averaged over the last samples
+    pub fn duty(&self) -> u8 {
+        ((self.duty + 128) >> 8) as u8
+    }
+
+    /// Returns whether the heater should be on for the next sample period
+    pub fn update(&mut self, state: State, tip: i16) -> bool {
+        self.heating = match (setpoint(state), allowed_heater_output(state)) {
+            (Some(_), Some(limit)) if tip >= limit => false,
+            (Some(target), Some(_)) if tip >= target => false,
+            (Some(target), Some(_)) if tip < target - HYSTERESIS => true,
+            // in between keep going
+            (Some(_), Some(_)) => self.heating,
+            _ => false,
+        };
+
+        let on = if self.heating { 100 << 8 } else { 0 };
+        self.duty = self.duty - (self.duty >> DUTY_SHIFT) + (on >> DUTY_SHIFT);
+        self.heating
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+    use state::{ConfigPage, STATES};
+
+    #[test]
+    fn heater_off_unless_heating_on_purpose() {
+        for &state in &[
+            State::Idle,
+            State::Config(ConfigPage::Calibrate),
+            State::Cooling,
+            State::Thermometer,
+        ] {
+            assert_eq!(allowed_heater_output(state), None);
+            assert_eq!(setpoint(state), None);
+            assert!(!Control::new().update(state, 20));
+        }
+    }
+
+    #[test]
+    fn setpoints_within_the_allowed_output() {
+        assert_eq!(setpoint(State::Soldering), Some(SOLDERING));
+        assert_eq!(setpoint(State::TemperatureControl), Some(SOLDERING));
+        assert_eq!(setpoint(State::Boost), Some(BOOST));
+        assert_eq!(setpoint(State::Sleep), Some(SLEEP));
+        assert_eq!(allowed_heater_output(State::Sleep), Some(SLEEP));
+        assert_eq!(allowed_heater_output(State::Boost), Some(TIP_MAX));
+    }
+
+    #[test]
+    fn hysteresis() {
+        let mut control = Control::new();
+        assert!(control.update(State::Soldering, 20));
+        // keeps heating up to the setpoint
+        assert!(control.update(State::Soldering, SOLDERING - 1));
+        assert!(!control.update(State::Soldering, SOLDERING));
+        // and stays off until it dropped below the hysteresis
+        assert!(!control.update(State::Soldering, SOLDERING - 1));
+        assert!(!control.update(State::Soldering, SOLDERING - HYSTERESIS));
+        assert!(control.update(State::Soldering, SOLDERING - HYSTERESIS - 1));
+    }
+
+    #[test]
+    fn never_heats_at_the_limit() {
+        let mut control = Control::new();
+        assert!(control.update(State::Boost, 20));
+        assert!(!control.update(State::Boost, TIP_MAX));
+        assert!(!control.update(State::Sleep, SLEEP));
+        assert!(control.update(State::Sleep, 20));
+        assert!(!control.update(State::Idle, 20));
+    }
+
+    #[test]
+    fn duty_averages_the_heater_output() {
+        let mut control = Control::new();
+        assert_eq!(control.duty(), 0);
+
+        control.update(State::Soldering, 20);
+        let first = control.duty();
+        assert!(first > 0 && first < 100);
+        for _ in 0..200 {
+            control.update(State::Soldering, 20);
+        }
+        assert_eq!(control.duty(), 100);
+
+        for _ in 0..200 {
+            control.update(State::Idle, 20);
+        }
+        assert_eq!(control.duty(), 0);
+    }
+
+    /// Tip readings from far below ambient to beyond the runaway limit,
+    /// close to the limits more often than not
+    fn any_tip() -> impl Strategy<Value = i16> {
+        const LIMITS: [i16; 4] = [SLEEP, SOLDERING, BOOST, TIP_MAX];
+        prop_oneof![
+            1 => -50i16..550,
+            2 => (prop::sample::select(&LIMITS[..]), -5i16..6).prop_map(|(limit, d)| limit + d),
+        ]
+    }
+
+    proptest! {
+        #[test]
+        fn never_heats_beyond_the_allowed_output(
+            samples in prop::collection::vec((prop::sample::select(&STATES[..]), any_tip()), 1..200)
+        ) {
+            let mut control = Control::new();
+            for &(state, tip) in &samples {
+                let allowed = allowed_heater_output(state);
+                if let Some(setpoint) = setpoint(state) {
+                    prop_assert!(allowed.is_some_and(|limit| setpoint <= limit));
+                }
+
+                let heating = control.update(state, tip);
+                match allowed {
+                    None => prop_assert!(!heating, "heating in {:?}", state),
+                    Some(limit) => {
+                        prop_assert!(!heating || tip < limit,
+                                     "heating at {} °C in {:?}, limit {} °C",
+                                     tip,
+                                     state,
+                                     limit)
+                    }
+                }
+                prop_assert!(control.duty() <= 100);
+            }
+        }
+    }
+}
+
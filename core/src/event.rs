@@ -0,0 +1,134 @@
+//! Events passed from the interrupt handlers to the state machine
+//!
+//! Interrupt handlers only push events into a `Queue`, a single dispatcher
+//! pops them and feeds them to `StateMachine::update_state`. This keeps the
+//! state transitions in one place and in a well defined order, and a
+//! recorded sequence of events replays to the same states.
+
+use accel::{self, Accel, Gesture};
+use core::cell::UnsafeCell;
+use core::sync::atomic::{AtomicUsize, Ordering};
+use input::KeyEvent;
+use motion::MotionEvent;
+use time::TimerId;
+
+/// Capacity of a queue is one less than this
+const QUEUE_SIZE: usize = 32;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Event {
+    /// A debounced and classified key event
+    Key(KeyEvent),
+    /// A gesture detected by the accelerometer
+    Gesture(Gesture),
+    /// The software motion detector changed state
+    Motion(MotionEvent),
+    /// The latest accelerometer sample in milli-g
+    Accel(Accel),
+    /// A software timer expired
+    Timer(TimerId),
+    /// The accelerometer failed and motion detection is unavailable
+    AccelError(accel::Error),
+}
+
+/// Lock-free single producer single consumer ring buffer
+///
+/// Safe as long as all `push`es happen in one context and all `pop`s in one
+/// (possibly other) context. Interrupt handlers running at the same
+/// priority can't preempt each other and count as one context.
+pub struct Queue {
+    buffer: UnsafeCell<[Event; QUEUE_SIZE]>,
+    /// Next slot to pop, only written by the consumer
+    head: AtomicUsize,
+    /// Next slot to push, only written by the producer
+    tail: AtomicUsize,
+}
+
+unsafe impl Sync for Queue {}
+
+impl Queue {
+    #[cfg_attr(clippy, allow(clippy::new_without_default))]
+    pub const fn new() -> Self {
+        Queue {
+            buffer: UnsafeCell::new([Event::Timer(TimerId::Second); QUEUE_SIZE]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+        }
+    }
+
+    /// Appends `event`, hands it back if the queue is full
+    pub fn push(&self, event: Event) -> Result<(), Event> {
+        let tail = self.tail.load(Ordering::Relaxed);
+        let next = (tail + 1) % QUEUE_SIZE;
+        if next == self.head.load(Ordering::Acquire) {
+            return Err(event);
+        }
+
+        unsafe {
+            (*self.buffer.get())[tail] = event;
+        }
+        self.tail.store(next, Ordering::Release);
+        Ok(())
+    }
+
+    /// Removes the oldest event
+    pub fn pop(&self) -> Option<Event> {
+        let head = self.head.load(Ordering::Relaxed);
+        if head == self.tail.load(Ordering::Acquire) {
+            return None;
+        }
+
+        let event = unsafe { (*self.buffer.get())[head] };
+        self.head.store((head + 1) % QUEUE_SIZE, Ordering::Release);
+        Some(event)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.head.load(Ordering::Acquire) == self.tail.load(Ordering::Acquire)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timer(event: Option<Event>) -> Option<TimerId> {
+        match event {
+            Some(Event::Timer(id)) => Some(id),
+            Some(other) => panic!("unexpected {:?}", other),
+            None => None,
+        }
+    }
+
+    #[test]
+    fn holds_one_less_than_its_size() {
+        let queue = Queue::new();
+        assert!(queue.is_empty());
+        for _ in 0..QUEUE_SIZE - 1 {
+            assert!(queue.push(Event::Timer(TimerId::Refresh)).is_ok());
+        }
+        match queue.push(Event::Timer(TimerId::Sample)) {
+            Err(Event::Timer(TimerId::Sample)) => {}
+            other => panic!("expected the event back, got {:?}", other),
+        }
+
+        assert_eq!(timer(queue.pop()), Some(TimerId::Refresh));
+        assert!(queue.push(Event::Timer(TimerId::Sample)).is_ok());
+    }
+
+    #[test]
+    fn pops_in_order_across_the_wrap() {
+        let queue = Queue::new();
+        let ids = [TimerId::Second, TimerId::Refresh, TimerId::MenuTimeout, TimerId::Sample];
+        for round in 0..QUEUE_SIZE {
+            for &id in &ids {
+                queue.push(Event::Timer(id)).unwrap();
+            }
+            for &id in &ids {
+                assert_eq!(timer(queue.pop()), Some(id), "round {}", round);
+            }
+            assert!(queue.is_empty());
+            assert_eq!(timer(queue.pop()), None);
+        }
+    }
+}
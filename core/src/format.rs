@@ -0,0 +1,101 @@
+//! Number formatting into fixed width fields without allocation
+
+use core::str;
+
+/// Writes `number` right aligned into `buffer`, padded with spaces
+///
+/// If the number doesn't fit the whole field is filled with `*`.
+pub fn decimal(number: i32, buffer: &mut [u8]) -> &str {
+    let negative = number < 0;
+    // negating in u32 also works for i32::min_value()
+    let mut value = if negative {
+        (number as u32).wrapping_neg()
+    } else {
+        number as u32
+    };
+    let mut end = buffer.len();
+
+    loop {
+        if end == 0 {
+            return overflow(buffer);
+        }
+        end -= 1;
+        buffer[end] = b'0' + (value % 10) as u8;
+        value /= 10;
+        if value == 0 {
+            break;
+        }
+    }
+
+    if negative {
+        if end == 0 {
+            return overflow(buffer);
+        }
+        end -= 1;
+        buffer[end] = b'-';
+    }
+
+    for byte in buffer[..end].iter_mut() {
+        *byte = b' ';
+    }
+
+    as_str(buffer)
+}
+
+/// Writes `number` as upper case hex digits into `buffer`, padded with
+/// zeros, only the lowest digits are kept if it doesn't fit
+pub fn hex(number: u32, buffer: &mut [u8]) -> &str {
+    let mut value = number;
+    for byte in buffer.iter_mut().rev() {
+        let digit = (value & 0xf) as u8;
+        *byte = if digit < 10 { b'0' + digit } else { b'A' + digit - 10 };
+        value >>= 4;
+    }
+
+    as_str(buffer)
+}
+
+fn overflow(buffer: &mut [u8]) -> &str {
+    for byte in buffer.iter_mut() {
+        *byte = b'*';
+    }
+
+    as_str(buffer)
+}
+
+/// The buffer only contains ASCII
+fn as_str(buffer: &[u8]) -> &str {
+    str::from_utf8(buffer).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decimal_right_aligned() {
+        assert_eq!(decimal(42, &mut [0; 6]), "    42");
+        assert_eq!(decimal(0, &mut [0; 3]), "  0");
+        assert_eq!(decimal(-7, &mut [0; 3]), " -7");
+        assert_eq!(decimal(-99, &mut [0; 3]), "-99");
+        assert_eq!(decimal(12345, &mut [0; 5]), "12345");
+    }
+
+    #[test]
+    fn decimal_overflow() {
+        assert_eq!(decimal(123456, &mut [0; 5]), "*****");
+        assert_eq!(decimal(-100, &mut [0; 3]), "***");
+        assert_eq!(decimal(i32::MIN, &mut [0; 11]), "-2147483648");
+        assert_eq!(decimal(i32::MIN, &mut [0; 10]), "**********");
+        assert_eq!(decimal(5, &mut []), "");
+    }
+
+    #[test]
+    fn hex_zero_padded() {
+        assert_eq!(hex(0x3A, &mut [0; 2]), "3A");
+        assert_eq!(hex(0xF, &mut [0; 4]), "000F");
+        assert_eq!(hex(0xDEAD_BEEF, &mut [0; 8]), "DEADBEEF");
+        // keeps the lowest digits
+        assert_eq!(hex(0x1234, &mut [0; 2]), "34");
+    }
+}
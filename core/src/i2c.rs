@@ -0,0 +1,17 @@
+//! Bus the display and accelerometer drivers talk over
+//!
+//! The firmware implements it for the I2C1 peripheral, the tests with the
+//! recording double in `mock`.
+
+/// Blocking register based access to devices on an I2C bus
+pub trait I2cBus {
+    /// Reads `bytes.len()` bytes starting at `register` of the `slave`
+    fn read(&self, slave: u8, register: u8, bytes: &mut [u8]);
+    /// Writes `value` to `register` of the `slave`
+    fn write(&self, slave: u8, register: u8, value: u8);
+    /// Checks if `slave` acknowledges its address
+    fn probe(&self, slave: u8) -> bool;
+    /// Busy waits about `cycles` CPU cycles, for devices which don't answer
+    /// while they reset
+    fn delay(&self, cycles: u32);
+}
@@ -0,0 +1,417 @@
+//! Button debouncing and classification
+//!
+//! Raw edges are reported with `Input::edge`, `Input::poll` is called
+//! periodically (every millisecond) to debounce them and to generate the
+//! time based events. All timestamps are in milliseconds and may wrap.
+//!
+//! Every `Press` of a key is followed by exactly one `Release`, and a
+//! `Click` or a `LongPress` with its repeats in between. Pressing the second
+//! button while the first is held releases the single key without a click
+//! and presses `AB`, which lasts until both buttons are up:
+//!
+//! ```text
+//! A down, B down, A up, B up: Press(A) Release(A) Press(AB) Release(AB) Click(AB)
+//! ```
+
+use time::reached;
+
+/// Time a level has to be stable before it's accepted
+const DEBOUNCE_MS: u32 = 20;
+/// Holding a key longer than this is a long press instead of a click
+const LONG_PRESS_MS: u32 = 800;
+/// Delay between the long press and the first repeat
+const REPEAT_DELAY_MS: u32 = 400;
+/// Interval between repeats while holding
+const REPEAT_INTERVAL_MS: u32 = 100;
+/// Events kept until they are consumed
+const QUEUE_SIZE: usize = 16;
+
+/// Physical buttons
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Button {
+    A = 0,
+    B = 1,
+}
+
+/// Keys as seen by the user, `AB` is the chord of both buttons
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Key {
+    A,
+    B,
+    AB,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyAction {
+    /// The key went down, a chord is pressed once the second button joins
+    Press,
+    /// All buttons of the key are up again, or it became part of the chord
+    Release,
+    /// Released before becoming a long press
+    Click,
+    /// Held for `LONG_PRESS_MS`
+    LongPress,
+    /// Generated every `REPEAT_INTERVAL_MS` after a long press while held
+    Repeat,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KeyEvent {
+    pub key: Key,
+    pub action: KeyAction,
+}
+
+/// Key corresponding to the debounced levels of A and B
+fn held_key(a: bool, b: bool) -> Option<Key> {
+    match (a, b) {
+        (true, true) => Some(Key::AB),
+        (true, false) => Some(Key::A),
+        (false, true) => Some(Key::B),
+        (false, false) => None,
+    }
+}
+
+pub struct Input {
+    /// Last reported level of each button, `true` if pressed
+    raw: [bool; 2],
+    /// Time of the last edge of each button
+    changed: [u32; 2],
+    /// Debounced level of each button
+    stable: [bool; 2],
+    /// Key currently held down
+    held: Option<Key>,
+    pressed_at: u32,
+    long_press: bool,
+    next_repeat: u32,
+    queue: [KeyEvent; QUEUE_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl Input {
+    #[cfg_attr(clippy, allow(clippy::new_without_default))]
+    pub const fn new() -> Self {
+        Input {
+            raw: [false; 2],
+            changed: [0; 2],
+            stable: [false; 2],
+            held: None,
+            pressed_at: 0,
+            long_press: false,
+            next_repeat: 0,
+            queue: [KeyEvent { key: Key::A, action: KeyAction::Press }; QUEUE_SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    /// Reports a raw, possibly bouncing, level change of `button`
+    pub fn edge(&mut self, button: Button, pressed: bool, now: u32) {
+        let index = button as usize;
+        if self.raw[index] != pressed {
+            self.raw[index] = pressed;
+            self.changed[index] = now;
+        }
+    }
+
+    /// Debounces the buttons and generates events due at `now`
+    pub fn poll(&mut self, now: u32) {
+        let mut changed = false;
+        for index in 0..2 {
+            if self.raw[index] != self.stable[index]
+                && reached(now, self.changed[index].wrapping_add(DEBOUNCE_MS))
+            {
+                self.stable[index] = self.raw[index];
+                changed = true;
+            }
+        }
+
+        if changed {
+            self.update_held(now);
+        }
+
+        if let Some(key) = self.held {
+            if !self.long_press {
+                if reached(now, self.pressed_at.wrapping_add(LONG_PRESS_MS)) {
+                    self.long_press = true;
+                    self.next_repeat = now.wrapping_add(REPEAT_DELAY_MS);
+                    self.push(key, KeyAction::LongPress);
+                }
+            } else if reached(now, self.next_repeat) {
+                self.next_repeat = self.next_repeat.wrapping_add(REPEAT_INTERVAL_MS);
+                self.push(key, KeyAction::Repeat);
+            }
+        }
+    }
+
+    /// Returns the oldest pending event
+    pub fn pop(&mut self) -> Option<KeyEvent> {
+        if self.len == 0 {
+            return None;
+        }
+
+        let event = self.queue[self.head];
+        self.head = (self.head + 1) % QUEUE_SIZE;
+        self.len -= 1;
+        Some(event)
+    }
+
+    fn update_held(&mut self, now: u32) {
+        let (a, b) = (self.stable[Button::A as usize], self.stable[Button::B as usize]);
+
+        match self.held {
+            None => {
+                if let Some(key) = held_key(a, b) {
+                    self.press(key, now);
+                }
+            }
+            Some(Key::AB) => {
+                // a chord lasts until both buttons are released
+                if !a && !b {
+                    self.release(Key::AB);
+                }
+            }
+            Some(key) => {
+                match held_key(a, b) {
+                    Some(Key::AB) => {
+                        // the chord takes over, the single key doesn't click
+                        self.push(key, KeyAction::Release);
+                        self.press(Key::AB, now);
+                    }
+                    Some(other) if other != key => {
+                        // released one and pressed the other button at once
+                        self.release(key);
+                        self.press(other, now);
+                    }
+                    Some(_) => {}
+                    None => self.release(key),
+                }
+            }
+        }
+    }
+
+    fn press(&mut self, key: Key, now: u32) {
+        self.held = Some(key);
+        self.pressed_at = now;
+        self.long_press = false;
+        self.push(key, KeyAction::Press);
+    }
+
+    fn release(&mut self, key: Key) {
+        self.held = None;
+        self.push(key, KeyAction::Release);
+        if !self.long_press {
+            self.push(key, KeyAction::Click);
+        }
+    }
+
+    /// Queues an event, drops it if the queue is full
+    fn push(&mut self, key: Key, action: KeyAction) {
+        if self.len == QUEUE_SIZE {
+            return;
+        }
+
+        let tail = (self.head + self.len) % QUEUE_SIZE;
+        self.queue[tail] = KeyEvent {
+            key,
+            action,
+        };
+        self.len += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use self::std::vec::Vec;
+    use super::*;
+
+    use self::Button::{A, B};
+
+    fn event(key: Key, action: KeyAction) -> KeyEvent {
+        KeyEvent {
+            key,
+            action,
+        }
+    }
+
+    fn drain(input: &mut Input) -> Vec<KeyEvent> {
+        let mut events = Vec::new();
+        while let Some(event) = input.pop() {
+            events.push(event);
+        }
+        events
+    }
+
+    /// Reports the `edges` at their time and polls every millisecond from
+    /// `from` to `to` inclusive, returns the events with the time they were
+    /// generated at
+    fn run(edges: &[(u32, Button, bool)], from: u32, to: u32) -> Vec<(u32, KeyEvent)> {
+        let mut input = Input::new();
+        let mut events = Vec::new();
+        let mut now = from;
+        loop {
+            for &(time, button, pressed) in edges {
+                if time == now {
+                    input.edge(button, pressed, now);
+                }
+            }
+            input.poll(now);
+            events.extend(drain(&mut input).into_iter().map(|event| (now, event)));
+            if now == to {
+                return events;
+            }
+            now = now.wrapping_add(1);
+        }
+    }
+
+    #[test]
+    fn bouncing_edges_are_debounced() {
+        let edges = [(0, A, true), (3, A, false), (8, A, true)];
+
+        // stable from the last edge on
+        assert_eq!(run(&edges, 0, 100), [(8 + DEBOUNCE_MS, event(Key::A, KeyAction::Press))]);
+    }
+
+    #[test]
+    fn short_glitches_are_ignored() {
+        let edges = [(100, B, true), (100 + DEBOUNCE_MS - 1, B, false)];
+
+        assert_eq!(run(&edges, 0, 200), Vec::new());
+    }
+
+    #[test]
+    fn short_press_clicks() {
+        let release = DEBOUNCE_MS + LONG_PRESS_MS - 1;
+        let edges = [(0, A, true), (release - DEBOUNCE_MS, A, false)];
+
+        assert_eq!(run(&edges, 0, 2000),
+                   [(DEBOUNCE_MS, event(Key::A, KeyAction::Press)),
+                    (release, event(Key::A, KeyAction::Release)),
+                    (release, event(Key::A, KeyAction::Click))]);
+    }
+
+    #[test]
+    fn long_press_repeats_until_released() {
+        let long = DEBOUNCE_MS + LONG_PRESS_MS;
+        let first = long + REPEAT_DELAY_MS;
+        let release = first + 2 * REPEAT_INTERVAL_MS + 50;
+        let edges = [(0, B, true), (release - DEBOUNCE_MS, B, false)];
+
+        assert_eq!(run(&edges, 0, 3000),
+                   [(DEBOUNCE_MS, event(Key::B, KeyAction::Press)),
+                    (long, event(Key::B, KeyAction::LongPress)),
+                    (first, event(Key::B, KeyAction::Repeat)),
+                    (first + REPEAT_INTERVAL_MS, event(Key::B, KeyAction::Repeat)),
+                    (first + 2 * REPEAT_INTERVAL_MS, event(Key::B, KeyAction::Repeat)),
+                    (release, event(Key::B, KeyAction::Release))]);
+    }
+
+    #[test]
+    fn repeats_keep_their_interval_when_polled_late() {
+        let mut input = Input::new();
+        input.edge(A, true, 0);
+        input.poll(DEBOUNCE_MS);
+        input.poll(DEBOUNCE_MS + LONG_PRESS_MS);
+        assert_eq!(drain(&mut input),
+                   [event(Key::A, KeyAction::Press), event(Key::A, KeyAction::LongPress)]);
+
+        // a late poll catches up one repeat at a time
+        let first = DEBOUNCE_MS + LONG_PRESS_MS + REPEAT_DELAY_MS;
+        input.poll(first + 150);
+        input.poll(first + 151);
+        input.poll(first + 199);
+        assert_eq!(drain(&mut input),
+                   [event(Key::A, KeyAction::Repeat), event(Key::A, KeyAction::Repeat)]);
+        input.poll(first + 200);
+        assert_eq!(drain(&mut input), [event(Key::A, KeyAction::Repeat)]);
+    }
+
+    #[test]
+    fn second_button_forms_the_chord() {
+        // the chord lasts until the last button is up
+        let edges = [(0, A, true), (100, B, true), (300, A, false), (400, B, false)];
+
+        assert_eq!(run(&edges, 0, 1000),
+                   [(20, event(Key::A, KeyAction::Press)),
+                    (120, event(Key::A, KeyAction::Release)),
+                    (120, event(Key::AB, KeyAction::Press)),
+                    (420, event(Key::AB, KeyAction::Release)),
+                    (420, event(Key::AB, KeyAction::Click))]);
+    }
+
+    #[test]
+    fn chord_long_press_counts_from_the_chord() {
+        let edges = [(0, B, true), (500, A, true), (1600, B, false), (1700, A, false)];
+
+        let chord = 500 + DEBOUNCE_MS;
+        assert_eq!(run(&edges, 0, 1500),
+                   [(20, event(Key::B, KeyAction::Press)),
+                    (chord, event(Key::B, KeyAction::Release)),
+                    (chord, event(Key::AB, KeyAction::Press)),
+                    (chord + LONG_PRESS_MS, event(Key::AB, KeyAction::LongPress))]);
+    }
+
+    #[test]
+    fn simultaneous_buttons_press_the_chord_only() {
+        // each button is debounced on its own, they have to settle together
+        let edges = [(0, A, true), (0, B, true), (200, A, false), (200, B, false)];
+
+        assert_eq!(run(&edges, 0, 1000),
+                   [(20, event(Key::AB, KeyAction::Press)),
+                    (220, event(Key::AB, KeyAction::Release)),
+                    (220, event(Key::AB, KeyAction::Click))]);
+    }
+
+    #[test]
+    fn swapping_buttons_releases_and_presses() {
+        let edges = [(0, A, true), (100, A, false), (100, B, true), (200, B, false)];
+
+        assert_eq!(run(&edges, 0, 1000),
+                   [(20, event(Key::A, KeyAction::Press)),
+                    (120, event(Key::A, KeyAction::Release)),
+                    (120, event(Key::A, KeyAction::Click)),
+                    (120, event(Key::B, KeyAction::Press)),
+                    (220, event(Key::B, KeyAction::Release)),
+                    (220, event(Key::B, KeyAction::Click))]);
+    }
+
+    #[test]
+    fn timestamps_wrap() {
+        let start = u32::MAX - 10;
+        let release = start.wrapping_add(DEBOUNCE_MS + LONG_PRESS_MS + 100);
+        let edges = [(start, A, true), (release.wrapping_sub(DEBOUNCE_MS), A, false)];
+
+        assert_eq!(run(&edges, start, start.wrapping_add(2000)),
+                   [(start.wrapping_add(DEBOUNCE_MS), event(Key::A, KeyAction::Press)),
+                    (start.wrapping_add(DEBOUNCE_MS + LONG_PRESS_MS),
+                     event(Key::A, KeyAction::LongPress)),
+                    (release, event(Key::A, KeyAction::Release))]);
+    }
+
+    #[test]
+    fn full_queue_drops_new_events() {
+        let mut input = Input::new();
+        // each click queues press, release and click
+        for click in 0..6 {
+            let now = click * 100;
+            input.edge(A, true, now);
+            input.poll(now + DEBOUNCE_MS);
+            input.edge(A, false, now + 50);
+            input.poll(now + 50 + DEBOUNCE_MS);
+        }
+
+        let events = drain(&mut input);
+        assert_eq!(events.len(), QUEUE_SIZE);
+        let cycle = [KeyAction::Press, KeyAction::Release, KeyAction::Click];
+        for (index, queued) in events.iter().enumerate() {
+            assert_eq!(*queued, event(Key::A, cycle[index % 3]));
+        }
+
+        // room again once consumed
+        input.edge(B, true, 600);
+        input.poll(600 + DEBOUNCE_MS);
+        assert_eq!(drain(&mut input), [event(Key::B, KeyAction::Press)]);
+    }
+}
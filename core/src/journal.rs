@@ -0,0 +1,749 @@
+//! Persistent journal of notable events
+//!
+//! Boots, watchdog resets, thermal runaway trips, accelerometer failures
+//! and low supply voltage are appended to a ring of `PAGES` flash pages so
+//! they can be read out after a field return, on the `Config(Events)` page
+//! or with the `events` console command.
+//!
+//! There is no real time clock, so records carry the number of the boot and
+//! the uptime in seconds instead of a date. Each page starts with a header,
+//! records of `RECORD_LEN` half-words follow:
+//!
+//! | Half-word | Header                  | Record                              |
+//! |-----------|-------------------------|-------------------------------------|
+//! | 0         | `MAGIC`                 | kind in the low, detail in the high |
+//! |           |                         | byte                                |
+//! | 1         | sequence number         | value                               |
+//! | 2         |                         | boot number                         |
+//! | 3, 4      |                         | uptime in s, low half-word first    |
+//! | 5         |                         | checksum                            |
+//!
+//! Records are appended to the page with the highest sequence number. When
+//! it's full the oldest page is erased and becomes the newest. The kind of a
+//! record and the header's `MAGIC` are programmed last, so a write cut short
+//! by a reset leaves an invalid record or an unused page behind and nothing
+//! is ever programmed twice.
+
+use accel;
+use core::fmt;
+use settings::checksum;
+
+/// Pages of the ring, one of them is erased when the ring wraps
+pub const PAGES: usize = 2;
+/// Half-words of a flash page
+pub const PAGE_LEN: usize = 512;
+/// Half-words of a record
+pub const RECORD_LEN: usize = 6;
+const HEADER_LEN: usize = 2;
+/// Records in a page
+pub const RECORDS_PER_PAGE: usize = (PAGE_LEN - HEADER_LEN) / RECORD_LEN;
+/// Supply in mV below which a low voltage event is recorded, the TS100 is
+/// specified for 12 to 24 V
+pub const LOW_VOLTAGE: u16 = 11_000;
+/// The supply has to recover by this much to record the next low voltage
+const LOW_VOLTAGE_HYSTERESIS: u16 = 500;
+/// Degrees below `TIP_MAX` the tip has to cool down to record the next
+/// runaway
+const RUNAWAY_HYSTERESIS: i16 = 20;
+
+const MAGIC: u16 = 0x10E5;
+const ERASED: u16 = 0xffff;
+
+const KIND_BOOT: u16 = 1;
+const KIND_WATCHDOG: u16 = 2;
+const KIND_RUNAWAY: u16 = 3;
+const KIND_ACCEL: u16 = 4;
+const KIND_LOW_VOLTAGE: u16 = 5;
+
+/// The flash pages reserved for the journal
+pub trait Flash {
+    /// Reads the half-word at `offset` of `page`
+    fn read(&self, page: usize, offset: usize) -> u16;
+    /// Sets all half-words of `page` to `0xffff`
+    fn erase(&mut self, page: usize);
+    /// Programs an erased half-word
+    fn program(&mut self, page: usize, offset: usize, value: u16);
+}
+
+/// Reason of the last reset
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResetCause {
+    PowerOn,
+    /// NRST pulled low, e.g. by the debugger
+    Pin,
+    Software,
+    IndependentWatchdog,
+    WindowWatchdog,
+    LowPower,
+}
+
+const RESET_CAUSES: [ResetCause; 6] = [
+    ResetCause::PowerOn,
+    ResetCause::Pin,
+    ResetCause::Software,
+    ResetCause::IndependentWatchdog,
+    ResetCause::WindowWatchdog,
+    ResetCause::LowPower,
+];
+
+impl ResetCause {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            ResetCause::PowerOn => "power-on",
+            ResetCause::Pin => "pin",
+            ResetCause::Software => "software",
+            ResetCause::IndependentWatchdog => "watchdog",
+            ResetCause::WindowWatchdog => "window-watchdog",
+            ResetCause::LowPower => "low-power",
+        }
+    }
+
+    fn index(&self) -> u8 {
+        RESET_CAUSES.iter().position(|cause| cause == self).unwrap_or(0) as u8
+    }
+}
+
+const ACCEL_ERRORS: [(&str, accel::Error); 5] = [
+    ("not-present", accel::Error::NotPresent),
+    ("unknown-device", accel::Error::UnknownDevice(0)),
+    ("no-data", accel::Error::NoData),
+    ("self-test-failed", accel::Error::SelfTestFailed),
+    ("invalid-config", accel::Error::InvalidConfig),
+];
+
+fn accel_error_index(error: accel::Error) -> usize {
+    match error {
+        accel::Error::NotPresent => 0,
+        accel::Error::UnknownDevice(_) => 1,
+        accel::Error::NoData => 2,
+        accel::Error::SelfTestFailed => 3,
+        accel::Error::InvalidConfig => 4,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    /// The firmware started
+    Boot(ResetCause),
+    /// The watchdog reset the iron, index of the task which missed its
+    /// deadline
+    Watchdog(u8),
+    /// The tip reading in °C exceeded `control::TIP_MAX` and the heater was
+    /// cut off, a runaway or a broken thermocouple
+    ThermalRunaway(i16),
+    /// The accelerometer on the I2C bus failed. The I2C driver blocks
+    /// instead of reporting errors, a hung bus ends in a watchdog reset.
+    AccelError(accel::Error),
+    /// The supply in mV dropped below `LOW_VOLTAGE`
+    LowVoltage(u16),
+}
+
+impl Event {
+    fn encode(&self) -> (u16, u16) {
+        match *self {
+            Event::Boot(cause) => (KIND_BOOT | u16::from(cause.index()) << 8, 0),
+            Event::Watchdog(task) => (KIND_WATCHDOG | u16::from(task) << 8, 0),
+            Event::ThermalRunaway(tip) => (KIND_RUNAWAY, tip as u16),
+            Event::AccelError(error) => {
+                let id = match error {
+                    accel::Error::UnknownDevice(id) => u16::from(id),
+                    _ => 0,
+                };
+                (KIND_ACCEL | (accel_error_index(error) as u16) << 8, id)
+            }
+            Event::LowVoltage(supply) => (KIND_LOW_VOLTAGE, supply),
+        }
+    }
+
+    fn decode(kind: u16, value: u16) -> Option<Event> {
+        let detail = (kind >> 8) as usize;
+        match kind & 0xff {
+            KIND_BOOT => RESET_CAUSES.get(detail).map(|&cause| Event::Boot(cause)),
+            KIND_WATCHDOG => Some(Event::Watchdog(detail as u8)),
+            KIND_RUNAWAY => Some(Event::ThermalRunaway(value as i16)),
+            KIND_ACCEL => ACCEL_ERRORS.get(detail).map(|&(_, error)| match error {
+                accel::Error::UnknownDevice(_) => {
+                    Event::AccelError(accel::Error::UnknownDevice(value as u8))
+                }
+                error => Event::AccelError(error),
+            }),
+            KIND_LOW_VOLTAGE => Some(Event::LowVoltage(value)),
+            _ => None,
+        }
+    }
+}
+
+/// `NAME KEY=VALUE`, e.g. `runaway tip=471`
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Event::Boot(cause) => write!(f, "boot cause={}", cause.name()),
+            Event::Watchdog(task) => write!(f, "watchdog task={}", task),
+            Event::ThermalRunaway(tip) => write!(f, "runaway tip={}", tip),
+            Event::AccelError(error) => {
+                write!(f, "accel error={}", ACCEL_ERRORS[accel_error_index(error)].0)?;
+                if let accel::Error::UnknownDevice(id) = error {
+                    write!(f, " id={}", id)?;
+                }
+                Ok(())
+            }
+            Event::LowVoltage(supply) => write!(f, "low-voltage supply={}", supply),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Record {
+    pub event: Event,
+    /// Number of the boot the event happened in, counting from 1
+    pub boot: u16,
+    /// Seconds since that boot
+    pub uptime: u32,
+}
+
+impl Record {
+    pub fn encode(&self) -> [u16; RECORD_LEN] {
+        let (kind, value) = self.event.encode();
+        let mut data = [kind, value, self.boot, self.uptime as u16, (self.uptime >> 16) as u16, 0];
+        data[RECORD_LEN - 1] = checksum(&data[..RECORD_LEN - 1]);
+        data
+    }
+
+    /// Decodes a stored record, `None` if it's erased, cut short or of an
+    /// unknown kind
+    pub fn decode(data: &[u16; RECORD_LEN]) -> Option<Record> {
+        if data[RECORD_LEN - 1] != checksum(&data[..RECORD_LEN - 1]) {
+            return None;
+        }
+        Event::decode(data[0], data[1]).map(|event| {
+            Record {
+                event,
+                boot: data[2],
+                uptime: u32::from(data[3]) | u32::from(data[4]) << 16,
+            }
+        })
+    }
+
+    /// Parses a line written by `Display`
+    pub fn parse(line: &str) -> Option<Record> {
+        let mut words = line.split_whitespace();
+        let boot = words.next()?.parse().ok()?;
+        let uptime = words.next()?.parse().ok()?;
+        let name = words.next()?;
+
+        let mut fields = [""; 2];
+        let mut count = 0;
+        for word in words {
+            let mut parts = word.splitn(2, '=');
+            let (key, value) = (parts.next()?, parts.next()?);
+            let expected = match (name, count) {
+                ("boot", 0) => "cause",
+                ("watchdog", 0) => "task",
+                ("runaway", 0) => "tip",
+                ("accel", 0) => "error",
+                ("accel", 1) => "id",
+                ("low-voltage", 0) => "supply",
+                _ => return None,
+            };
+            if key != expected {
+                return None;
+            }
+            fields[count] = value;
+            count += 1;
+        }
+
+        let event = match name {
+            "boot" => Event::Boot(*RESET_CAUSES.iter().find(|cause| cause.name() == fields[0])?),
+            "watchdog" => Event::Watchdog(fields[0].parse().ok()?),
+            "runaway" => Event::ThermalRunaway(fields[0].parse().ok()?),
+            "accel" => {
+                let &(_, error) = ACCEL_ERRORS.iter().find(|&&(name, _)| name == fields[0])?;
+                match (error, count) {
+                    (accel::Error::UnknownDevice(_), 2) => {
+                        Event::AccelError(accel::Error::UnknownDevice(fields[1].parse().ok()?))
+                    }
+                    (accel::Error::UnknownDevice(_), _) | (_, 2) => return None,
+                    (error, _) => Event::AccelError(error),
+                }
+            }
+            "low-voltage" => Event::LowVoltage(fields[0].parse().ok()?),
+            _ => return None,
+        };
+        if count == 0 {
+            return None;
+        }
+
+        Some(Record {
+            event,
+            boot,
+            uptime,
+        })
+    }
+}
+
+/// `BOOT UPTIME EVENT`, e.g. `12 3600 runaway tip=471`
+impl fmt::Display for Record {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.boot, self.uptime, self.event)
+    }
+}
+
+/// Position of a record, to continue reading behind it with `Journal::next`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Cursor {
+    /// Sequence number of the record's page
+    sequence: u16,
+    slot: usize,
+}
+
+/// Position in the ring
+#[derive(Clone, Copy)]
+pub struct Journal {
+    /// Page appended to
+    page: usize,
+    /// Slot of the next record in `page`
+    slot: usize,
+    /// Sequence number of `page`
+    sequence: u16,
+    /// Number of the current boot
+    boot: u16,
+}
+
+impl Journal {
+    /// A journal which hasn't been read from flash yet, the first record
+    /// starts a new ring
+    #[cfg_attr(clippy, allow(clippy::new_without_default))]
+    pub const fn new() -> Self {
+        Journal {
+            page: PAGES - 1,
+            slot: RECORDS_PER_PAGE,
+            sequence: 0xffff,
+            boot: 1,
+        }
+    }
+
+    /// Finds the end of the journal in `flash` and starts the next boot
+    pub fn open<F: Flash>(flash: &F) -> Self {
+        let mut journal = Journal::new();
+        let mut found = false;
+        for page in 0..PAGES {
+            if let Some(sequence) = header(flash, page) {
+                if !found || (sequence.wrapping_sub(journal.sequence) as i16) > 0 {
+                    journal.page = page;
+                    journal.sequence = sequence;
+                    found = true;
+                }
+            }
+        }
+
+        if found {
+            // behind the last slot written to, even if the write was cut short
+            journal.slot = (0..RECORDS_PER_PAGE)
+                .rev()
+                .find(|&slot| {
+                    (0..RECORD_LEN).any(|i| flash.read(journal.page, offset(slot) + i) != ERASED)
+                })
+                .map_or(0, |slot| slot + 1);
+        }
+        journal.boot = journal.get(flash, 0).map_or(1, |record| record.boot.wrapping_add(1));
+        journal
+    }
+
+    /// Number of the current boot
+    pub fn boot(&self) -> u16 {
+        self.boot
+    }
+
+    /// Appends `event` which happened `uptime` seconds after boot
+    pub fn append<F: Flash>(&mut self, flash: &mut F, event: Event, uptime: u32) {
+        if self.slot == RECORDS_PER_PAGE {
+            self.page = (self.page + 1) % PAGES;
+            self.sequence = self.sequence.wrapping_add(1);
+            self.slot = 0;
+            flash.erase(self.page);
+            flash.program(self.page, 1, self.sequence);
+            flash.program(self.page, 0, MAGIC);
+        }
+
+        let record = Record {
+            event,
+            boot: self.boot,
+            uptime,
+        };
+        let start = offset(self.slot);
+        let data = record.encode();
+        for (i, &half_word) in data.iter().enumerate().skip(1) {
+            flash.program(self.page, start + i, half_word);
+        }
+        // an erased kind is invalid, whatever else was written
+        flash.program(self.page, start, data[0]);
+        self.slot += 1;
+    }
+
+    /// Number of valid records
+    pub fn len<F: Flash>(&self, flash: &F) -> usize {
+        let mut len = 0;
+        self.for_each(flash, |_| {
+            len += 1;
+            true
+        });
+        len
+    }
+
+    pub fn is_empty<F: Flash>(&self, flash: &F) -> bool {
+        self.get(flash, 0).is_none()
+    }
+
+    /// The record `age` records before the newest one
+    pub fn get<F: Flash>(&self, flash: &F, age: usize) -> Option<Record> {
+        let mut found = None;
+        let mut skip = age;
+        self.for_each(flash, |record| {
+            if skip == 0 {
+                found = Some(record);
+                return false;
+            }
+            skip -= 1;
+            true
+        });
+        found
+    }
+
+    /// The valid record following `after` and its position, oldest first
+    ///
+    /// Starts at the oldest record if `after` is `None` or its page was
+    /// erased in the meantime, records appended since are returned as well.
+    pub fn next<F: Flash>(&self, flash: &F, after: Option<Cursor>) -> Option<(Record, Cursor)> {
+        let (mut sequence, mut slot) = match after {
+            Some(cursor) if self.in_use(flash, cursor.sequence) => (cursor.sequence, cursor.slot + 1),
+            _ => {
+                let pages = (0..PAGES)
+                    .take_while(|&back| self.in_use(flash, self.sequence.wrapping_sub(back as u16)))
+                    .count();
+                if pages == 0 {
+                    return None;
+                }
+                (self.sequence.wrapping_sub(pages as u16 - 1), 0)
+            }
+        };
+
+        loop {
+            let back = self.sequence.wrapping_sub(sequence) as usize;
+            let page = (self.page + PAGES - back) % PAGES;
+            let slots = if back == 0 { self.slot } else { RECORDS_PER_PAGE };
+            while slot < slots {
+                if let Some(record) = read(flash, page, slot) {
+                    return Some((record, Cursor { sequence, slot }));
+                }
+                slot += 1;
+            }
+            if back == 0 {
+                return None;
+            }
+            sequence = sequence.wrapping_add(1);
+            slot = 0;
+        }
+    }
+
+    /// `true` if the page with `sequence` is still part of the ring
+    fn in_use<F: Flash>(&self, flash: &F, sequence: u16) -> bool {
+        let back = self.sequence.wrapping_sub(sequence) as usize;
+        back < PAGES && header(flash, (self.page + PAGES - back) % PAGES) == Some(sequence)
+    }
+
+    /// Calls `f` with the valid records, newest first, until it returns
+    /// `false`
+    fn for_each<F: Flash, C: FnMut(Record) -> bool>(&self, flash: &F, mut f: C) {
+        for back in 0..PAGES {
+            let page = (self.page + PAGES - back) % PAGES;
+            let expected = self.sequence.wrapping_sub(back as u16);
+            if header(flash, page) != Some(expected) {
+                return;
+            }
+
+            let slots = if back == 0 { self.slot } else { RECORDS_PER_PAGE };
+            for slot in (0..slots).rev() {
+                if let Some(record) = read(flash, page, slot) {
+                    if !f(record) {
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Sequence number of `page`, `None` if it isn't in use
+fn header<F: Flash>(flash: &F, page: usize) -> Option<u16> {
+    if flash.read(page, 0) == MAGIC {
+        Some(flash.read(page, 1))
+    } else {
+        None
+    }
+}
+
+/// The record in `slot` of `page`, `None` if it isn't valid
+fn read<F: Flash>(flash: &F, page: usize, slot: usize) -> Option<Record> {
+    let mut data = [0; RECORD_LEN];
+    for (i, half_word) in data.iter_mut().enumerate() {
+        *half_word = flash.read(page, offset(slot) + i);
+    }
+    Record::decode(&data)
+}
+
+fn offset(slot: usize) -> usize {
+    HEADER_LEN + slot * RECORD_LEN
+}
+
+/// Watches the tip temperature and the supply for events to record
+pub struct Monitor {
+    runaway: bool,
+    low_voltage: bool,
+}
+
+impl Monitor {
+    #[cfg_attr(clippy, allow(clippy::new_without_default))]
+    pub const fn new() -> Self {
+        Monitor {
+            runaway: false,
+            low_voltage: false,
+        }
+    }
+
+    /// Checks the latest tip temperature in °C and supply in mV, returns an
+    /// event when a limit is crossed
+    pub fn update(&mut self, tip: i16, supply: u16) -> Option<Event> {
+        use control::TIP_MAX;
+
+        if self.runaway {
+            self.runaway = tip >= TIP_MAX - RUNAWAY_HYSTERESIS;
+        } else if tip >= TIP_MAX {
+            self.runaway = true;
+            return Some(Event::ThermalRunaway(tip));
+        }
+
+        if self.low_voltage {
+            self.low_voltage = supply < LOW_VOLTAGE + LOW_VOLTAGE_HYSTERESIS;
+        } else if supply < LOW_VOLTAGE {
+            self.low_voltage = true;
+            return Some(Event::LowVoltage(supply));
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use self::std::string::ToString;
+    use self::std::vec::Vec;
+    use super::*;
+    use control::TIP_MAX;
+    use mock::MockFlash;
+    use proptest::prelude::*;
+
+    /// Appends of a run, enough to wrap the ring twice
+    const APPENDS: usize = 400;
+
+    fn any_event() -> impl Strategy<Value = Event> {
+        prop_oneof![
+            prop::sample::select(&RESET_CAUSES[..]).prop_map(Event::Boot),
+            any::<u8>().prop_map(Event::Watchdog),
+            any::<i16>().prop_map(Event::ThermalRunaway),
+            (prop::sample::select(&ACCEL_ERRORS[..]), any::<u8>()).prop_map(|((_, error), id)| {
+                Event::AccelError(match error {
+                    accel::Error::UnknownDevice(_) => accel::Error::UnknownDevice(id),
+                    error => error,
+                })
+            }),
+            any::<u16>().prop_map(Event::LowVoltage),
+        ]
+    }
+
+    fn any_record() -> impl Strategy<Value = Record> {
+        (any_event(), any::<u16>(), any::<u32>()).prop_map(|(event, boot, uptime)| {
+            Record {
+                event,
+                boot,
+                uptime,
+            }
+        })
+    }
+
+    /// The records of `journal`, oldest first
+    fn records(journal: &Journal, flash: &MockFlash) -> Vec<Record> {
+        let mut records = Vec::new();
+        let mut cursor = None;
+        while let Some((record, next)) = journal.next(flash, cursor) {
+            records.push(record);
+            cursor = Some(next);
+        }
+        records
+    }
+
+    /// Records the ring holds after `appends` appends without power cuts
+    fn capacity(appends: usize) -> usize {
+        if appends <= PAGES * RECORDS_PER_PAGE {
+            appends
+        } else {
+            (appends - 1) % RECORDS_PER_PAGE + 1 + (PAGES - 1) * RECORDS_PER_PAGE
+        }
+    }
+
+    /// Compares the journal with the newest records of `model`, returns the
+    /// number of records kept
+    fn compare(journal: &Journal, flash: &MockFlash, model: &[Record])
+               -> Result<usize, TestCaseError> {
+        let kept = records(journal, flash);
+        prop_assert!(kept.len() <= model.len() && kept[..] == model[model.len() - kept.len()..],
+                     "journal of {} records isn't the end of the {} appended, newest {:?}",
+                     kept.len(),
+                     model.len(),
+                     kept.last());
+        prop_assert_eq!(journal.len(flash), kept.len());
+        prop_assert_eq!(journal.get(flash, 0), kept.last().copied());
+        prop_assert_eq!(flash.violations, 0, "half-words programmed twice");
+        Ok(kept.len())
+    }
+
+    /// Reopens the journal like after a reset and checks the boot number
+    fn reopen(flash: &MockFlash, model: &[Record]) -> Result<Journal, TestCaseError> {
+        let journal = Journal::open(flash);
+        let expected = model.last().map_or(1, |record| record.boot.wrapping_add(1));
+        prop_assert_eq!(journal.boot(), expected);
+        Ok(journal)
+    }
+
+    #[test]
+    fn malformed_lines_are_rejected() {
+        for line in ["", "1 2", "1 2 boot", "1 2 boot cause=sleep", "1 2 runaway tip=1 x=2",
+                     "1 2 accel error=unknown-device", "1 2 accel error=no-data id=3",
+                     "1 2 watchdog cause=1", "-1 2 runaway tip=1", "1 2 low-voltage supply=-1"] {
+            assert_eq!(Record::parse(line), None, "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn each_limit_is_recorded_once_per_crossing() {
+        let mut monitor = Monitor::new();
+        let supply = LOW_VOLTAGE + 8000;
+        let steps = [
+            (TIP_MAX - 1, supply, None),
+            (TIP_MAX, supply, Some(Event::ThermalRunaway(TIP_MAX))),
+            (TIP_MAX + 30, supply, None),
+            (TIP_MAX - 10, supply, None),
+            (TIP_MAX, supply, None),
+            (TIP_MAX - 50, supply, None),
+            (TIP_MAX + 1, supply, Some(Event::ThermalRunaway(TIP_MAX + 1))),
+            (300, LOW_VOLTAGE - 1, Some(Event::LowVoltage(LOW_VOLTAGE - 1))),
+            (300, LOW_VOLTAGE - 2000, None),
+            (300, LOW_VOLTAGE + 100, None),
+            (300, LOW_VOLTAGE - 1, None),
+            (300, LOW_VOLTAGE + 1000, None),
+            (300, LOW_VOLTAGE - 1, Some(Event::LowVoltage(LOW_VOLTAGE - 1))),
+        ];
+
+        for (index, &(tip, supply, expected)) in steps.iter().enumerate() {
+            assert_eq!(monitor.update(tip, supply), expected,
+                       "step {} at {} °C and {} mV", index, tip, supply);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn records_survive_encoding_and_parsing(
+            record in any_record(),
+            half_word in 0..RECORD_LEN,
+            bit in 0..16u32
+        ) {
+            let mut data = record.encode();
+            prop_assert_eq!(Record::decode(&data), Some(record));
+            prop_assert_eq!(Record::parse(&record.to_string()), Some(record));
+
+            data[half_word] ^= 1 << bit;
+            prop_assert_eq!(Record::decode(&data), None);
+        }
+
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        /// Appends with a reset now and then, the ring has to keep the
+        /// newest records without gaps and as many as it can
+        #[test]
+        fn the_ring_keeps_the_newest_records(
+            steps in prop::collection::vec((any_event(), 0..1000u32, prop::bool::weighted(0.1)),
+                                           1..APPENDS)
+        ) {
+            let mut flash = MockFlash::new();
+            let mut journal = reopen(&flash, &[])?;
+            let mut model = Vec::new();
+            let mut uptime = 0;
+
+            for &(event, seconds, reset) in &steps {
+                if reset {
+                    journal = reopen(&flash, &model)?;
+                    uptime = 0;
+                }
+
+                uptime += seconds;
+                model.push(Record {
+                    event,
+                    boot: journal.boot(),
+                    uptime,
+                });
+                journal.append(&mut flash, event, uptime);
+                prop_assert_eq!(compare(&journal, &flash, &model)?, capacity(model.len()));
+            }
+        }
+
+        /// Cuts the power during some appends, only the torn record may be
+        /// lost
+        #[test]
+        fn power_cuts_only_lose_the_torn_record(
+            steps in prop::collection::vec(
+                // a rotation takes 3 operations, the record another RECORD_LEN
+                (any_event(), 1..1000u32, prop::option::weighted(0.25, 0..RECORD_LEN + 4)),
+                1..APPENDS
+            )
+        ) {
+            let mut flash = MockFlash::new();
+            let mut journal = reopen(&flash, &[])?;
+            let mut model = Vec::new();
+            let mut uptime = 0;
+
+            for &(event, seconds, cut) in &steps {
+                uptime += seconds;
+                let record = Record {
+                    event,
+                    boot: journal.boot(),
+                    uptime,
+                };
+
+                if let Some(operations) = cut {
+                    flash.cut_power_after(operations);
+                    journal.append(&mut flash, event, uptime);
+                    let completed = flash.is_powered();
+                    flash.restore_power();
+
+                    // the record is either complete or lost, the uptime
+                    // tells it apart from older ones
+                    let newest = Journal::open(&flash).get(&flash, 0);
+                    if newest == Some(record) {
+                        model.push(record);
+                    } else {
+                        prop_assert!(!completed, "{:?} lost without a power cut", record);
+                    }
+                    journal = reopen(&flash, &model)?;
+                    uptime = 0;
+                } else {
+                    journal.append(&mut flash, event, uptime);
+                    model.push(record);
+                }
+                compare(&journal, &flash, &model)?;
+            }
+        }
+    }
+}
@@ -0,0 +1,36 @@
+//! Hardware independent parts of the ts100 firmware
+//!
+//! Everything in here builds for the host as well, the `firmware` crate only
+//! adds the board bring-up and the peripherals of the MCU. The display and
+//! accelerometer drivers only need an `i2c::I2cBus`.
+
+#![cfg_attr(feature = "const-fn", feature(const_fn))]
+#![no_std]
+
+#[cfg(test)]
+extern crate proptest;
+
+pub mod accel;
+pub mod accelerometer;
+pub mod console;
+pub mod control;
+pub mod event;
+pub mod font5x7;
+pub mod format;
+pub mod i2c;
+pub mod input;
+pub mod journal;
+pub mod lis2dh12;
+pub mod log;
+pub mod mma8652fc;
+#[cfg(test)]
+mod mock;
+pub mod motion;
+pub mod settings;
+pub mod ssd1306;
+pub mod state;
+pub mod temperature;
+pub mod time;
+pub mod trace;
+pub mod ui;
+pub mod usb;
@@ -0,0 +1,463 @@
+use accelerometer::{self, Accel, Accelerometer, Error, Gesture, Orientation, TapConfig};
+use i2c::I2cBus;
+
+/// SA0 is pulled high on the TS100
+pub const I2C_ADDRESS: u8 = 0x19;
+/// Content of the `WHO_AM_I` register
+pub const DEVICE_ID: u8 = 0x33;
+
+/// Set in the register address to read multiple registers in one go
+const AUTO_INCREMENT: u8 = 0x80;
+/// Resolution of `INT1_THS` and `CLICK_THS` in milli-g at ±2 g
+const INT1_THS_STEP: u16 = 16;
+/// Click timing step in µs, one sample at 100 Hz
+const CLICK_TIME_STEP: u32 = 10_000;
+/// Cycles the sensor is left alone while it reloads its trimming
+const BOOT_CYCLES: u32 = 10_000;
+
+/// LIS2DH12 Register Addresses
+#[allow(dead_code)]
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy)]
+pub enum Register {
+    /// Temperature data status register (R)
+    STATUS_REG_AUX = 0x07,
+    /// Temperature sensor data (R)
+    OUT_TEMP_L = 0x0C,
+    OUT_TEMP_H = 0x0D,
+    /// Device ID Register (R)
+    WHO_AM_I = 0x0F,
+
+    /// SDO/SA0 pull-up configuration (R/W)
+    CTRL_REG0 = 0x1E,
+    /// Temperature sensor enable (R/W)
+    TEMP_CFG_REG = 0x1F,
+    /// Data rate, low power mode and axis enable (R/W)
+    CTRL_REG1 = 0x20,
+    /// High pass filter configuration (R/W)
+    CTRL_REG2 = 0x21,
+    /// INT1 routing (R/W)
+    CTRL_REG3 = 0x22,
+    /// Block data update, full scale, high resolution and self-test (R/W)
+    CTRL_REG4 = 0x23,
+    /// Reboot, FIFO enable and interrupt latching (R/W)
+    CTRL_REG5 = 0x24,
+    /// INT2 routing and interrupt polarity (R/W)
+    CTRL_REG6 = 0x25,
+    /// Reference value for interrupt generation, reading resets the high
+    /// pass filter (R/W)
+    REFERENCE = 0x26,
+    /// Status Register (R)
+    STATUS_REG = 0x27,
+
+    /// X-axis sample, 12-bit left justified in high resolution mode (R)
+    OUT_X_L = 0x28,
+    OUT_X_H = 0x29,
+    /// Y-axis sample (R)
+    OUT_Y_L = 0x2A,
+    OUT_Y_H = 0x2B,
+    /// Z-axis sample (R)
+    OUT_Z_L = 0x2C,
+    OUT_Z_H = 0x2D,
+
+    /// FIFO Control Register (R/W)
+    FIFO_CTRL_REG = 0x2E,
+    /// FIFO Source Register (R)
+    FIFO_SRC_REG = 0x2F,
+
+    /// Interrupt 1 Configuration Register (R/W)
+    INT1_CFG = 0x30,
+    /// Interrupt 1 Source Register (R)
+    INT1_SRC = 0x31,
+    /// Interrupt 1 Threshold Register (R/W)
+    INT1_THS = 0x32,
+    /// Interrupt 1 Duration Register (R/W)
+    INT1_DURATION = 0x33,
+    /// Interrupt 2 Configuration Register (R/W)
+    INT2_CFG = 0x34,
+    /// Interrupt 2 Source Register (R)
+    INT2_SRC = 0x35,
+    /// Interrupt 2 Threshold Register (R/W)
+    INT2_THS = 0x36,
+    /// Interrupt 2 Duration Register (R/W)
+    INT2_DURATION = 0x37,
+
+    /// Click Configuration Register (R/W)
+    CLICK_CFG = 0x38,
+    /// Click Source Register (R)
+    CLICK_SRC = 0x39,
+    /// Click Threshold Register (R/W)
+    CLICK_THS = 0x3A,
+    /// Click Time Limit Register (R/W)
+    TIME_LIMIT = 0x3B,
+    /// Click Latency Register (R/W)
+    TIME_LATENCY = 0x3C,
+    /// Click Window Register (R/W)
+    TIME_WINDOW = 0x3D,
+
+    /// Sleep-to-wake activation threshold (R/W)
+    ACT_THS = 0x3E,
+    /// Sleep-to-wake duration (R/W)
+    ACT_DUR = 0x3F,
+}
+
+impl Register {
+    pub fn addr(&self) -> u8 {
+        *self as u8
+    }
+}
+
+// CTRL_REG1: 100 Hz, X, Y and Z enabled
+const ODR_100HZ_XYZ: u8 = 0x57;
+// CTRL_REG2: high pass filter for interrupt 1
+const HP_IA1: u8 = 1 << 0;
+// CTRL_REG3: data ready, interrupt 1 and click on INT1
+const I1_ZYXDA: u8 = 1 << 4;
+const I1_IA1: u8 = 1 << 6;
+const I1_CLICK: u8 = 1 << 7;
+// CTRL_REG4: block data update, high resolution, ±2 g
+const BDU_HR_2G: u8 = 0x88;
+// CTRL_REG5
+const BOOT: u8 = 1 << 7;
+// INT1_CFG: OR of X, Y and Z high events
+const INT_XYZ_HIGH: u8 = 0x2A;
+// CLICK_CFG: single and double click on X, Y and Z
+const CLICK_SINGLE_XYZ: u8 = 0x15;
+const CLICK_DOUBLE_XYZ: u8 = 0x2A;
+// CLICK_THS: latch CLICK_SRC until read
+const LIR_CLICK: u8 = 1 << 7;
+// CLICK_SRC
+const CLICK_DCLICK: u8 = 1 << 5;
+const CLICK_IA: u8 = 1 << 6;
+
+/// Combines the little endian, left justified registers of one axis into a
+/// sign extended 12-bit sample, which is milli-g at ±2 g
+pub fn sample(low: u8, high: u8) -> i16 {
+    (((u16::from(high) << 8) | u16::from(low)) as i16) >> 4
+}
+
+pub struct LIS2DH12<'a, B: 'a + I2cBus>(pub &'a B);
+
+impl<'a, B: I2cBus> LIS2DH12<'a, B> {
+    /// Reboots the sensor and configures 100 Hz high resolution sampling at
+    /// ±2 g
+    pub fn init(&self) -> Result<(), Error> {
+        if !self.0.probe(I2C_ADDRESS) {
+            return Err(Error::NotPresent);
+        }
+
+        let id = self.get_register(Register::WHO_AM_I);
+        if id != DEVICE_ID {
+            return Err(Error::UnknownDevice(id));
+        }
+
+        // reload trimming parameters
+        self.set_register(Register::CTRL_REG5, BOOT);
+        self.0.delay(BOOT_CYCLES);
+
+        self.set_register(Register::CTRL_REG4, BDU_HR_2G)
+            .set_register(Register::CTRL_REG3, I1_ZYXDA)
+            .set_register(Register::CTRL_REG1, ODR_100HZ_XYZ);
+
+        Ok(())
+    }
+
+    pub fn accel(&self) -> Accel {
+        let mut bytes = [0; 6];
+        self.0.read(I2C_ADDRESS, Register::OUT_X_L.addr() | AUTO_INCREMENT, &mut bytes);
+
+        Accel {
+            x: sample(bytes[0], bytes[1]),
+            y: sample(bytes[2], bytes[3]),
+            z: sample(bytes[4], bytes[5]),
+        }
+    }
+
+    pub fn get_register(&self, reg: Register) -> u8 {
+        let mut value = [0];
+        self.0.read(I2C_ADDRESS, reg.addr(), &mut value);
+        value[0]
+    }
+
+    pub fn set_register(&self, reg: Register, value: u8) -> &Self {
+        self.0.write(I2C_ADDRESS, reg.addr(), value);
+        self
+    }
+}
+
+impl<'a, B: I2cBus> Accelerometer for LIS2DH12<'a, B> {
+    fn sample(&self) -> Accel {
+        self.accel()
+    }
+
+    fn configure_motion_wake(&self, threshold: u16, count: u8) {
+        let ctrl_reg3 = self.get_register(Register::CTRL_REG3);
+        let ths = (threshold / INT1_THS_STEP).min(0x7F) as u8;
+
+        self.set_register(Register::CTRL_REG2, HP_IA1)
+            .set_register(Register::INT1_THS, ths)
+            .set_register(Register::INT1_DURATION, count & 0x7F)
+            .set_register(Register::INT1_CFG, INT_XYZ_HIGH)
+            .set_register(Register::CTRL_REG3, ctrl_reg3 | I1_IA1);
+        // reset the high pass filter to the current acceleration
+        self.get_register(Register::REFERENCE);
+    }
+
+    /// Interrupt generator 1 is taken by motion wake, so the orientation
+    /// is derived from gravity in software
+    fn orientation(&self) -> Orientation {
+        let accel = self.accel();
+        let (x, y, z) = (accel.x.abs(), accel.y.abs(), accel.z.abs());
+
+        if z > x && z > y {
+            Orientation::Flat
+        } else if y >= x {
+            if accel.y > 0 {
+                Orientation::PortraitUp
+            } else {
+                Orientation::PortraitDown
+            }
+        } else if accel.x > 0 {
+            Orientation::LandscapeRight
+        } else {
+            Orientation::LandscapeLeft
+        }
+    }
+
+    fn configure_taps(&self, config: &TapConfig) {
+        let ctrl_reg3 = self.get_register(Register::CTRL_REG3);
+
+        let mut click_cfg = 0;
+        if config.single {
+            click_cfg |= CLICK_SINGLE_XYZ;
+        }
+        if config.double {
+            click_cfg |= CLICK_DOUBLE_XYZ;
+        }
+
+        if click_cfg == 0 {
+            self.set_register(Register::CLICK_CFG, 0)
+                .set_register(Register::CTRL_REG3, ctrl_reg3 & !I1_CLICK);
+            return;
+        }
+
+        let ths = (config.threshold / INT1_THS_STEP).min(0x7F) as u8;
+        self.set_register(Register::CLICK_CFG, click_cfg)
+            .set_register(Register::CLICK_THS, ths | LIR_CLICK)
+            .set_register(
+                Register::TIME_LIMIT,
+                accelerometer::steps(config.time_limit, CLICK_TIME_STEP) & 0x7F,
+            )
+            .set_register(
+                Register::TIME_LATENCY,
+                accelerometer::steps(config.latency, CLICK_TIME_STEP),
+            )
+            .set_register(
+                Register::TIME_WINDOW,
+                accelerometer::steps(config.window, CLICK_TIME_STEP),
+            )
+            .set_register(Register::CTRL_REG3, ctrl_reg3 | I1_CLICK);
+    }
+
+    fn gesture(&self) -> Option<Gesture> {
+        let source = self.get_register(Register::CLICK_SRC);
+        if source & CLICK_IA == 0 {
+            None
+        } else if source & CLICK_DCLICK != 0 {
+            Some(Gesture::DoubleTap)
+        } else {
+            Some(Gesture::Tap)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::{MockI2c, Transaction};
+
+    /// The last value written to `register`
+    fn last_write(bus: &MockI2c, register: Register) -> Option<u8> {
+        let mut values = [0; 16];
+        match bus.writes_to(I2C_ADDRESS, register.addr(), &mut values) {
+            0 => None,
+            count => Some(values[count - 1]),
+        }
+    }
+
+    fn write(register: Register, value: u8) -> Option<Transaction> {
+        Some(Transaction::Write {
+            slave: I2C_ADDRESS,
+            register: register.addr(),
+            value,
+        })
+    }
+
+    #[test]
+    fn sample_sign_extends_12_bits() {
+        assert_eq!(sample(0xF0, 0x7F), 2047);
+        assert_eq!(sample(0x00, 0x80), -2048);
+        assert_eq!(sample(0xF0, 0xFF), -1);
+        assert_eq!(sample(0x10, 0x00), 1);
+        // the unused low nibble is ignored
+        assert_eq!(sample(0x3F, 0x12), 0x123);
+    }
+
+    #[test]
+    fn init_fails_without_sensor() {
+        let bus = MockI2c::new();
+        bus.set_present(I2C_ADDRESS, false);
+
+        assert_eq!(LIS2DH12(&bus).init(), Err(Error::NotPresent));
+        assert_eq!(bus.len(), 1);
+    }
+
+    #[test]
+    fn init_rejects_other_devices() {
+        let bus = MockI2c::new();
+        bus.script_read(&[0x4A]);
+
+        assert_eq!(LIS2DH12(&bus).init(), Err(Error::UnknownDevice(0x4A)));
+        assert_eq!(bus.len(), 2);
+    }
+
+    #[test]
+    fn init_boots_and_configures() {
+        let bus = MockI2c::new();
+        bus.script_read(&[DEVICE_ID]);
+
+        assert_eq!(LIS2DH12(&bus).init(), Ok(()));
+        assert_eq!(bus.transaction(2), write(Register::CTRL_REG5, BOOT));
+        assert_eq!(bus.transaction(3), Some(Transaction::Delay { cycles: BOOT_CYCLES }));
+        assert_eq!(bus.transaction(4), write(Register::CTRL_REG4, BDU_HR_2G));
+        assert_eq!(bus.transaction(5), write(Register::CTRL_REG3, I1_ZYXDA));
+        // sampling starts last
+        assert_eq!(bus.transaction(6), write(Register::CTRL_REG1, ODR_100HZ_XYZ));
+        assert_eq!(bus.len(), 7);
+    }
+
+    #[test]
+    fn accel_reads_all_axes_in_one_burst() {
+        let bus = MockI2c::new();
+        bus.script_read(&[0xF0, 0x7F, 0x00, 0x80, 0x10, 0x00]);
+
+        let accel = LIS2DH12(&bus).sample();
+        assert_eq!((accel.x, accel.y, accel.z), (2047, -2048, 1));
+        assert_eq!(bus.transaction(0),
+                   Some(Transaction::Read {
+                       slave: I2C_ADDRESS,
+                       register: Register::OUT_X_L.addr() | AUTO_INCREMENT,
+                       len: 6,
+                   }));
+        assert_eq!(bus.len(), 1);
+    }
+
+    #[test]
+    fn drain_reads_a_single_sample() {
+        let bus = MockI2c::new();
+        bus.script_read(&[0x10, 0x00, 0x20, 0x00, 0x00, 0x40]);
+
+        let mut samples = [Accel { x: 0, y: 0, z: 0 }; 4];
+        assert_eq!(LIS2DH12(&bus).drain(&mut samples), 1);
+        assert_eq!((samples[0].x, samples[0].y, samples[0].z), (1, 2, 1024));
+        assert_eq!(LIS2DH12(&bus).drain(&mut []), 0);
+        assert_eq!(bus.len(), 1);
+    }
+
+    #[test]
+    fn motion_wake_uses_interrupt_1() {
+        let bus = MockI2c::new();
+        bus.script_read(&[I1_ZYXDA]);
+
+        LIS2DH12(&bus).configure_motion_wake(126, 0x85);
+        assert_eq!(last_write(&bus, Register::INT1_THS), Some(7));
+        assert_eq!(last_write(&bus, Register::INT1_DURATION), Some(0x05));
+        assert_eq!(last_write(&bus, Register::INT1_CFG), Some(INT_XYZ_HIGH));
+        assert_eq!(last_write(&bus, Register::CTRL_REG2), Some(HP_IA1));
+        assert_eq!(last_write(&bus, Register::CTRL_REG3), Some(I1_ZYXDA | I1_IA1));
+        // reading REFERENCE settles the high pass filter
+        assert_eq!(bus.transaction(bus.len() - 1),
+                   Some(Transaction::Read {
+                       slave: I2C_ADDRESS,
+                       register: Register::REFERENCE.addr(),
+                       len: 1,
+                   }));
+    }
+
+    #[test]
+    fn configure_double_taps() {
+        let bus = MockI2c::new();
+        bus.script_read(&[I1_ZYXDA]);
+
+        LIS2DH12(&bus).configure_taps(&TapConfig::new());
+        assert_eq!(last_write(&bus, Register::CLICK_CFG), Some(CLICK_DOUBLE_XYZ));
+        assert_eq!(last_write(&bus, Register::CLICK_THS), Some(62 | LIR_CLICK));
+        // 50, 100 and 300 ms in 10 ms steps
+        assert_eq!(last_write(&bus, Register::TIME_LIMIT), Some(5));
+        assert_eq!(last_write(&bus, Register::TIME_LATENCY), Some(10));
+        assert_eq!(last_write(&bus, Register::TIME_WINDOW), Some(30));
+        assert_eq!(last_write(&bus, Register::CTRL_REG3), Some(I1_ZYXDA | I1_CLICK));
+    }
+
+    #[test]
+    fn configure_single_taps_saturates() {
+        let bus = MockI2c::new();
+        let config = TapConfig {
+            single: true,
+            double: false,
+            threshold: 4000,
+            time_limit: 5000,
+            latency: 5000,
+            window: 5000,
+        };
+
+        LIS2DH12(&bus).configure_taps(&config);
+        assert_eq!(last_write(&bus, Register::CLICK_CFG), Some(CLICK_SINGLE_XYZ));
+        assert_eq!(last_write(&bus, Register::CLICK_THS), Some(0x7F | LIR_CLICK));
+        assert_eq!(last_write(&bus, Register::TIME_LIMIT), Some(0x7F));
+        assert_eq!(last_write(&bus, Register::TIME_LATENCY), Some(0xFF));
+        assert_eq!(last_write(&bus, Register::TIME_WINDOW), Some(0xFF));
+    }
+
+    #[test]
+    fn disabling_taps_unroutes_the_interrupt() {
+        let bus = MockI2c::new();
+        bus.script_read(&[I1_ZYXDA | I1_CLICK]);
+        let mut config = TapConfig::new();
+        config.double = false;
+
+        LIS2DH12(&bus).configure_taps(&config);
+        assert_eq!(last_write(&bus, Register::CLICK_CFG), Some(0));
+        assert_eq!(last_write(&bus, Register::CTRL_REG3), Some(I1_ZYXDA));
+        assert_eq!(last_write(&bus, Register::CLICK_THS), None);
+    }
+
+    #[test]
+    fn gesture_decodes_the_click_source() {
+        let bus = MockI2c::new();
+        bus.script_read(&[0, CLICK_DCLICK, CLICK_IA, CLICK_IA | CLICK_DCLICK]);
+        let sensor = LIS2DH12(&bus);
+
+        assert_eq!(sensor.gesture(), None);
+        // a stale double click flag without interrupt isn't a gesture
+        assert_eq!(sensor.gesture(), None);
+        assert_eq!(sensor.gesture(), Some(Gesture::Tap));
+        assert_eq!(sensor.gesture(), Some(Gesture::DoubleTap));
+    }
+
+    #[test]
+    fn orientation_from_gravity() {
+        let cases: [([u8; 6], Orientation); 5] = [
+            ([0x00, 0x00, 0x00, 0x00, 0x00, 0x40], Orientation::Flat),
+            ([0x00, 0x00, 0x00, 0x40, 0x00, 0x10], Orientation::PortraitUp),
+            ([0x00, 0x00, 0x00, 0xC0, 0x00, 0x10], Orientation::PortraitDown),
+            ([0x00, 0x40, 0x00, 0x10, 0x00, 0x10], Orientation::LandscapeRight),
+            ([0x00, 0xC0, 0x00, 0x10, 0x00, 0x10], Orientation::LandscapeLeft),
+        ];
+        for &(bytes, orientation) in &cases {
+            let bus = MockI2c::new();
+            bus.script_read(&bytes);
+            assert!(LIS2DH12(&bus).orientation() == orientation);
+        }
+    }
+}
@@ -0,0 +1,520 @@
+//! Leveled logging usable from any task
+//!
+//! The macros `error!`, `warn!`, `info!`, `debug!` and `trace!` take a
+//! format string literal with `{}` (decimal) and `{:x}` (hex) placeholders
+//! and up to `MAX_ARGS` integer or `bool` arguments:
+//!
+//! ```ignore
+//! warn!("accelerometer id {:x} unknown", id);
+//! ```
+//!
+//! Logging has to be cheap in an interrupt handler, so nothing is formatted
+//! there. The format string is interned in a static `Metadata`, the task
+//! only pushes a pointer to it, the time and the raw arguments into `LOG`.
+//! A backend drains `LOG` later and formats the entries, see the `log`
+//! module of the firmware for the backends.
+//!
+//! Levels above `MAX_LEVEL` are compiled out with their strings. The level
+//! is Trace in debug and Info in release builds, the features `log-off`,
+//! `log-error`, `log-warn`, `log-info` and `log-debug` lower it.
+
+use core::cell::UnsafeCell;
+use core::fmt::{self, Write};
+use core::sync::atomic::{AtomicUsize, Ordering};
+use time;
+
+/// Arguments kept per entry, further ones are dropped
+pub const MAX_ARGS: usize = 4;
+/// Capacity of `LOG`, a power of two so positions stay valid when they
+/// wrap
+const LOG_SIZE: usize = 16;
+/// Entries kept by a `History`
+pub const HISTORY_SIZE: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub enum Level {
+    Error = 1,
+    Warn = 2,
+    Info = 3,
+    Debug = 4,
+    Trace = 5,
+}
+
+impl Level {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Level::Error => "ERROR",
+            Level::Warn => "WARN",
+            Level::Info => "INFO",
+            Level::Debug => "DEBUG",
+            Level::Trace => "TRACE",
+        }
+    }
+}
+
+/// Most verbose level compiled in as `Level as u8`, 0 compiles out all
+#[cfg(feature = "log-off")]
+pub const MAX_LEVEL: u8 = 0;
+#[cfg(all(feature = "log-error", not(feature = "log-off")))]
+pub const MAX_LEVEL: u8 = Level::Error as u8;
+#[cfg(all(feature = "log-warn", not(any(feature = "log-off", feature = "log-error"))))]
+pub const MAX_LEVEL: u8 = Level::Warn as u8;
+#[cfg(all(feature = "log-info",
+          not(any(feature = "log-off", feature = "log-error", feature = "log-warn"))))]
+pub const MAX_LEVEL: u8 = Level::Info as u8;
+#[cfg(all(feature = "log-debug",
+          not(any(feature = "log-off", feature = "log-error", feature = "log-warn",
+                  feature = "log-info"))))]
+pub const MAX_LEVEL: u8 = Level::Debug as u8;
+#[cfg(all(not(any(feature = "log-off", feature = "log-error", feature = "log-warn",
+                  feature = "log-info", feature = "log-debug")),
+          debug_assertions))]
+pub const MAX_LEVEL: u8 = Level::Trace as u8;
+#[cfg(all(not(any(feature = "log-off", feature = "log-error", feature = "log-warn",
+                  feature = "log-info", feature = "log-debug")),
+          not(debug_assertions)))]
+pub const MAX_LEVEL: u8 = Level::Info as u8;
+
+/// The static part of a log statement, created by the macros
+#[derive(Debug)]
+pub struct Metadata {
+    pub level: Level,
+    pub format: &'static str,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Arg {
+    Unsigned(u32),
+    Signed(i32),
+    Bool(bool),
+}
+
+macro_rules! arg_from {
+    ($variant:ident, $target:ty, $($source:ty),*) => {
+        $(
+            impl From<$source> for Arg {
+                fn from(value: $source) -> Arg {
+                    Arg::$variant(value as $target)
+                }
+            }
+        )*
+    };
+}
+
+arg_from!(Unsigned, u32, u8, u16, u32, usize);
+arg_from!(Signed, i32, i8, i16, i32, isize);
+
+impl From<bool> for Arg {
+    fn from(value: bool) -> Arg {
+        Arg::Bool(value)
+    }
+}
+
+impl Arg {
+    fn write(&self, out: &mut fmt::Formatter, hex: bool) -> fmt::Result {
+        match (*self, hex) {
+            (Arg::Unsigned(value), false) => write!(out, "{}", value),
+            (Arg::Unsigned(value), true) => write!(out, "{:x}", value),
+            (Arg::Signed(value), false) => write!(out, "{}", value),
+            (Arg::Signed(value), true) => write!(out, "{:x}", value),
+            (Arg::Bool(value), _) => write!(out, "{}", value),
+        }
+    }
+}
+
+/// A log statement as queued by a task
+#[derive(Clone, Copy, Debug)]
+pub struct Entry {
+    pub metadata: &'static Metadata,
+    /// Milliseconds since boot
+    pub time: u32,
+    args: [Arg; MAX_ARGS],
+    count: u8,
+}
+
+impl Entry {
+    pub fn new(metadata: &'static Metadata, time: u32, args: &[Arg]) -> Self {
+        let mut entry = Entry {
+            metadata,
+            time,
+            args: [Arg::Unsigned(0); MAX_ARGS],
+            count: 0,
+        };
+        for (slot, arg) in entry.args.iter_mut().zip(args) {
+            *slot = *arg;
+            entry.count += 1;
+        }
+        entry
+    }
+
+    pub fn level(&self) -> Level {
+        self.metadata.level
+    }
+
+    pub fn args(&self) -> &[Arg] {
+        &self.args[..usize::from(self.count)]
+    }
+
+    /// The message with the arguments in place of the placeholders, `?` for
+    /// missing ones
+    pub fn message<'a>(&'a self) -> Message<'a> {
+        Message(self)
+    }
+}
+
+/// `TIME LEVEL MESSAGE`, e.g. `1200 WARN accelerometer id 3a unknown`
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {} {}", self.time, self.metadata.level.name(), self.message())
+    }
+}
+
+pub struct Message<'a>(&'a Entry);
+
+impl<'a> fmt::Display for Message<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut args = self.0.args().iter();
+        let mut rest = self.0.metadata.format;
+
+        while let Some(start) = rest.find('{') {
+            f.write_str(&rest[..start])?;
+            rest = &rest[start..];
+
+            let hex = if rest.starts_with("{}") {
+                false
+            } else if rest.starts_with("{:x}") {
+                true
+            } else {
+                f.write_char('{')?;
+                rest = &rest[1..];
+                continue;
+            };
+            rest = &rest[if hex { 4 } else { 2 }..];
+
+            match args.next() {
+                Some(arg) => arg.write(f, hex)?,
+                None => f.write_char('?')?,
+            }
+        }
+        f.write_str(rest)
+    }
+}
+
+/// A slot of `Log`
+///
+/// `sequence` is the position a producer may claim the slot at, one more
+/// once the entry is written and ready to pop, and `LOG_SIZE` more once it's
+/// popped, which is the position of the next lap.
+struct Slot {
+    entry: UnsafeCell<Option<Entry>>,
+    sequence: AtomicUsize,
+}
+
+impl Slot {
+    const fn new(position: usize) -> Self {
+        Slot {
+            entry: UnsafeCell::new(None),
+            sequence: AtomicUsize::new(position),
+        }
+    }
+}
+
+/// Lock-free multiple producer single consumer queue of log entries
+///
+/// Any task may log at any priority: a producer claims a slot by advancing
+/// `tail` with a compare and swap and publishes the entry through the
+/// sequence of the slot, so a task preempting another one in the middle of
+/// a `push` takes the next slot. An entry claimed but not written yet ends
+/// `pop` until its producer resumes. A single backend drains the queue.
+/// Entries which don't fit are counted and dropped.
+pub struct Log {
+    slots: [Slot; LOG_SIZE],
+    /// Position of the next slot to pop, only written by the consumer
+    head: AtomicUsize,
+    /// Position of the next slot to claim
+    tail: AtomicUsize,
+    dropped: AtomicUsize,
+}
+
+unsafe impl Sync for Log {}
+
+impl Log {
+    #[cfg_attr(clippy, allow(clippy::new_without_default))]
+    pub const fn new() -> Self {
+        Log {
+            slots: [
+                Slot::new(0), Slot::new(1), Slot::new(2), Slot::new(3),
+                Slot::new(4), Slot::new(5), Slot::new(6), Slot::new(7),
+                Slot::new(8), Slot::new(9), Slot::new(10), Slot::new(11),
+                Slot::new(12), Slot::new(13), Slot::new(14), Slot::new(15),
+            ],
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            dropped: AtomicUsize::new(0),
+        }
+    }
+
+    /// Appends `entry`, `false` if the queue is full
+    pub fn push(&self, entry: Entry) -> bool {
+        let mut tail = self.tail.load(Ordering::Relaxed);
+        loop {
+            let slot = &self.slots[tail % LOG_SIZE];
+            let lap = slot.sequence.load(Ordering::Acquire).wrapping_sub(tail) as isize;
+            if lap < 0 {
+                // still holds the entry of the previous lap
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                return false;
+            }
+            if lap > 0 {
+                // claimed by a task which preempted this one
+                tail = self.tail.load(Ordering::Relaxed);
+                continue;
+            }
+
+            match self.tail.compare_exchange_weak(
+                tail,
+                tail.wrapping_add(1),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => {
+                    unsafe {
+                        *slot.entry.get() = Some(entry);
+                    }
+                    slot.sequence.store(tail.wrapping_add(1), Ordering::Release);
+                    return true;
+                }
+                Err(current) => tail = current,
+            }
+        }
+    }
+
+    /// Removes the oldest entry
+    pub fn pop(&self) -> Option<Entry> {
+        let head = self.head.load(Ordering::Relaxed);
+        let slot = &self.slots[head % LOG_SIZE];
+        if slot.sequence.load(Ordering::Acquire) != head.wrapping_add(1) {
+            return None;
+        }
+
+        let entry = unsafe { (*slot.entry.get()).take() };
+        slot.sequence.store(head.wrapping_add(LOG_SIZE), Ordering::Release);
+        self.head.store(head.wrapping_add(1), Ordering::Relaxed);
+        entry
+    }
+
+    /// `true` if there's no entry ready to pop
+    pub fn is_empty(&self) -> bool {
+        let head = self.head.load(Ordering::Relaxed);
+        self.slots[head % LOG_SIZE].sequence.load(Ordering::Acquire) != head.wrapping_add(1)
+    }
+
+    /// Entries dropped since the last call
+    pub fn take_dropped(&self) -> usize {
+        self.dropped.swap(0, Ordering::Relaxed)
+    }
+}
+
+/// The queue the macros log to
+pub static LOG: Log = Log::new();
+
+/// Queues a log statement, used by the macros
+pub fn write(metadata: &'static Metadata, args: &[Arg]) {
+    LOG.push(Entry::new(metadata, time::now(), args));
+}
+
+/// The latest entries, the RAM backend for reading with a debugger
+pub struct History {
+    entries: [Option<Entry>; HISTORY_SIZE],
+    /// Slot of the next entry
+    next: usize,
+}
+
+impl History {
+    #[cfg_attr(clippy, allow(clippy::new_without_default))]
+    pub const fn new() -> Self {
+        History {
+            entries: [None; HISTORY_SIZE],
+            next: 0,
+        }
+    }
+
+    /// Appends `entry`, replacing the oldest one when full
+    pub fn push(&mut self, entry: Entry) {
+        self.entries[self.next] = Some(entry);
+        self.next = (self.next + 1) % HISTORY_SIZE;
+    }
+
+    /// The entry logged `age` entries before the latest one
+    pub fn get(&self, age: usize) -> Option<&Entry> {
+        if age >= HISTORY_SIZE {
+            return None;
+        }
+        let index = (self.next + HISTORY_SIZE - 1 - age) % HISTORY_SIZE;
+        self.entries[index].as_ref()
+    }
+}
+
+#[macro_export]
+macro_rules! log {
+    ($level:ident, $format:expr) => {
+        log!($level, $format,)
+    };
+    ($level:ident, $format:expr, $($arg:expr),*) => {
+        if $crate::log::Level::$level as u8 <= $crate::log::MAX_LEVEL {
+            static METADATA: $crate::log::Metadata = $crate::log::Metadata {
+                level: $crate::log::Level::$level,
+                format: $format,
+            };
+            $crate::log::write(&METADATA, &[$($crate::log::Arg::from($arg)),*]);
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! error {
+    ($($arg:tt)*) => { log!(Error, $($arg)*) };
+}
+
+#[macro_export]
+macro_rules! warn {
+    ($($arg:tt)*) => { log!(Warn, $($arg)*) };
+}
+
+#[macro_export]
+macro_rules! info {
+    ($($arg:tt)*) => { log!(Info, $($arg)*) };
+}
+
+#[macro_export]
+macro_rules! debug {
+    ($($arg:tt)*) => { log!(Debug, $($arg)*) };
+}
+
+#[macro_export]
+macro_rules! trace {
+    ($($arg:tt)*) => { log!(Trace, $($arg)*) };
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use self::std::sync::Arc;
+    use self::std::thread;
+    use self::std::vec::Vec;
+    use super::*;
+
+    static METADATA: Metadata = Metadata {
+        level: Level::Info,
+        format: "{} {}",
+    };
+
+    fn entry(time: u32) -> Entry {
+        Entry::new(&METADATA, time, &[])
+    }
+
+    fn times(log: &Log) -> Vec<u32> {
+        let mut times = Vec::new();
+        while let Some(entry) = log.pop() {
+            times.push(entry.time);
+        }
+        times
+    }
+
+    #[test]
+    fn drops_entries_when_full() {
+        let log = Log::new();
+        assert!(log.is_empty());
+        for time in 0..LOG_SIZE as u32 {
+            assert!(log.push(entry(time)));
+        }
+        assert!(!log.push(entry(100)));
+        assert!(!log.push(entry(101)));
+        assert_eq!(log.take_dropped(), 2);
+        assert_eq!(log.take_dropped(), 0);
+
+        assert_eq!(log.pop().map(|entry| entry.time), Some(0));
+        assert!(log.push(entry(16)));
+        let expected: Vec<u32> = (1..17).collect();
+        assert_eq!(times(&log), expected);
+        assert!(log.is_empty());
+    }
+
+    #[test]
+    fn positions_wrap() {
+        let log = Log::new();
+        let start = usize::MAX - 5;
+        log.head.store(start, Ordering::Relaxed);
+        log.tail.store(start, Ordering::Relaxed);
+        // as if the previous laps were pushed and popped
+        for (index, slot) in log.slots.iter().enumerate() {
+            let ahead = (index + LOG_SIZE - start % LOG_SIZE) % LOG_SIZE;
+            slot.sequence.store(start.wrapping_add(ahead), Ordering::Relaxed);
+        }
+
+        for round in 0..3 {
+            for time in 0..10 {
+                assert!(log.push(entry(round * 10 + time)));
+            }
+            let expected: Vec<u32> = (round * 10..round * 10 + 10).collect();
+            assert_eq!(times(&log), expected);
+        }
+    }
+
+    #[test]
+    fn preempting_producer_takes_the_next_slot() {
+        let log = Log::new();
+        // a task claimed slot 0 and was preempted before writing it
+        log.tail.store(1, Ordering::Relaxed);
+
+        assert!(log.push(entry(2)));
+        assert!(log.is_empty());
+        assert_eq!(log.pop().map(|entry| entry.time), None);
+
+        // the preempted task resumes
+        unsafe {
+            *log.slots[0].entry.get() = Some(entry(1));
+        }
+        log.slots[0].sequence.store(1, Ordering::Release);
+        assert_eq!(times(&log), [1, 2]);
+    }
+
+    #[test]
+    fn concurrent_producers() {
+        const PRODUCERS: u32 = 4;
+        const ENTRIES: u32 = 10_000;
+
+        let log = Arc::new(Log::new());
+        let producers: Vec<_> = (0..PRODUCERS)
+            .map(|producer| {
+                let log = log.clone();
+                thread::spawn(move || {
+                    for count in 0..ENTRIES {
+                        while !log.push(entry(producer * ENTRIES + count)) {
+                            thread::yield_now();
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        let mut next = [0; PRODUCERS as usize];
+        let mut received = 0;
+        while received < PRODUCERS * ENTRIES {
+            match log.pop() {
+                Some(entry) => {
+                    let producer = (entry.time / ENTRIES) as usize;
+                    assert_eq!(entry.time % ENTRIES, next[producer]);
+                    next[producer] += 1;
+                    received += 1;
+                }
+                None => thread::yield_now(),
+            }
+        }
+        for producer in producers {
+            producer.join().unwrap();
+        }
+        assert!(log.is_empty());
+    }
+}
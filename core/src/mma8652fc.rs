@@ -0,0 +1,1218 @@
+use accelerometer::{self, Accel, Accelerometer, Error, Gesture, Orientation, TapConfig};
+use i2c::I2cBus;
+
+pub const I2C_ADDRESS: u8 = 0x1D;
+/// Content of the `WHO_AM_I` register
+pub const DEVICE_ID: u8 = 0x4A;
+
+/// Minimum output change of every axis with self-test enabled at ±4 g, well
+/// below the typical values given in the datasheet
+const SELF_TEST_MIN_DELTA: [i16; 3] = [5, 5, 50];
+/// Samples averaged per self-test phase
+const SELF_TEST_SAMPLES: i16 = 4;
+/// `STATUS` polls before giving up on new data
+const DATA_READY_POLLS: u32 = 10_000;
+/// Samples the FIFO can hold
+pub const FIFO_SIZE: usize = 32;
+/// Samples averaged by the offset calibration
+const CALIBRATION_SAMPLES: i32 = 32;
+/// Counts per g at ±2 g
+const ONE_G: i32 = 1024;
+/// Counts at ±2 g per `OFF_*` step of 1.96 mg
+const OFFSET_STEP: i32 = 2;
+/// Cycles the sensor is left alone after a reset
+const RESET_CYCLES: u32 = 10_000;
+
+/// MMA8652FC Register Addresses
+#[allow(dead_code)]
+#[allow(non_camel_case_types)]
+#[derive(Clone, Copy)]
+pub enum Register {
+    /// Status Register (R)
+    STATUS = 0x00,
+
+    /// [7:0] are 8 MSBs of the 12-bit X-axis sample (R)
+    OUT_X_MSB = 0x01,
+    /// [7:4] are 4 LSBs of the 12-bit X-axis sample (R)
+    OUT_X_LSB = 0x02,
+    /// [7:0] are 8 MSBs of the 12-bit Y-axis sample (R)
+    OUT_Y_MSB = 0x03,
+    /// [7:4] are 4 LSBs of the 12-bit Y-axis sample (R)
+    OUT_Y_LSB = 0x04,
+    /// [7:0] are 8 MSBs of the 12-bit Z-axis sample (R)
+    OUT_Z_MSB = 0x05,
+    /// [7:4] are 4 LSBs of the 12-bit Z-axis sample (R)
+    OUT_Z_LSB = 0x06,
+
+    /// FIFO Setup Register (R/W)
+    F_SETUP = 0x09,
+    /// Map of FIFO data capture events (R/W)
+    TRIG_CFG = 0x0A,
+    /// System Mode Register (R)
+    SYSMOD = 0x0B,
+    /// System Interrupt Status Register (R)
+    INT_SOURCE = 0x0C,
+    /// Device ID Register (R)
+    WHO_AM_I = 0x0D,
+    /// Sensor Data Configuration Register (R/W)
+    XYZ_DATA_CFG = 0x0E,
+    /// High Pass Filter Register (R/W)
+    HP_FILTER_CUTOFF = 0x0F,
+
+    /// Portait/tLandscape Status Register (R)
+    PL_STATUS = 0x10,
+    /// Portrait/Landscape Configuration Register (R/W)
+    PL_CFG = 0x11,
+    /// Portrait/Landscape Debounce Register (R/W)
+    PL_COUNT = 0x12,
+    /// Portrait/Landscape Back/Front and Z Compensation Register (R/W)
+    PL_BF_ZCOMP = 0x13,
+    /// Portrait/Landscape Threshold Register (R/W)
+    P_L_THS_REG = 0x14,
+
+    /// Freefall and Motion Configuration Register (R/W)
+    FF_MT_CFG = 0x15,
+    /// Freefall and Motion Source Register (R)
+    FF_MT_SRC = 0x16,
+    /// Freefall and Motion Threshold Register (R/W)
+    FF_MT_THS = 0x17,
+    /// Freefall Motion Count Register (R/W)
+    FF_MT_COUNT = 0x18,
+
+    /// Transient Configuration Register (R/W)
+    TRANSIENT_CFG = 0x1D,
+    /// Transient Source Register (R)
+    TRANSIENT_SRC = 0x1E,
+    /// Transient Threshold Register (R/W)
+    TRANSIENT_THS = 0x1F,
+    /// Transient Debounce Counter Register (R/W)
+    TRANSIENT_COUNT = 0x20,
+
+    /// Pulse Configuration Register (R/W)
+    PULSE_CFG = 0x21,
+    /// Pulse Source Register (R)
+    PULSE_SRC = 0x22,
+    /// Pulse X Threshold Register (R/W)
+    PULSE_THS_X = 0x23,
+    /// Pulse Y Threshold Register (R/W)
+    PULSE_THS_Y = 0x24,
+    /// Pulse Z Threshold Register (R/W)
+    PULSE_THS_Z = 0x25,
+    /// Pulse Time Window Register (R/W)
+    PULSE_TLMT = 0x26,
+    /// Pulse Latency Timer Register (R/W)
+    PULSE_LTCY = 0x27,
+    /// Second Pulse Time Window Register (R/W)
+    PULSE_WIND = 0x28,
+
+    /// Auto Sleep Inactivity Timer Register (R/W)
+    ALSP_COUNT = 0x29,
+
+    /// System Control 1 Register (R/W)
+    CTRL_REG1 = 0x2A,
+    /// System Control 2 Register (R/W)
+    CTRL_REG2 = 0x2B,
+    /// Interrupt Control Register (R/W)
+    CTRL_REG3 = 0x2C,
+    /// Interrupt Enable Register (R/W)
+    CTRL_REG4 = 0x2D,
+    /// Interrupt Configuration Register (R/W)
+    CTRL_REG5 = 0x2E,
+
+    /// X Offset Correction Register (R/W)
+    OFF_X = 0x2F,
+    /// Y Offset Correction Register (R/W)
+    OFF_Y = 0x30,
+    /// Z Offset Correction Register (R/W)
+    OFF_Z = 0x31,
+}
+
+impl Register {
+    pub fn addr(&self) -> u8 {
+        *self as u8
+    }
+}
+
+/// Full scale range selected by the `FS` bits of `XYZ_DATA_CFG`
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum Range {
+    /// ±2 g, 1024 counts/g
+    G2 = 0b00,
+    /// ±4 g, 512 counts/g
+    G4 = 0b01,
+    /// ±8 g, 256 counts/g
+    G8 = 0b10,
+}
+
+impl Range {
+    /// Decodes the `FS` bits of `XYZ_DATA_CFG`, the reserved value 0b11 is
+    /// treated as ±8 g
+    pub fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0b00 => Range::G2,
+            0b01 => Range::G4,
+            _ => Range::G8,
+        }
+    }
+
+    /// Counts per g of a 12-bit sample
+    pub fn sensitivity(&self) -> i32 {
+        match *self {
+            Range::G2 => 1024,
+            Range::G4 => 512,
+            Range::G8 => 256,
+        }
+    }
+
+    /// Converts a 12-bit sample to milli-g, truncating towards zero
+    pub fn milli_g(&self, counts: i16) -> i16 {
+        (i32::from(counts) * 1000 / self.sensitivity()) as i16
+    }
+}
+
+/// Output data rate selected by the `DR` bits of `CTRL_REG1`
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum DataRate {
+    Hz800 = 0b000,
+    Hz400 = 0b001,
+    Hz200 = 0b010,
+    Hz100 = 0b011,
+    Hz50 = 0b100,
+    Hz12_5 = 0b101,
+    Hz6_25 = 0b110,
+    Hz1_56 = 0b111,
+}
+
+impl DataRate {
+    /// Output data rate in mHz
+    pub fn millihertz(&self) -> u32 {
+        match *self {
+            DataRate::Hz800 => 800_000,
+            DataRate::Hz400 => 400_000,
+            DataRate::Hz200 => 200_000,
+            DataRate::Hz100 => 100_000,
+            DataRate::Hz50 => 50_000,
+            DataRate::Hz12_5 => 12_500,
+            DataRate::Hz6_25 => 6_250,
+            DataRate::Hz1_56 => 1_563,
+        }
+    }
+}
+
+/// Output data rate while auto-sleeping, `ASLP_RATE` bits of `CTRL_REG1`
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum SleepRate {
+    Hz50 = 0b00,
+    Hz12_5 = 0b01,
+    Hz6_25 = 0b10,
+    Hz1_56 = 0b11,
+}
+
+impl SleepRate {
+    /// Output data rate in mHz
+    pub fn millihertz(&self) -> u32 {
+        match *self {
+            SleepRate::Hz50 => 50_000,
+            SleepRate::Hz12_5 => 12_500,
+            SleepRate::Hz6_25 => 6_250,
+            SleepRate::Hz1_56 => 1_563,
+        }
+    }
+}
+
+/// Oversampling (power) mode, `MODS`/`SMODS` bits of `CTRL_REG2`
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum Oversampling {
+    Normal = 0b00,
+    LowNoiseLowPower = 0b01,
+    HighResolution = 0b10,
+    LowPower = 0b11,
+}
+
+/// High pass filter cutoff, `SEL` bits of `HP_FILTER_CUTOFF`
+///
+/// The resulting frequency depends on the data rate and oversampling mode,
+/// see table 34 of the datasheet. In high resolution mode it's 16, 8, 4 and
+/// 2 Hz.
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum HpfCutoff {
+    Highest = 0b00,
+    High = 0b01,
+    Low = 0b10,
+    Lowest = 0b11,
+}
+
+/// FIFO operating mode, `F_MODE` bits of `F_SETUP`
+#[allow(dead_code)]
+#[derive(Clone, Copy, PartialEq)]
+pub enum FifoMode {
+    Disabled = 0b00,
+    /// Oldest samples are overwritten when full
+    Circular = 0b01,
+    /// Sampling stops when full
+    Fill = 0b10,
+    /// Circular until a trigger event, then keeps the samples around it
+    Trigger = 0b11,
+}
+
+/// Events latching the FIFO in trigger mode, bits of `TRIG_CFG`
+pub mod trigger {
+    pub const FF_MT: u8 = 1 << 2;
+    pub const PULSE: u8 = 1 << 3;
+    pub const LNDPRT: u8 = 1 << 4;
+    pub const TRANS: u8 = 1 << 5;
+}
+
+/// FIFO settings
+#[derive(Clone, Copy)]
+pub struct FifoConfig {
+    pub mode: FifoMode,
+    /// Samples in the FIFO raising the watermark interrupt, 0 disables it
+    pub watermark: u8,
+    /// Trigger events used in `FifoMode::Trigger`, see `trigger`
+    pub triggers: u8,
+}
+
+/// Auto-sleep settings
+#[derive(Clone, Copy)]
+pub struct AutoSleep {
+    /// Output data rate while sleeping
+    pub rate: SleepRate,
+    /// Oversampling mode while sleeping
+    pub oversampling: Oversampling,
+    /// Inactivity time before entering sleep, 320 ms per count at 50 Hz
+    /// and above, 640 ms per count at 12.5 Hz and below
+    pub count: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConfigError {
+    /// The auto-sleep rate is faster than the active data rate
+    SleepRateAboveDataRate,
+}
+
+impl From<ConfigError> for Error {
+    fn from(_: ConfigError) -> Self {
+        Error::InvalidConfig
+    }
+}
+
+// STATUS
+const ZYXDR: u8 = 1 << 3;
+// F_STATUS, the STATUS register with FIFO enabled
+const F_CNT: u8 = 0x3F;
+// F_SETUP
+const F_WMRK: u8 = 0x3F;
+// PL_STATUS
+const LO: u8 = 1 << 6;
+// CTRL_REG1
+const ACTIVE: u8 = 1 << 0;
+// CTRL_REG2
+const SLPE: u8 = 1 << 2;
+const RST: u8 = 1 << 6;
+const ST: u8 = 1 << 7;
+// CTRL_REG4, CTRL_REG5
+const INT_DRDY: u8 = 1 << 0;
+const INT_FF_MT: u8 = 1 << 2;
+const INT_PULSE: u8 = 1 << 3;
+const INT_FIFO: u8 = 1 << 6;
+// PULSE_CFG: single and double pulse on X, Y and Z, latched
+const PULSE_SINGLE_XYZ: u8 = 0x15;
+const PULSE_DOUBLE_XYZ: u8 = 0x2A;
+const PULSE_ELE: u8 = 1 << 6;
+// PULSE_SRC
+const PULSE_DPE: u8 = 1 << 3;
+const PULSE_EA: u8 = 1 << 7;
+/// Resolution of `PULSE_THS_*` in milli-g
+const PULSE_THS_STEP: u16 = 63;
+/// `PULSE_TLMT` step in µs, high resolution mode with the pulse low pass
+/// filter disabled, independent of the data rate
+const PULSE_TLMT_STEP: u32 = 625;
+/// `PULSE_LTCY` and `PULSE_WIND` step in µs
+const PULSE_WIND_STEP: u32 = 2 * PULSE_TLMT_STEP;
+// XYZ_DATA_CFG
+const HPF_OUT: u8 = 1 << 4;
+// FF_MT_CFG: OR of X, Y and Z high events, motion detection
+const FF_MT_MOTION_XYZ: u8 = 0x78;
+/// Resolution of `FF_MT_THS` in milli-g
+const FF_MT_THS_STEP: u16 = 63;
+
+/// Sensor configuration written by `MMA8652FC::init`
+#[derive(Clone, Copy)]
+pub struct Config {
+    range: Range,
+    data_rate: DataRate,
+    oversampling: Oversampling,
+    high_pass: Option<HpfCutoff>,
+    auto_sleep: Option<AutoSleep>,
+    offsets: [i8; 3],
+}
+
+impl Config {
+    /// ±2 g at 100 Hz with high resolution oversampling and unfiltered
+    /// output, which keeps gravity and the offset correction in the samples
+    #[cfg_attr(clippy, allow(clippy::new_without_default))]
+    pub fn new() -> Self {
+        Config {
+            range: Range::G2,
+            data_rate: DataRate::Hz100,
+            oversampling: Oversampling::HighResolution,
+            high_pass: None,
+            auto_sleep: None,
+            offsets: [0; 3],
+        }
+    }
+
+    pub fn range(mut self, range: Range) -> Self {
+        self.range = range;
+        self
+    }
+
+    pub fn data_rate(mut self, data_rate: DataRate) -> Self {
+        self.data_rate = data_rate;
+        self
+    }
+
+    pub fn oversampling(mut self, oversampling: Oversampling) -> Self {
+        self.oversampling = oversampling;
+        self
+    }
+
+    /// Selects high pass filtered output data, `None` for unfiltered data
+    ///
+    /// The filter removes gravity and with it any effect of the offset
+    /// correction on the samples and the FIFO, only the embedded functions
+    /// still see the corrected unfiltered data.
+    pub fn high_pass(mut self, cutoff: Option<HpfCutoff>) -> Self {
+        self.high_pass = cutoff;
+        self
+    }
+
+    pub fn auto_sleep(mut self, auto_sleep: Option<AutoSleep>) -> Self {
+        self.auto_sleep = auto_sleep;
+        self
+    }
+
+    /// Offset correction register values for X, Y and Z, see
+    /// `MMA8652FC::calibrate`
+    pub fn offsets(mut self, offsets: [i8; 3]) -> Self {
+        self.offsets = offsets;
+        self
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if let Some(sleep) = self.auto_sleep {
+            if sleep.rate.millihertz() > self.data_rate.millihertz() {
+                return Err(ConfigError::SleepRateAboveDataRate);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn ctrl_reg1(&self) -> u8 {
+        let sleep_rate = self.auto_sleep.map_or(0, |sleep| sleep.rate as u8);
+        (sleep_rate << 6) | ((self.data_rate as u8) << 3) | ACTIVE
+    }
+
+    fn ctrl_reg2(&self) -> u8 {
+        match self.auto_sleep {
+            Some(sleep) => ((sleep.oversampling as u8) << 3) | SLPE | self.oversampling as u8,
+            None => ((self.oversampling as u8) << 3) | self.oversampling as u8,
+        }
+    }
+
+    fn xyz_data_cfg(&self) -> u8 {
+        let hpf = if self.high_pass.is_some() { HPF_OUT } else { 0 };
+        hpf | self.range as u8
+    }
+
+    fn hp_filter_cutoff(&self) -> u8 {
+        self.high_pass.unwrap_or(HpfCutoff::Highest) as u8
+    }
+}
+
+/// Combines the left justified MSB and LSB registers of one axis into a sign
+/// extended 12-bit sample
+pub fn sample(msb: u8, lsb: u8) -> i16 {
+    (((u16::from(msb) << 8) | u16::from(lsb)) as i16) >> 4
+}
+
+pub struct MMA8652FC<'a, B: 'a + I2cBus>(pub &'a B);
+
+impl<'a, B: I2cBus> MMA8652FC<'a, B> {
+    /// Checks the sensor is present and working, then resets it and applies
+    /// `config`, leaving it in active mode
+    pub fn init(&self, config: &Config) -> Result<(), Error> {
+        config.validate()?;
+
+        if !self.0.probe(I2C_ADDRESS) {
+            return Err(Error::NotPresent);
+        }
+
+        let id = self.get_register(Register::WHO_AM_I);
+        if id != DEVICE_ID {
+            return Err(Error::UnknownDevice(id));
+        }
+
+        self.reset();
+        self.self_test()?;
+        self.reset();
+        // Enable motion detection for X, Y and Z axis, latch disabled
+        self.set_register(Register::FF_MT_CFG, FF_MT_MOTION_XYZ);
+
+//        self.set_register(Register::FreefallMotionThr, 0x10);
+//        self.set_register(Register::FreefallMotionCnt, 0x02);
+
+        // Enable orientation detection
+        self.set_register(Register::PL_CFG, 0x40);
+        // set Debounce to 200 Counts
+        self.set_register(Register::PL_COUNT, 200);
+        // set Threshold to 42 degrees
+        self.set_register(Register::PL_BF_ZCOMP, 0b01000111);
+        // set threshold
+        self.set_register(Register::P_L_THS_REG, 0b10011100);
+        // enable data ready and orientation interrupt
+        self.set_register(Register::CTRL_REG4, 0x01 | (1 << 4));
+        // route data ready interrupt to INT1 and orientation interrupt to INT2
+        self.set_register(Register::CTRL_REG5, 0x01);
+
+        self.configure(config);
+        Ok(())
+    }
+
+    /// Writes the data path registers of `config` and activates the sensor
+    ///
+    /// The sensor has to be in standby mode.
+    fn configure(&self, config: &Config) {
+        if let Some(sleep) = config.auto_sleep {
+            self.set_register(Register::ALSP_COUNT, sleep.count);
+        }
+        self.set_register(Register::OFF_X, config.offsets[0] as u8)
+            .set_register(Register::OFF_Y, config.offsets[1] as u8)
+            .set_register(Register::OFF_Z, config.offsets[2] as u8);
+        self.set_register(Register::CTRL_REG2, config.ctrl_reg2())
+            .set_register(Register::XYZ_DATA_CFG, config.xyz_data_cfg())
+            .set_register(Register::HP_FILTER_CUTOFF, config.hp_filter_cutoff())
+            .set_register(Register::CTRL_REG1, config.ctrl_reg1());
+    }
+
+    /// Resets all registers to their POR values
+    fn reset(&self) {
+        self
+        // Normal Mode
+            .set_register(Register::CTRL_REG2, 0);
+        // Reset all registers to POR values
+        self.set_register(Register::CTRL_REG2, RST);
+        self.0.delay(RESET_CYCLES);
+    }
+
+    /// Compares samples with and without the self-test actuation
+    ///
+    /// Expects the sensor freshly reset and leaves it in standby mode.
+    fn self_test(&self) -> Result<(), Error> {
+        // ±4 g, high resolution, 100 Hz
+        self.set_register(Register::XYZ_DATA_CFG, Range::G4 as u8)
+            .set_register(Register::CTRL_REG2, Oversampling::HighResolution as u8)
+            .set_register(Register::CTRL_REG1, ((DataRate::Hz100 as u8) << 3) | ACTIVE);
+        let normal = self.average()?;
+
+        self.standby()
+            .set_register(Register::CTRL_REG2, ST | Oversampling::HighResolution as u8)
+            .set_register(Register::CTRL_REG1, ((DataRate::Hz100 as u8) << 3) | ACTIVE);
+        // drop the sample taken while the actuation settles
+        self.average()?;
+        let actuated = self.average()?;
+
+        self.standby()
+            .set_register(Register::CTRL_REG2, Oversampling::HighResolution as u8);
+
+        let delta = [actuated.x - normal.x, actuated.y - normal.y, actuated.z - normal.z];
+        if delta.iter().zip(SELF_TEST_MIN_DELTA.iter()).all(|(d, min)| d >= min) {
+            Ok(())
+        } else {
+            Err(Error::SelfTestFailed)
+        }
+    }
+
+    /// Computes offset correction register values from the average of
+    /// `CALIBRATION_SAMPLES` samples, the iron has to lie flat and still
+    ///
+    /// X and Y are corrected to 0 g, Z to ±1 g depending on which side is
+    /// up. Resets the sensor, it has to be initialized again afterwards.
+    /// The offsets only show in the samples without high pass filter, see
+    /// `Config::high_pass`.
+    #[cfg_attr(clippy, allow(clippy::manual_clamp))]
+    pub fn calibrate(&self) -> Result<[i8; 3], Error> {
+        self.reset();
+        // ±2 g, high resolution, 100 Hz, no high pass filter
+        self.set_register(Register::XYZ_DATA_CFG, Range::G2 as u8)
+            .set_register(Register::CTRL_REG2, Oversampling::HighResolution as u8)
+            .set_register(Register::CTRL_REG1, ((DataRate::Hz100 as u8) << 3) | ACTIVE);
+
+        let mut sum = [0i32; 3];
+        for _ in 0..CALIBRATION_SAMPLES {
+            self.wait_data_ready()?;
+            let sample = self.accel();
+            sum[0] += i32::from(sample.x);
+            sum[1] += i32::from(sample.y);
+            sum[2] += i32::from(sample.z);
+        }
+        self.standby();
+
+        let gravity = if sum[2] >= 0 { ONE_G } else { -ONE_G };
+        let expected = [0, 0, gravity];
+        let mut offsets = [0i8; 3];
+        for ((offset, sum), expected) in offsets.iter_mut().zip(sum.iter()).zip(expected.iter()) {
+            let error = sum / CALIBRATION_SAMPLES - expected;
+            *offset = (-error / OFFSET_STEP).max(-128).min(127) as i8;
+        }
+
+        Ok(offsets)
+    }
+
+    /// Averages `SELF_TEST_SAMPLES` fresh samples
+    fn average(&self) -> Result<Accel, Error> {
+        let mut sum = Accel { x: 0, y: 0, z: 0 };
+        for _ in 0..SELF_TEST_SAMPLES {
+            self.wait_data_ready()?;
+            let sample = self.accel();
+            sum.x += sample.x;
+            sum.y += sample.y;
+            sum.z += sample.z;
+        }
+
+        Ok(Accel {
+            x: sum.x / SELF_TEST_SAMPLES,
+            y: sum.y / SELF_TEST_SAMPLES,
+            z: sum.z / SELF_TEST_SAMPLES,
+        })
+    }
+
+    fn wait_data_ready(&self) -> Result<(), Error> {
+        for _ in 0..DATA_READY_POLLS {
+            if self.get_register(Register::STATUS) & ZYXDR != 0 {
+                return Ok(());
+            }
+        }
+
+        Err(Error::NoData)
+    }
+
+    /// Enters standby mode, most registers can only be written in standby
+    pub fn standby(&self) -> &Self {
+        self.set_register(Register::CTRL_REG1, 0)
+    }
+
+    pub fn accel(&self) -> Accel {
+        let mut bytes = [0; 6];
+        self.0.read(I2C_ADDRESS, Register::OUT_X_MSB.addr(), &mut bytes);
+
+        Accel {
+            x: sample(bytes[0], bytes[1]),
+            y: sample(bytes[2], bytes[3]),
+            z: sample(bytes[4], bytes[5]),
+        }
+    }
+
+    /// Sets up the FIFO and replaces the data ready interrupt on INT1 with
+    /// the FIFO watermark interrupt, or the other way round if disabled
+    pub fn configure_fifo(&self, config: &FifoConfig) -> &Self {
+        let ctrl_reg1 = self.get_register(Register::CTRL_REG1);
+        let ctrl_reg4 = self.get_register(Register::CTRL_REG4) & !(INT_DRDY | INT_FIFO);
+        let ctrl_reg5 = self.get_register(Register::CTRL_REG5) & !(INT_DRDY | INT_FIFO);
+
+        // interrupt routing and F_MODE can only be changed in standby
+        self.set_register(Register::CTRL_REG1, ctrl_reg1 & !ACTIVE)
+            .set_register(Register::F_SETUP, 0);
+
+        if config.mode == FifoMode::Disabled {
+            self.set_register(Register::CTRL_REG4, ctrl_reg4 | INT_DRDY)
+                .set_register(Register::CTRL_REG5, ctrl_reg5 | INT_DRDY);
+        } else {
+            let interrupt = if config.watermark > 0 { INT_FIFO } else { 0 };
+            self.set_register(Register::TRIG_CFG, config.triggers)
+                .set_register(Register::CTRL_REG4, ctrl_reg4 | interrupt)
+                .set_register(Register::CTRL_REG5, ctrl_reg5 | interrupt)
+                .set_register(
+                    Register::F_SETUP,
+                    ((config.mode as u8) << 6) | (config.watermark & F_WMRK),
+                );
+        }
+
+        self.set_register(Register::CTRL_REG1, ctrl_reg1)
+    }
+
+    /// Reads all buffered samples in one burst and converts them to milli-g
+    ///
+    /// Returns the number of samples written to `samples`. Reads a single
+    /// sample if the FIFO is disabled.
+    pub fn drain_fifo(&self, samples: &mut [Accel]) -> usize {
+        if samples.is_empty() {
+            return 0;
+        }
+
+        if self.get_register(Register::F_SETUP) >> 6 == FifoMode::Disabled as u8 {
+            samples[0] = self.accel_milli_g();
+            return 1;
+        }
+
+        let range = self.range();
+        let count = (self.get_register(Register::STATUS) & F_CNT) as usize;
+        let count = count.min(samples.len()).min(FIFO_SIZE);
+        if count == 0 {
+            return 0;
+        }
+
+        let mut bytes = [0; FIFO_SIZE * 6];
+        self.0.read(I2C_ADDRESS, Register::OUT_X_MSB.addr(), &mut bytes[..count * 6]);
+
+        for (sample, bytes) in samples.iter_mut().zip(bytes.chunks(6)).take(count) {
+            *sample = Accel {
+                x: range.milli_g(self::sample(bytes[0], bytes[1])),
+                y: range.milli_g(self::sample(bytes[2], bytes[3])),
+                z: range.milli_g(self::sample(bytes[4], bytes[5])),
+            };
+        }
+
+        count
+    }
+
+    /// Reads the currently configured full scale range
+    pub fn range(&self) -> Range {
+        Range::from_bits(self.get_register(Register::XYZ_DATA_CFG))
+    }
+
+    /// Reads a sample and converts it to milli-g
+    pub fn accel_milli_g(&self) -> Accel {
+        let range = self.range();
+        let accel = self.accel();
+
+        Accel {
+            x: range.milli_g(accel.x),
+            y: range.milli_g(accel.y),
+            z: range.milli_g(accel.z),
+        }
+    }
+
+    pub fn get_register(&self, reg: Register) -> u8 {
+        let mut value = [0];
+        self.0.read(I2C_ADDRESS, reg.addr(), &mut value);
+        value[0]
+    }
+
+    pub fn set_register(&self, reg: Register, value: u8) -> &Self {
+        self.0.write(I2C_ADDRESS, reg.addr(), value);
+        self
+    }
+}
+
+impl<'a, B: I2cBus> Accelerometer for MMA8652FC<'a, B> {
+    fn sample(&self) -> Accel {
+        self.accel_milli_g()
+    }
+
+    fn drain(&self, samples: &mut [Accel]) -> usize {
+        self.drain_fifo(samples)
+    }
+
+    fn configure_motion_wake(&self, threshold: u16, count: u8) {
+        let ctrl_reg1 = self.get_register(Register::CTRL_REG1);
+        let ctrl_reg4 = self.get_register(Register::CTRL_REG4);
+        let ctrl_reg5 = self.get_register(Register::CTRL_REG5);
+        let ths = (threshold / FF_MT_THS_STEP).min(0x7F) as u8;
+
+        self.set_register(Register::CTRL_REG1, ctrl_reg1 & !ACTIVE)
+            .set_register(Register::FF_MT_CFG, FF_MT_MOTION_XYZ)
+            .set_register(Register::FF_MT_THS, ths)
+            .set_register(Register::FF_MT_COUNT, count)
+            .set_register(Register::CTRL_REG4, ctrl_reg4 | INT_FF_MT)
+            // route motion interrupt to INT1
+            .set_register(Register::CTRL_REG5, ctrl_reg5 | INT_FF_MT)
+            .set_register(Register::CTRL_REG1, ctrl_reg1);
+    }
+
+    fn orientation(&self) -> Orientation {
+        let status = self.get_register(Register::PL_STATUS);
+        if status & LO != 0 {
+            return Orientation::Flat;
+        }
+
+        match (status >> 1) & 0b11 {
+            0b00 => Orientation::PortraitUp,
+            0b01 => Orientation::PortraitDown,
+            0b10 => Orientation::LandscapeRight,
+            _ => Orientation::LandscapeLeft,
+        }
+    }
+
+    /// The time steps assume high resolution oversampling
+    fn configure_taps(&self, config: &TapConfig) {
+        let ctrl_reg1 = self.get_register(Register::CTRL_REG1);
+        let ctrl_reg4 = self.get_register(Register::CTRL_REG4);
+        let ctrl_reg5 = self.get_register(Register::CTRL_REG5);
+
+        let mut pulse_cfg = 0;
+        if config.single {
+            pulse_cfg |= PULSE_SINGLE_XYZ;
+        }
+        if config.double {
+            pulse_cfg |= PULSE_DOUBLE_XYZ;
+        }
+
+        self.set_register(Register::CTRL_REG1, ctrl_reg1 & !ACTIVE);
+        if pulse_cfg == 0 {
+            self.set_register(Register::PULSE_CFG, 0)
+                .set_register(Register::CTRL_REG4, ctrl_reg4 & !INT_PULSE);
+        } else {
+            let ths = (config.threshold / PULSE_THS_STEP).min(0x7F) as u8;
+            self.set_register(Register::PULSE_CFG, pulse_cfg | PULSE_ELE)
+                .set_register(Register::PULSE_THS_X, ths)
+                .set_register(Register::PULSE_THS_Y, ths)
+                .set_register(Register::PULSE_THS_Z, ths)
+                .set_register(
+                    Register::PULSE_TLMT,
+                    accelerometer::steps(config.time_limit, PULSE_TLMT_STEP),
+                )
+                .set_register(
+                    Register::PULSE_LTCY,
+                    accelerometer::steps(config.latency, PULSE_WIND_STEP),
+                )
+                .set_register(
+                    Register::PULSE_WIND,
+                    accelerometer::steps(config.window, PULSE_WIND_STEP),
+                )
+                .set_register(Register::CTRL_REG4, ctrl_reg4 | INT_PULSE)
+                // route pulse interrupt to INT1
+                .set_register(Register::CTRL_REG5, ctrl_reg5 | INT_PULSE);
+        }
+        self.set_register(Register::CTRL_REG1, ctrl_reg1);
+    }
+
+    fn gesture(&self) -> Option<Gesture> {
+        let source = self.get_register(Register::PULSE_SRC);
+        if source & PULSE_EA == 0 {
+            None
+        } else if source & PULSE_DPE != 0 {
+            Some(Gesture::DoubleTap)
+        } else {
+            Some(Gesture::Tap)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::{MockI2c, Transaction};
+
+    /// Scripts the `STATUS` poll and the burst read of one sample
+    fn script_sample(bus: &MockI2c, bytes: &[u8; 6]) {
+        bus.script_read(&[ZYXDR]).script_read(bytes);
+    }
+
+    /// Scripts `WHO_AM_I` and the samples of a passing self-test
+    fn script_init(bus: &MockI2c) {
+        bus.script_read(&[DEVICE_ID]);
+        for _ in 0..SELF_TEST_SAMPLES {
+            script_sample(bus, &[0; 6]);
+        }
+        // settling and actuated samples, 16, 16 and 64 counts above normal
+        for _ in 0..2 * SELF_TEST_SAMPLES {
+            script_sample(bus, &[0x01, 0x00, 0x01, 0x00, 0x04, 0x00]);
+        }
+    }
+
+    /// The last value written to `register`
+    fn last_write(bus: &MockI2c, register: Register) -> Option<u8> {
+        let mut values = [0; 64];
+        match bus.writes_to(I2C_ADDRESS, register.addr(), &mut values) {
+            0 => None,
+            count => Some(values[count - 1]),
+        }
+    }
+
+    #[test]
+    fn sample_sign_extends_12_bits() {
+        assert_eq!(sample(0x7F, 0xF0), 2047);
+        assert_eq!(sample(0x80, 0x00), -2048);
+        assert_eq!(sample(0xFF, 0xF0), -1);
+        assert_eq!(sample(0x00, 0x10), 1);
+        assert_eq!(sample(0x00, 0x00), 0);
+    }
+
+    #[test]
+    fn sample_ignores_the_low_nibble() {
+        for low in 0..0x10 {
+            assert_eq!(sample(0x7F, 0xF0 | low), 2047);
+            assert_eq!(sample(0x80, low), -2048);
+            assert_eq!(sample(0xFF, 0xF0 | low), -1);
+            assert_eq!(sample(0x12, 0x30 | low), 0x123);
+        }
+    }
+
+    #[test]
+    fn milli_g_scales_with_the_range() {
+        let ranges = [(Range::G2, 2000), (Range::G4, 4000), (Range::G8, 8000)];
+        for &(range, full_scale) in &ranges {
+            assert_eq!(range.milli_g(-2048), -full_scale);
+            assert_eq!(range.milli_g(0), 0);
+            assert_eq!(range.milli_g(range.sensitivity() as i16), 1000);
+            assert_eq!(range.milli_g(-range.sensitivity() as i16), -1000);
+        }
+        // truncated towards zero
+        assert_eq!(Range::G2.milli_g(2047), 1999);
+        assert_eq!(Range::G4.milli_g(2047), 3998);
+        assert_eq!(Range::G8.milli_g(2047), 7996);
+        assert_eq!(Range::G8.milli_g(-1), -3);
+    }
+
+    #[test]
+    fn range_decodes_the_fs_bits() {
+        assert!(Range::from_bits(0b00) == Range::G2);
+        assert!(Range::from_bits(0b01) == Range::G4);
+        assert!(Range::from_bits(0b10) == Range::G8);
+        assert!(Range::from_bits(0b11) == Range::G8);
+        assert!(Range::from_bits(HPF_OUT | 0b01) == Range::G4);
+    }
+
+    #[test]
+    fn init_fails_without_sensor() {
+        let bus = MockI2c::new();
+        bus.set_present(I2C_ADDRESS, false);
+
+        assert_eq!(MMA8652FC(&bus).init(&Config::new()), Err(Error::NotPresent));
+        assert_eq!(bus.len(), 1);
+        assert_eq!(bus.transaction(0), Some(Transaction::Probe { slave: I2C_ADDRESS }));
+    }
+
+    #[test]
+    fn init_rejects_other_devices() {
+        let bus = MockI2c::new();
+        bus.script_read(&[0x2A]);
+
+        assert_eq!(MMA8652FC(&bus).init(&Config::new()), Err(Error::UnknownDevice(0x2A)));
+        assert_eq!(bus.transaction(1),
+                   Some(Transaction::Read {
+                       slave: I2C_ADDRESS,
+                       register: Register::WHO_AM_I.addr(),
+                       len: 1,
+                   }));
+        assert_eq!(bus.len(), 2);
+    }
+
+    #[test]
+    fn init_rejects_invalid_configs_before_touching_the_bus() {
+        let bus = MockI2c::new();
+        let config = Config::new().data_rate(DataRate::Hz1_56).auto_sleep(Some(AutoSleep {
+            rate: SleepRate::Hz50,
+            oversampling: Oversampling::Normal,
+            count: 1,
+        }));
+
+        assert_eq!(MMA8652FC(&bus).init(&config), Err(Error::InvalidConfig));
+        assert_eq!(bus.len(), 0);
+    }
+
+    #[test]
+    fn init_resets_tests_and_configures() {
+        let bus = MockI2c::new();
+        script_init(&bus);
+        let config = Config::new()
+            .range(Range::G4)
+            .high_pass(Some(HpfCutoff::Lowest))
+            .offsets([1, -2, 3]);
+
+        assert_eq!(MMA8652FC(&bus).init(&config), Ok(()));
+
+        // reset before and after the self-test, each followed by the wait
+        let mut ctrl_reg2 = [0; 16];
+        let writes = bus.writes_to(I2C_ADDRESS, Register::CTRL_REG2.addr(), &mut ctrl_reg2);
+        assert_eq!(ctrl_reg2[..writes].iter().filter(|&&value| value == RST).count(), 2);
+        let delays = (0..bus.len())
+            .filter(|&index| {
+                bus.transaction(index) == Some(Transaction::Delay { cycles: RESET_CYCLES })
+            })
+            .count();
+        assert_eq!(delays, 2);
+
+        // the self-test actuation is switched off again
+        assert!(ctrl_reg2[..writes].iter().any(|&value| value & ST != 0));
+        assert_eq!(last_write(&bus, Register::CTRL_REG2), Some(config.ctrl_reg2()));
+
+        assert_eq!(last_write(&bus, Register::OFF_X), Some(1));
+        assert_eq!(last_write(&bus, Register::OFF_Y), Some(-2i8 as u8));
+        assert_eq!(last_write(&bus, Register::OFF_Z), Some(3));
+        assert_eq!(last_write(&bus, Register::XYZ_DATA_CFG), Some(HPF_OUT | Range::G4 as u8));
+        assert_eq!(last_write(&bus, Register::HP_FILTER_CUTOFF),
+                   Some(HpfCutoff::Lowest as u8));
+
+        // activated last
+        assert_eq!(bus.transaction(bus.len() - 1),
+                   Some(Transaction::Write {
+                       slave: I2C_ADDRESS,
+                       register: Register::CTRL_REG1.addr(),
+                       value: config.ctrl_reg1(),
+                   }));
+        assert!(config.ctrl_reg1() & ACTIVE != 0);
+    }
+
+    #[test]
+    fn init_fails_if_the_self_test_doesnt_move_the_axes() {
+        let bus = MockI2c::new();
+        bus.script_read(&[DEVICE_ID]);
+        for _ in 0..3 * SELF_TEST_SAMPLES {
+            script_sample(&bus, &[0x01, 0x00, 0x01, 0x00, 0x04, 0x00]);
+        }
+
+        assert_eq!(MMA8652FC(&bus).init(&Config::new()), Err(Error::SelfTestFailed));
+        // left in standby
+        assert_eq!(last_write(&bus, Register::CTRL_REG1), Some(0));
+    }
+
+    #[test]
+    fn accel_reads_all_axes_in_one_burst() {
+        let bus = MockI2c::new();
+        bus.script_read(&[0x7F, 0xF0, 0x80, 0x00, 0x00, 0x10]);
+
+        let accel = MMA8652FC(&bus).accel();
+        assert_eq!((accel.x, accel.y, accel.z), (2047, -2048, 1));
+        assert_eq!(bus.len(), 1);
+        assert_eq!(bus.transaction(0),
+                   Some(Transaction::Read {
+                       slave: I2C_ADDRESS,
+                       register: Register::OUT_X_MSB.addr(),
+                       len: 6,
+                   }));
+    }
+
+    /// Scripts `CALIBRATION_SAMPLES` identical samples of `counts` at ±2 g
+    fn script_calibration(bus: &MockI2c, counts: [i16; 3]) {
+        let mut bytes = [0; 6];
+        for (bytes, counts) in bytes.chunks_mut(2).zip(counts.iter()) {
+            let left_justified = (counts << 4) as u16;
+            bytes[0] = (left_justified >> 8) as u8;
+            bytes[1] = left_justified as u8;
+        }
+        for _ in 0..CALIBRATION_SAMPLES {
+            script_sample(bus, &bytes);
+        }
+    }
+
+    #[test]
+    fn calibrate_corrects_to_one_g() {
+        let bus = MockI2c::new();
+        script_calibration(&bus, [20, -10, ONE_G as i16 + 40]);
+
+        assert_eq!(MMA8652FC(&bus).calibrate(), Ok([-10, 5, -20]));
+        // measured unfiltered at ±2 g and left in standby
+        assert_eq!(last_write(&bus, Register::XYZ_DATA_CFG), Some(Range::G2 as u8));
+        assert_eq!(last_write(&bus, Register::CTRL_REG1), Some(0));
+    }
+
+    #[test]
+    fn calibrate_upside_down() {
+        let bus = MockI2c::new();
+        script_calibration(&bus, [0, 0, -ONE_G as i16 - 40]);
+
+        assert_eq!(MMA8652FC(&bus).calibrate(), Ok([0, 0, 20]));
+    }
+
+    #[test]
+    fn calibrate_saturates_the_offsets() {
+        let bus = MockI2c::new();
+        script_calibration(&bus, [600, -600, ONE_G as i16]);
+
+        assert_eq!(MMA8652FC(&bus).calibrate(), Ok([-128, 127, 0]));
+    }
+
+    #[test]
+    fn offsets_are_applied_to_unfiltered_data() {
+        let bus = MockI2c::new();
+        script_init(&bus);
+        let config = Config::new().offsets([-10, 5, -20]);
+
+        assert_eq!(MMA8652FC(&bus).init(&config), Ok(()));
+        assert_eq!(last_write(&bus, Register::OFF_X), Some(-10i8 as u8));
+        assert_eq!(last_write(&bus, Register::OFF_Y), Some(5));
+        assert_eq!(last_write(&bus, Register::OFF_Z), Some(-20i8 as u8));
+        assert_eq!(last_write(&bus, Register::XYZ_DATA_CFG), Some(Range::G2 as u8));
+    }
+
+    #[test]
+    fn fifo_watermark_replaces_data_ready() {
+        let bus = MockI2c::new();
+        let ctrl_reg1 = ((DataRate::Hz100 as u8) << 3) | ACTIVE;
+        bus.script_read(&[ctrl_reg1, INT_DRDY | INT_FF_MT, INT_DRDY | INT_FF_MT]);
+
+        MMA8652FC(&bus).configure_fifo(&FifoConfig {
+            mode: FifoMode::Circular,
+            watermark: 16,
+            triggers: trigger::PULSE,
+        });
+        assert_eq!(bus.transaction(3),
+                   Some(Transaction::Write {
+                       slave: I2C_ADDRESS,
+                       register: Register::CTRL_REG1.addr(),
+                       value: ctrl_reg1 & !ACTIVE,
+                   }));
+        assert_eq!(last_write(&bus, Register::TRIG_CFG), Some(trigger::PULSE));
+        assert_eq!(last_write(&bus, Register::CTRL_REG4), Some(INT_FIFO | INT_FF_MT));
+        assert_eq!(last_write(&bus, Register::CTRL_REG5), Some(INT_FIFO | INT_FF_MT));
+        assert_eq!(last_write(&bus, Register::F_SETUP), Some(0x40 | 16));
+        assert_eq!(bus.transaction(bus.len() - 1),
+                   Some(Transaction::Write {
+                       slave: I2C_ADDRESS,
+                       register: Register::CTRL_REG1.addr(),
+                       value: ctrl_reg1,
+                   }));
+    }
+
+    #[test]
+    fn fifo_without_watermark_raises_no_interrupt() {
+        let bus = MockI2c::new();
+        bus.script_read(&[ACTIVE, INT_DRDY, INT_DRDY]);
+
+        MMA8652FC(&bus).configure_fifo(&FifoConfig {
+            mode: FifoMode::Fill,
+            watermark: 0,
+            triggers: 0,
+        });
+        assert_eq!(last_write(&bus, Register::CTRL_REG4), Some(0));
+        assert_eq!(last_write(&bus, Register::CTRL_REG5), Some(0));
+        assert_eq!(last_write(&bus, Register::F_SETUP), Some(0x80));
+    }
+
+    #[test]
+    fn disabling_the_fifo_restores_data_ready() {
+        let bus = MockI2c::new();
+        bus.script_read(&[ACTIVE, INT_FIFO, INT_FIFO]);
+
+        MMA8652FC(&bus).configure_fifo(&FifoConfig {
+            mode: FifoMode::Disabled,
+            watermark: 16,
+            triggers: 0,
+        });
+        assert_eq!(last_write(&bus, Register::CTRL_REG4), Some(INT_DRDY));
+        assert_eq!(last_write(&bus, Register::CTRL_REG5), Some(INT_DRDY));
+        assert_eq!(last_write(&bus, Register::F_SETUP), Some(0));
+        assert_eq!(last_write(&bus, Register::TRIG_CFG), None);
+    }
+
+    #[test]
+    fn drain_reads_the_fifo_in_one_burst() {
+        let bus = MockI2c::new();
+        // F_SETUP, XYZ_DATA_CFG and F_STATUS with 3 samples
+        bus.script_read(&[0x40 | 16, Range::G4 as u8, 0x80 | 3]);
+        bus.script_read(&[0x20, 0x00, 0x00, 0x00, 0xE0, 0x00]);
+        bus.script_read(&[0x00, 0x00, 0x20, 0x00, 0x00, 0x00]);
+        bus.script_read(&[0x00, 0x00, 0x00, 0x00, 0x20, 0x00]);
+
+        let mut samples = [Accel { x: 0, y: 0, z: 0 }; FIFO_SIZE];
+        assert_eq!(MMA8652FC(&bus).drain_fifo(&mut samples), 3);
+        assert_eq!((samples[0].x, samples[0].y, samples[0].z), (1000, 0, -1000));
+        assert_eq!((samples[1].x, samples[1].y, samples[1].z), (0, 1000, 0));
+        assert_eq!((samples[2].x, samples[2].y, samples[2].z), (0, 0, 1000));
+        assert_eq!(bus.transaction(3),
+                   Some(Transaction::Read {
+                       slave: I2C_ADDRESS,
+                       register: Register::OUT_X_MSB.addr(),
+                       len: 18,
+                   }));
+        assert_eq!(bus.len(), 4);
+    }
+
+    #[test]
+    fn drain_stops_at_the_buffer() {
+        let bus = MockI2c::new();
+        bus.script_read(&[0x40, Range::G2 as u8, FIFO_SIZE as u8]);
+
+        let mut samples = [Accel { x: 0, y: 0, z: 0 }; 2];
+        assert_eq!(MMA8652FC(&bus).drain_fifo(&mut samples), 2);
+        assert_eq!(bus.transaction(3),
+                   Some(Transaction::Read {
+                       slave: I2C_ADDRESS,
+                       register: Register::OUT_X_MSB.addr(),
+                       len: 12,
+                   }));
+    }
+
+    #[test]
+    fn drain_an_empty_fifo() {
+        let bus = MockI2c::new();
+        bus.script_read(&[0x40, Range::G2 as u8, 0]);
+
+        let mut samples = [Accel { x: 0, y: 0, z: 0 }; 4];
+        assert_eq!(MMA8652FC(&bus).drain_fifo(&mut samples), 0);
+        assert_eq!(bus.len(), 3);
+        assert_eq!(MMA8652FC(&bus).drain_fifo(&mut []), 0);
+        assert_eq!(bus.len(), 3);
+    }
+
+    #[test]
+    fn drain_without_fifo_reads_one_sample() {
+        let bus = MockI2c::new();
+        // F_SETUP disabled, XYZ_DATA_CFG, one sample
+        bus.script_read(&[0, Range::G2 as u8, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00]);
+
+        let mut samples = [Accel { x: 0, y: 0, z: 0 }; 4];
+        assert_eq!(MMA8652FC(&bus).drain_fifo(&mut samples), 1);
+        assert_eq!(samples[0].x, 1000);
+    }
+
+    #[test]
+    fn configure_double_taps() {
+        let bus = MockI2c::new();
+        let ctrl_reg1 = ((DataRate::Hz100 as u8) << 3) | ACTIVE;
+        bus.script_read(&[ctrl_reg1, INT_FIFO, INT_FIFO]);
+
+        MMA8652FC(&bus).configure_taps(&TapConfig::new());
+        assert_eq!(last_write(&bus, Register::PULSE_CFG), Some(PULSE_DOUBLE_XYZ | PULSE_ELE));
+        // 1000 mg in 63 mg steps
+        for &register in &[Register::PULSE_THS_X, Register::PULSE_THS_Y, Register::PULSE_THS_Z] {
+            assert_eq!(last_write(&bus, register), Some(15));
+        }
+        // 50 ms in 0.625 ms, 100 and 300 ms in 1.25 ms steps
+        assert_eq!(last_write(&bus, Register::PULSE_TLMT), Some(80));
+        assert_eq!(last_write(&bus, Register::PULSE_LTCY), Some(80));
+        assert_eq!(last_write(&bus, Register::PULSE_WIND), Some(240));
+        assert_eq!(last_write(&bus, Register::CTRL_REG4), Some(INT_FIFO | INT_PULSE));
+        assert_eq!(last_write(&bus, Register::CTRL_REG5), Some(INT_FIFO | INT_PULSE));
+        assert_eq!(bus.transaction(bus.len() - 1),
+                   Some(Transaction::Write {
+                       slave: I2C_ADDRESS,
+                       register: Register::CTRL_REG1.addr(),
+                       value: ctrl_reg1,
+                   }));
+    }
+
+    #[test]
+    fn disabling_taps_disables_the_interrupt() {
+        let bus = MockI2c::new();
+        bus.script_read(&[ACTIVE, INT_FIFO | INT_PULSE, INT_FIFO | INT_PULSE]);
+        let mut config = TapConfig::new();
+        config.double = false;
+
+        MMA8652FC(&bus).configure_taps(&config);
+        assert_eq!(last_write(&bus, Register::PULSE_CFG), Some(0));
+        assert_eq!(last_write(&bus, Register::CTRL_REG4), Some(INT_FIFO));
+        assert_eq!(last_write(&bus, Register::PULSE_THS_X), None);
+    }
+
+    #[test]
+    fn gesture_decodes_the_pulse_source() {
+        let bus = MockI2c::new();
+        bus.script_read(&[0, PULSE_DPE, PULSE_EA, PULSE_EA | PULSE_DPE]);
+        let sensor = MMA8652FC(&bus);
+
+        assert_eq!(sensor.gesture(), None);
+        assert_eq!(sensor.gesture(), None);
+        assert_eq!(sensor.gesture(), Some(Gesture::Tap));
+        assert_eq!(sensor.gesture(), Some(Gesture::DoubleTap));
+    }
+}
@@ -0,0 +1,218 @@
+//! I2C bus and flash doubles for exercising the drivers on the host
+//!
+//! Every register access is recorded in order. Reads are served from a
+//! script of bytes which is consumed front to back, unscripted reads return 0.
+//!
+//! The flash behaves like the STM32 flash as far as the journal can tell:
+//! programming a half-word which isn't erased fails and is counted instead,
+//! and a power cut drops every operation after it.
+
+use core::cell::{Cell, RefCell};
+use i2c::I2cBus;
+use journal::{Flash, PAGES, PAGE_LEN};
+
+const MAX_TRANSACTIONS: usize = 512;
+const MAX_SCRIPT: usize = 256;
+const ERASED: u16 = 0xffff;
+
+/// A single recorded bus access
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Transaction {
+    /// `value` was written to `register` of `slave`
+    Write { slave: u8, register: u8, value: u8 },
+    /// `len` bytes were read starting at `register` of `slave`
+    Read { slave: u8, register: u8, len: usize },
+    /// `slave` was probed
+    Probe { slave: u8 },
+    /// The driver waited `cycles` CPU cycles
+    Delay { cycles: u32 },
+}
+
+pub struct MockI2c {
+    log: RefCell<[Option<Transaction>; MAX_TRANSACTIONS]>,
+    logged: Cell<usize>,
+    script: RefCell<[u8; MAX_SCRIPT]>,
+    scripted: Cell<usize>,
+    consumed: Cell<usize>,
+    absent: RefCell<[bool; 128]>,
+}
+
+impl MockI2c {
+    pub fn new() -> Self {
+        MockI2c {
+            log: RefCell::new([None; MAX_TRANSACTIONS]),
+            logged: Cell::new(0),
+            script: RefCell::new([0; MAX_SCRIPT]),
+            scripted: Cell::new(0),
+            consumed: Cell::new(0),
+            absent: RefCell::new([false; 128]),
+        }
+    }
+
+    /// Makes `slave` (not) acknowledge probes, all slaves are present initially
+    pub fn set_present(&self, slave: u8, present: bool) -> &Self {
+        self.absent.borrow_mut()[(slave & 0x7f) as usize] = !present;
+        self
+    }
+
+    /// Appends `bytes` to the data served by subsequent reads
+    pub fn script_read(&self, bytes: &[u8]) -> &Self {
+        let start = self.scripted.get();
+        assert!(start + bytes.len() <= MAX_SCRIPT, "read script overflow");
+        self.script.borrow_mut()[start..start + bytes.len()].copy_from_slice(bytes);
+        self.scripted.set(start + bytes.len());
+        self
+    }
+
+    /// Number of recorded transactions
+    pub fn len(&self) -> usize {
+        self.logged.get()
+    }
+
+    /// Returns the `index`th recorded transaction
+    pub fn transaction(&self, index: usize) -> Option<Transaction> {
+        if index < self.logged.get() {
+            self.log.borrow()[index]
+        } else {
+            None
+        }
+    }
+
+    /// Copies the values written to `slave`/`register` into `values`
+    ///
+    /// Returns the number of matching writes, which may exceed `values.len()`.
+    pub fn writes_to(&self, slave: u8, register: u8, values: &mut [u8]) -> usize {
+        let mut count = 0;
+        for transaction in self.log.borrow()[..self.logged.get()].iter() {
+            if let Some(Transaction::Write { slave: s, register: r, value }) = *transaction {
+                if s == slave && r == register {
+                    if count < values.len() {
+                        values[count] = value;
+                    }
+                    count += 1;
+                }
+            }
+        }
+        count
+    }
+
+    /// Forgets all recorded transactions and the remaining read script
+    pub fn clear(&self) {
+        self.logged.set(0);
+        self.scripted.set(0);
+        self.consumed.set(0);
+    }
+
+    fn record(&self, transaction: Transaction) {
+        let index = self.logged.get();
+        assert!(index < MAX_TRANSACTIONS, "transaction log overflow");
+        self.log.borrow_mut()[index] = Some(transaction);
+        self.logged.set(index + 1);
+    }
+}
+
+impl I2cBus for MockI2c {
+    fn read(&self, slave: u8, register: u8, bytes: &mut [u8]) {
+        self.record(Transaction::Read {
+            slave,
+            register,
+            len: bytes.len(),
+        });
+
+        let script = self.script.borrow();
+        for byte in bytes.iter_mut() {
+            let position = self.consumed.get();
+            *byte = if position < self.scripted.get() {
+                self.consumed.set(position + 1);
+                script[position]
+            } else {
+                0
+            };
+        }
+    }
+
+    fn write(&self, slave: u8, register: u8, value: u8) {
+        self.record(Transaction::Write {
+            slave,
+            register,
+            value,
+        });
+    }
+
+    fn probe(&self, slave: u8) -> bool {
+        self.record(Transaction::Probe { slave });
+        !self.absent.borrow()[(slave & 0x7f) as usize]
+    }
+
+    fn delay(&self, cycles: u32) {
+        self.record(Transaction::Delay { cycles });
+    }
+}
+
+pub struct MockFlash {
+    pages: [[u16; PAGE_LEN]; PAGES],
+    /// Programs of half-words which weren't erased
+    pub violations: usize,
+    /// Erases and programs left until the power is cut
+    power: Option<usize>,
+}
+
+impl MockFlash {
+    /// Erased pages as shipped
+    pub fn new() -> Self {
+        MockFlash {
+            pages: [[ERASED; PAGE_LEN]; PAGES],
+            violations: 0,
+            power: None,
+        }
+    }
+
+    /// Drops every operation after the next `operations`
+    pub fn cut_power_after(&mut self, operations: usize) {
+        self.power = Some(operations);
+    }
+
+    /// Ends a power cut
+    pub fn restore_power(&mut self) {
+        self.power = None;
+    }
+
+    pub fn is_powered(&self) -> bool {
+        self.power != Some(0)
+    }
+
+    /// `true` if the operation happens before the power cut
+    fn operate(&mut self) -> bool {
+        match self.power {
+            Some(0) => false,
+            Some(ref mut left) => {
+                *left -= 1;
+                true
+            }
+            None => true,
+        }
+    }
+}
+
+impl Flash for MockFlash {
+    fn read(&self, page: usize, offset: usize) -> u16 {
+        self.pages[page][offset]
+    }
+
+    fn erase(&mut self, page: usize) {
+        if self.operate() {
+            self.pages[page] = [ERASED; PAGE_LEN];
+        }
+    }
+
+    fn program(&mut self, page: usize, offset: usize, value: u16) {
+        if !self.operate() {
+            return;
+        }
+        if self.pages[page][offset] == ERASED {
+            self.pages[page][offset] = value;
+        } else {
+            self.violations += 1;
+        }
+    }
+}
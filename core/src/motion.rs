@@ -0,0 +1,234 @@
+//! Motion analysis of accelerometer samples
+//!
+//! The hardware motion interrupt proved unreliable, so motion is detected
+//! in software from the sample stream instead. The magnitude of each sample
+//! is high pass filtered to remove gravity and slow drift, the absolute
+//! result is averaged over a sliding window and compared to a threshold
+//! which follows the noise level observed while the iron rests.
+
+use accel::Accel;
+
+/// Samples in the sliding window, 160 ms at 100 Hz
+const WINDOW: usize = 16;
+/// Weight of a new sample in the magnitude baseline as power of two, 1/16
+const BASELINE_SHIFT: u32 = 4;
+/// Weight of a new window average in the noise estimate as power of two
+const NOISE_SHIFT: u32 = 6;
+/// Fractional bits of the baseline and noise estimate
+const FRACTION_BITS: u32 = 4;
+
+/// Least and most sensitive setting
+pub const SENSITIVITY_MIN: u8 = 1;
+pub const SENSITIVITY_MAX: u8 = 9;
+/// Sensitivity of new settings, the start threshold stays 4x above the noise
+/// on the stand and the release threshold half the activity of holding
+pub const SENSITIVITY_DEFAULT: u8 = 6;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MotionEvent {
+    /// The iron started moving
+    Started,
+    /// The iron has been still for a whole window
+    Stopped,
+}
+
+pub struct MotionDetector {
+    /// Low passed magnitude in milli-g scaled by `1 << FRACTION_BITS`
+    baseline: i32,
+    primed: bool,
+    /// Absolute high passed magnitudes
+    window: [u16; WINDOW],
+    index: usize,
+    sum: u32,
+    /// Average window activity while still, scaled by `1 << FRACTION_BITS`
+    noise: u32,
+    moving: bool,
+    /// Consecutive samples below the release threshold
+    still: usize,
+    sensitivity: u8,
+}
+
+impl MotionDetector {
+    pub const fn new(sensitivity: u8) -> Self {
+        MotionDetector {
+            baseline: 0,
+            primed: false,
+            window: [0; WINDOW],
+            index: 0,
+            sum: 0,
+            noise: 0,
+            moving: false,
+            still: 0,
+            sensitivity: clamp_sensitivity(sensitivity),
+        }
+    }
+
+    /// Sets the sensitivity between `SENSITIVITY_MIN` and `SENSITIVITY_MAX`
+    pub fn set_sensitivity(&mut self, sensitivity: u8) {
+        self.sensitivity = clamp_sensitivity(sensitivity);
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.moving
+    }
+
+    /// Current window activity in milli-g
+    pub fn activity(&self) -> u32 {
+        self.sum / WINDOW as u32
+    }
+
+    /// Activity in milli-g starting a motion
+    ///
+    /// The threshold is a multiple of the noise level, 2x at the highest and
+    /// 6x at the lowest sensitivity, but never below a floor of 2 to 18 mg.
+    pub fn threshold(&self) -> u32 {
+        let insensitivity = u32::from(SENSITIVITY_MAX - self.sensitivity);
+        let floor = 2 + 2 * insensitivity;
+        // noise multiplier in quarters
+        let multiplier = 8 + 2 * insensitivity;
+        let adaptive = (self.noise * multiplier / 4) >> FRACTION_BITS;
+
+        adaptive.max(floor)
+    }
+
+    /// Feeds samples in milli-g, oldest first, and returns the last motion
+    /// event among them
+    pub fn update(&mut self, samples: &[Accel]) -> Option<MotionEvent> {
+        let mut event = None;
+
+        for sample in samples {
+            if let Some(e) = self.update_sample(sample) {
+                event = Some(e);
+            }
+        }
+
+        event
+    }
+
+    #[cfg_attr(clippy, allow(clippy::legacy_numeric_constants))]
+    fn update_sample(&mut self, sample: &Accel) -> Option<MotionEvent> {
+        let magnitude = magnitude(sample) as i32;
+
+        if !self.primed {
+            self.baseline = magnitude << FRACTION_BITS;
+            self.primed = true;
+        }
+
+        let delta = (magnitude << FRACTION_BITS) - self.baseline;
+        self.baseline += delta >> BASELINE_SHIFT;
+
+        let value = (delta.abs() >> FRACTION_BITS).min(u16::max_value() as i32) as u16;
+        self.sum = self.sum - u32::from(self.window[self.index]) + u32::from(value);
+        self.window[self.index] = value;
+        self.index = (self.index + 1) % WINDOW;
+
+        let activity = self.activity();
+        let threshold = self.threshold();
+
+        if self.moving {
+            // release with hysteresis once the window calmed down
+            if activity < threshold / 2 {
+                self.still += 1;
+                if self.still >= WINDOW {
+                    self.moving = false;
+                    self.still = 0;
+                    return Some(MotionEvent::Stopped);
+                }
+            } else {
+                self.still = 0;
+            }
+            None
+        } else if activity >= threshold {
+            self.moving = true;
+            self.still = 0;
+            Some(MotionEvent::Started)
+        } else {
+            // learn the noise floor only while resting
+            let scaled = activity << FRACTION_BITS;
+            if scaled > self.noise {
+                self.noise += (scaled - self.noise) >> NOISE_SHIFT;
+            } else {
+                self.noise -= (self.noise - scaled) >> NOISE_SHIFT;
+            }
+            None
+        }
+    }
+}
+
+/// Limits `sensitivity` to `SENSITIVITY_MIN..=SENSITIVITY_MAX`, indexing
+/// instead of branching as the `const fn` of the firmware compiler can't
+const fn clamp_sensitivity(sensitivity: u8) -> u8 {
+    [[sensitivity, SENSITIVITY_MAX][(sensitivity > SENSITIVITY_MAX) as usize],
+     SENSITIVITY_MIN][(sensitivity < SENSITIVITY_MIN) as usize]
+}
+
+/// Euclidean length of a sample in milli-g
+fn magnitude(sample: &Accel) -> u32 {
+    let (x, y, z) = (sample.x as i32, sample.y as i32, sample.z as i32);
+    isqrt((x * x + y * y + z * z) as u32)
+}
+
+/// Integer square root, rounded down
+fn isqrt(value: u32) -> u32 {
+    let mut remainder = value;
+    let mut root = 0;
+    let mut bit = 1 << 30;
+
+    while bit > value {
+        bit >>= 2;
+    }
+
+    while bit != 0 {
+        if remainder >= root + bit {
+            remainder -= root + bit;
+            root = (root >> 1) + bit;
+        } else {
+            root >>= 1;
+        }
+        bit >>= 2;
+    }
+
+    root
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn threshold_floor_follows_the_sensitivity() {
+        // nothing learned yet, the floor applies
+        let floors: [u32; 9] = [18, 16, 14, 12, 10, 8, 6, 4, 2];
+        for (sensitivity, &floor) in (SENSITIVITY_MIN..SENSITIVITY_MAX + 1).zip(floors.iter()) {
+            assert_eq!(MotionDetector::new(sensitivity).threshold(), floor);
+        }
+    }
+
+    #[test]
+    fn threshold_scales_the_noise() {
+        let mut detector = MotionDetector::new(SENSITIVITY_MAX);
+        detector.noise = 10 << FRACTION_BITS;
+        assert_eq!(detector.threshold(), 20);
+        detector.set_sensitivity(SENSITIVITY_MIN);
+        assert_eq!(detector.threshold(), 60);
+    }
+
+    #[test]
+    fn clamp_sensitivity_limits_to_the_range() {
+        assert_eq!(clamp_sensitivity(0), SENSITIVITY_MIN);
+        assert_eq!(clamp_sensitivity(SENSITIVITY_MIN), SENSITIVITY_MIN);
+        assert_eq!(clamp_sensitivity(5), 5);
+        assert_eq!(clamp_sensitivity(SENSITIVITY_MAX), SENSITIVITY_MAX);
+        assert_eq!(clamp_sensitivity(SENSITIVITY_MAX + 1), SENSITIVITY_MAX);
+        assert_eq!(clamp_sensitivity(255), SENSITIVITY_MAX);
+    }
+
+    #[test]
+    fn isqrt_rounds_down() {
+        for value in 0..10_000 {
+            let root = isqrt(value);
+            assert!(root * root <= value && (root + 1) * (root + 1) > value, "{}", value);
+        }
+        assert_eq!(isqrt(u32::MAX), 65535);
+    }
+}
@@ -0,0 +1,117 @@
+//! User settings and their encoding for persistent storage
+//!
+//! Settings are stored as half-words, which is what the flash of the
+//! STM32F103 is programmed in.
+
+use motion;
+
+/// Marks a programmed settings page
+const MAGIC: u16 = 0x7510;
+/// Incremented whenever the layout changes, older layouts are discarded
+const VERSION: u16 = 2;
+/// Half-words of the encoded settings including magic, version and checksum
+pub const ENCODED_LEN: usize = 6;
+
+#[derive(Clone, Copy)]
+pub struct Settings {
+    /// Accelerometer offset correction register values for X, Y and Z
+    pub accel_offset: [i8; 3],
+    /// Motion detection sensitivity from 1 (least) to 9 (most sensitive)
+    pub motion_sensitivity: u8,
+}
+
+impl Settings {
+    #[cfg_attr(clippy, allow(clippy::new_without_default))]
+    pub const fn new() -> Self {
+        Settings {
+            accel_offset: [0; 3],
+            motion_sensitivity: motion::SENSITIVITY_DEFAULT,
+        }
+    }
+
+    pub fn encode(&self) -> [u16; ENCODED_LEN] {
+        let offset = self.accel_offset;
+        let mut data = [
+            MAGIC,
+            VERSION,
+            u16::from(offset[0] as u8) | (u16::from(offset[1] as u8) << 8),
+            u16::from(offset[2] as u8) | (u16::from(self.motion_sensitivity) << 8),
+            0,
+            0,
+        ];
+        data[ENCODED_LEN - 1] = checksum(&data[..ENCODED_LEN - 1]);
+        data
+    }
+
+    /// Decodes stored settings, `None` if they are erased, corrupted or of
+    /// an older version
+    #[cfg_attr(clippy, allow(clippy::manual_range_contains))]
+    pub fn decode(data: &[u16; ENCODED_LEN]) -> Option<Self> {
+        if data[0] != MAGIC || data[1] != VERSION
+            || data[ENCODED_LEN - 1] != checksum(&data[..ENCODED_LEN - 1])
+        {
+            return None;
+        }
+
+        let motion_sensitivity = (data[3] >> 8) as u8;
+        if motion_sensitivity < motion::SENSITIVITY_MIN || motion_sensitivity > motion::SENSITIVITY_MAX {
+            return None;
+        }
+
+        Some(Settings {
+            accel_offset: [data[2] as u8 as i8, (data[2] >> 8) as u8 as i8, data[3] as u8 as i8],
+            motion_sensitivity,
+        })
+    }
+}
+
+/// Sum of all half-words, inverted so an all zero page doesn't validate
+pub fn checksum(data: &[u16]) -> u16 {
+    !data.iter().fold(0u16, |sum, half_word| sum.wrapping_add(*half_word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let settings = Settings {
+            accel_offset: [-128, 5, 127],
+            motion_sensitivity: motion::SENSITIVITY_MAX,
+        };
+        let decoded = Settings::decode(&settings.encode()).unwrap();
+        assert_eq!(decoded.accel_offset, [-128, 5, 127]);
+        assert_eq!(decoded.motion_sensitivity, motion::SENSITIVITY_MAX);
+    }
+
+    #[test]
+    fn rejects_erased_and_corrupted_pages() {
+        assert!(Settings::decode(&[0xFFFF; ENCODED_LEN]).is_none());
+        assert!(Settings::decode(&[0; ENCODED_LEN]).is_none());
+
+        let encoded = Settings::new().encode();
+        for index in 0..ENCODED_LEN {
+            let mut data = encoded;
+            data[index] ^= 0x0100;
+            assert!(Settings::decode(&data).is_none(), "half-word {}", index);
+        }
+    }
+
+    #[test]
+    fn rejects_older_versions() {
+        let mut data = Settings::new().encode();
+        data[1] = VERSION - 1;
+        data[ENCODED_LEN - 1] = checksum(&data[..ENCODED_LEN - 1]);
+        assert!(Settings::decode(&data).is_none());
+    }
+
+    #[test]
+    fn rejects_sensitivity_out_of_range() {
+        for &sensitivity in &[motion::SENSITIVITY_MIN - 1, motion::SENSITIVITY_MAX + 1] {
+            let mut settings = Settings::new();
+            settings.motion_sensitivity = sensitivity;
+            assert!(Settings::decode(&settings.encode()).is_none());
+        }
+    }
+}
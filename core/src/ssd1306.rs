@@ -0,0 +1,199 @@
+use i2c::I2cBus;
+use {font5x7, format};
+use ui::{self, Framebuffer};
+
+// Registers
+const COMMAND_MODE: u8 = 0x80;
+const DATA_MODE: u8 = 0x40;
+// Commands
+const CHARGE_PUMP_SETTING: u8 = 0x8d;
+const CHARGE_PUMP_ENABLE: u8 = 0x14;
+
+const DISPLAY_OFF: u8 = 0xAE;
+const DISPLAY_ON: u8 = 0xAF;
+// page addressing, the column nibbles and the page are added to the command
+const LOWER_COLUMN: u8 = 0x00;
+const HIGHER_COLUMN: u8 = 0x10;
+const PAGE: u8 = 0xB0;
+/// The 96 visible columns start at column 32 of the controller
+const FIRST_COLUMN: u8 = 32;
+
+
+//0x80, 0xAE,/*Display off*/
+//0x80, 0xD5,/*Set display clock divide ratio / osc freq*/
+//0x80, 0x52,/*Unknown*/
+//0x80, 0xA8,/*Set Multiplex Ratio*/
+//0x80, 0x0F, /*16 == max brightness,39==dimmest*/
+//0x80, 0xC0,/*Set COM Scan direction*/
+//0x80, 0xD3,/*Set Display offset*/
+//0x80, 0x00,/*0 Offset*/
+//0x80, 0x40,/*Set Display start line to 0*/
+//0x80, 0xA0,/*Set Segment remap to normal*/
+//0x80, 0x8D,/*Unknown*/
+//0x80, 0x14,/*Unknown*/
+//0x80, 0xDA,/*Set VCOM Pins hardware config*/
+//0x80, 0x02,/*Combination 2*/
+//0x80, 0x81,/*Contrast*/
+//0x80, 0x33,/*51*/
+//0x80, 0xD9,/*Set pre-charge period*/
+//0x80, 0xF1,/**/
+//0x80, 0xDB,/*Adjust VCOMH regulator ouput*/
+//0x80, 0x30,/*Unknown*/
+//0x80, 0xA4,/*Enable the display GDDR*/
+//0x80, 0XA6,/*Normal display*/
+//0x80, 0xAF /*Dispaly on*/
+
+pub struct SSD1306<'a, B: 'a + I2cBus>(pub u8, pub &'a B);
+
+impl<'a, B: I2cBus> SSD1306<'a, B> {
+    pub fn init(&self) {
+        self.send_command(CHARGE_PUMP_SETTING)
+            .send_command(CHARGE_PUMP_ENABLE)
+            .send_command(DISPLAY_OFF)
+
+//            .send_command(0xD5)
+//            .send_command(0x52)
+//            .send_command(0xA8)
+//            .send_command(0x0F)
+            .send_command(0xC0)
+            .send_command(0xD3)
+            .send_command(0x00)
+//            .send_command(0x02)
+            .send_command(0x40)
+            .send_command(0xA0)
+            .send_command(0x8D)
+            .send_command(0x14)
+            .send_command(0xDA)
+            .send_command(0x02)
+//            .send_command(0x81)
+
+//            .send_command(PAGE_ADDRESSING)
+//            .send_command(0xD9)
+//            .send_command(0x33)
+//            .send_command(0xD9)
+//            .send_command(0xF1)
+            .send_command(0xDB)
+            .send_command(0x30)
+            .send_command(0xA4)
+            .send_command(0xA6)
+            .send_command(DISPLAY_ON);
+    }
+
+    pub fn send_command(&self, command: u8) -> &Self {
+        self.1.write(self.0, COMMAND_MODE, command);
+        self
+    }
+
+    pub fn send_data(&self, data: u8) -> &Self {
+        self.1.write(self.0, DATA_MODE, data);
+        self
+    }
+
+    pub fn print_number(&self, x: u8, y: u8, number: i16) -> &Self {
+        let mut buffer = [b' '; 6];
+        self.print(x, y, format::decimal(i32::from(number), &mut buffer))
+    }
+
+    /// Prints `number` as two hex digits
+    pub fn print_hex(&self, x: u8, y: u8, number: u8) -> &Self {
+        let mut buffer = [b'0'; 2];
+        self.print(x, y, format::hex(u32::from(number), &mut buffer))
+    }
+
+    /// Copies the whole framebuffer to the display
+    pub fn flush(&self, fb: &Framebuffer) -> &Self {
+        for page in 0..ui::PAGES {
+            self.set_position(0, page as u8);
+            for column in fb.page(page) {
+                self.send_data(*column);
+            }
+        }
+        self
+    }
+
+    /// Moves the write position to `column` of `page`
+    fn set_position(&self, column: u8, page: u8) -> &Self {
+        let column = column + FIRST_COLUMN;
+        self.send_command(LOWER_COLUMN | (column & 0x0f))
+            .send_command(HIGHER_COLUMN | (column >> 4))
+            .send_command(PAGE | page)
+    }
+
+    #[cfg_attr(clippy, allow(clippy::manual_range_contains))]
+    pub fn print(&self, x: u8, y: u8, text: &str) -> &Self {
+        self.set_position(6 * x, y);
+
+        for byte in text.as_bytes().iter().cloned() {
+            // check if byte is printable
+            // TODO let the font decide
+            if byte >= 0x20 && byte < 0x20 + 0x60 {
+                for column in 0..5 {
+                    self.send_data(font5x7::FONT_5X7[(byte - 0x20) as usize * 5 + column]);
+                }
+                // 1 pixel space between chars
+                self.send_data(0x00);
+            }
+        }
+
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mock::{MockI2c, Transaction};
+
+    const ADDRESS: u8 = 0x3C;
+
+    #[test]
+    fn init_sends_the_command_sequence() {
+        let bus = MockI2c::new();
+        SSD1306(ADDRESS, &bus).init();
+
+        let expected = [CHARGE_PUMP_SETTING, CHARGE_PUMP_ENABLE, DISPLAY_OFF, 0xC0, 0xD3, 0x00,
+                        0x40, 0xA0, 0x8D, 0x14, 0xDA, 0x02, 0xDB, 0x30, 0xA4, 0xA6, DISPLAY_ON];
+        let mut commands = [0; 32];
+        assert_eq!(bus.writes_to(ADDRESS, COMMAND_MODE, &mut commands), expected.len());
+        assert_eq!(&commands[..expected.len()], &expected[..]);
+        assert_eq!(bus.len(), expected.len());
+    }
+
+    #[test]
+    fn print_addresses_the_cell_and_sends_the_glyphs() {
+        let bus = MockI2c::new();
+        let oled = SSD1306(ADDRESS, &bus);
+        oled.init();
+        bus.clear();
+        oled.print(1, 2, "A!");
+
+        // column 6 * 1 + 32 = 38 split into nibbles, then page 2
+        let mut commands = [0; 3];
+        assert_eq!(bus.writes_to(ADDRESS, COMMAND_MODE, &mut commands), 3);
+        assert_eq!(commands, [0x06, 0x12, 0xB2]);
+        assert_eq!(bus.transaction(0),
+                   Some(Transaction::Write {
+                       slave: ADDRESS,
+                       register: COMMAND_MODE,
+                       value: 0x06,
+                   }));
+
+        let a = (b'A' - 0x20) as usize * 5;
+        let bang = (b'!' - 0x20) as usize * 5;
+        let mut expected = [0; 12];
+        expected[..5].copy_from_slice(&font5x7::FONT_5X7[a..a + 5]);
+        expected[6..11].copy_from_slice(&font5x7::FONT_5X7[bang..bang + 5]);
+        let mut data = [0xFF; 12];
+        assert_eq!(bus.writes_to(ADDRESS, DATA_MODE, &mut data), 12);
+        assert_eq!(data, expected);
+    }
+
+    #[test]
+    fn print_skips_unprintable_bytes() {
+        let bus = MockI2c::new();
+        SSD1306(ADDRESS, &bus).print(0, 0, "\n\u{e9}");
+
+        assert_eq!(bus.writes_to(ADDRESS, DATA_MODE, &mut []), 0);
+        assert_eq!(bus.len(), 3);
+    }
+}
@@ -0,0 +1,953 @@
+//! Hierarchical state machine of the user interface
+//!
+//! The states visible to the user are the leaves of a small hierarchy:
+//!
+//! ```text
+//! Top
+//! ├── Off                    heater off
+//! │   ├── Idle
+//! │   ├── Cooling
+//! │   ├── Thermometer
+//! │   └── Menu               menu timeout running
+//! │       ├── Config(Calibrate)
+//! │       ├── Config(Save)
+//! │       └── Config(Events)
+//! └── Heating                heater regulated, AB stops
+//!     ├── Active             falls asleep without activity
+//!     │   ├── Soldering
+//!     │   ├── Boost
+//!     │   └── TemperatureControl
+//!     └── Sleep
+//! ```
+//!
+//! Events are first turned into a `Trigger`. The `TRANSITIONS` table is
+//! searched for a row of the current state, then of its parents up to `Top`,
+//! the first row whose guard passes is taken. Triggers without a row are
+//! ignored. Taking a row exits the states up to the common parent of source
+//! and target and enters the states down to the target, running their exit
+//! and entry actions, and records the transition in the `TransitionLog`.
+//! Rows without target only run their action.
+
+use accel::{Accel, Gesture};
+use event::Event;
+use input::{Key, KeyAction, KeyEvent};
+use motion::MotionEvent;
+use time::TimerId;
+
+/// Seconds without activity until the iron goes to sleep
+pub const SLEEP_TIMEOUT: u32 = 60;
+/// Seconds until boost falls back to the normal setpoint
+pub const BOOST_TIMEOUT: u32 = 30;
+/// Pending actions kept until they are taken, the most a single event can
+/// queue: the menu timeout restart of a key, the exit action of `Heating`,
+/// the entry actions of `Menu` and `Config(Events)` and the transition's own
+const ACTIONS: usize = 5;
+/// Transitions kept in the log
+const LOG_SIZE: usize = 16;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConfigPage {
+    Calibrate,
+    Save,
+    /// Browses the journal of events
+    Events,
+}
+
+/// Work requested by the state machine which it can't do itself
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    /// Calibrate the accelerometer offsets and save them
+    CalibrateAccel,
+    SaveSettings,
+    /// Turn the heater off right away instead of with the next sample
+    HeaterOff,
+    /// (Re)start the `MenuTimeout` timer
+    StartMenuTimeout,
+    /// Show the newest record of the journal
+    ShowEvents,
+    /// Show the record before the one shown
+    OlderEvent,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum State {
+    Idle,
+    Soldering,
+    Boost,
+    TemperatureControl,
+    Config(ConfigPage),
+    Sleep,
+    Cooling,
+    Thermometer,
+}
+
+/// Every state, in the order of the declaration
+pub const STATES: [State; 10] = [
+    State::Idle,
+    State::Soldering,
+    State::Boost,
+    State::TemperatureControl,
+    State::Config(ConfigPage::Calibrate),
+    State::Config(ConfigPage::Save),
+    State::Config(ConfigPage::Events),
+    State::Sleep,
+    State::Cooling,
+    State::Thermometer,
+];
+
+/// What the transitions react to, derived from an `Event`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Trigger {
+    Click(Key),
+    LongPress(Key),
+    Gesture(Gesture),
+    Motion(MotionEvent),
+    /// One second passed
+    Second,
+    MenuTimeout,
+}
+
+/// Every trigger
+pub const TRIGGERS: [Trigger; 12] = [
+    Trigger::Click(Key::A),
+    Trigger::Click(Key::B),
+    Trigger::Click(Key::AB),
+    Trigger::LongPress(Key::A),
+    Trigger::LongPress(Key::B),
+    Trigger::LongPress(Key::AB),
+    Trigger::Gesture(Gesture::Tap),
+    Trigger::Gesture(Gesture::DoubleTap),
+    Trigger::Motion(MotionEvent::Started),
+    Trigger::Motion(MotionEvent::Stopped),
+    Trigger::Second,
+    Trigger::MenuTimeout,
+];
+
+/// States of the hierarchy, `Leaf` are the ones visible to the user
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Node {
+    Top,
+    Off,
+    Menu,
+    Heating,
+    Active,
+    Leaf(State),
+}
+
+impl Node {
+    fn parent(&self) -> Option<Node> {
+        use state::State::*;
+
+        match *self {
+            Node::Top => None,
+            Node::Off | Node::Heating => Some(Node::Top),
+            Node::Menu => Some(Node::Off),
+            Node::Active => Some(Node::Heating),
+            Node::Leaf(Idle) | Node::Leaf(Cooling) | Node::Leaf(Thermometer) => Some(Node::Off),
+            Node::Leaf(Config(_)) => Some(Node::Menu),
+            Node::Leaf(Soldering) | Node::Leaf(Boost) | Node::Leaf(TemperatureControl) => {
+                Some(Node::Active)
+            }
+            Node::Leaf(Sleep) => Some(Node::Heating),
+        }
+    }
+
+    fn depth(&self) -> usize {
+        let mut depth = 0;
+        let mut node = *self;
+        while let Some(parent) = node.parent() {
+            depth += 1;
+            node = parent;
+        }
+        depth
+    }
+
+    fn contains(&self, node: Node) -> bool {
+        let mut current = Some(node);
+        while let Some(n) = current {
+            if n == *self {
+                return true;
+            }
+            current = n.parent();
+        }
+        false
+    }
+}
+
+type Guard = fn(&StateMachine) -> bool;
+
+struct Transition {
+    source: Node,
+    trigger: Trigger,
+    guard: Option<Guard>,
+    /// `None` for internal transitions which only run `action`
+    target: Option<State>,
+    action: Option<Action>,
+}
+
+const fn row(source: Node, trigger: Trigger, guard: Option<Guard>, target: Option<State>,
+              action: Option<Action>) -> Transition {
+    Transition {
+        source,
+        trigger,
+        guard,
+        target,
+        action,
+    }
+}
+
+fn sleep_due(machine: &StateMachine) -> bool {
+    machine.idle_seconds >= SLEEP_TIMEOUT
+}
+
+fn boost_over(machine: &StateMachine) -> bool {
+    machine.state_seconds >= BOOST_TIMEOUT
+}
+
+/// Searched from top to bottom, children before parents
+static TRANSITIONS: [Transition; 30] = [
+    row(Node::Leaf(State::Idle), Trigger::Click(Key::A), None, Some(State::Soldering), None),
+    row(Node::Leaf(State::Idle), Trigger::Click(Key::B), None, Some(State::Thermometer), None),
+    row(Node::Leaf(State::Cooling), Trigger::Click(Key::A), None, Some(State::Soldering), None),
+    row(Node::Leaf(State::Cooling), Trigger::Click(Key::B), None, Some(State::Idle), None),
+    row(Node::Leaf(State::Thermometer), Trigger::Click(Key::A), None, Some(State::Idle), None),
+    row(Node::Leaf(State::Thermometer), Trigger::Click(Key::B), None,
+        Some(State::Config(ConfigPage::Calibrate)), None),
+
+    row(Node::Leaf(State::Config(ConfigPage::Calibrate)), Trigger::Click(Key::A), None,
+        None, Some(Action::CalibrateAccel)),
+    row(Node::Leaf(State::Config(ConfigPage::Calibrate)), Trigger::Click(Key::B), None,
+        Some(State::Config(ConfigPage::Save)), None),
+    row(Node::Leaf(State::Config(ConfigPage::Save)), Trigger::Click(Key::A), None,
+        Some(State::Idle), Some(Action::SaveSettings)),
+    row(Node::Leaf(State::Config(ConfigPage::Save)), Trigger::Click(Key::B), None,
+        Some(State::Config(ConfigPage::Events)), None),
+    row(Node::Leaf(State::Config(ConfigPage::Events)), Trigger::Click(Key::A), None,
+        None, Some(Action::OlderEvent)),
+    row(Node::Leaf(State::Config(ConfigPage::Events)), Trigger::Click(Key::B), None,
+        Some(State::Idle), None),
+    row(Node::Menu, Trigger::LongPress(Key::AB), None, Some(State::Idle), None),
+    // leaves the menu without saving
+    row(Node::Menu, Trigger::MenuTimeout, None, Some(State::Idle), None),
+
+    row(Node::Leaf(State::Soldering), Trigger::Click(Key::A), None,
+        Some(State::TemperatureControl), None),
+    row(Node::Leaf(State::Soldering), Trigger::Click(Key::B), None,
+        Some(State::TemperatureControl), None),
+    row(Node::Leaf(State::Soldering), Trigger::Gesture(Gesture::DoubleTap), None,
+        Some(State::Boost), None),
+    row(Node::Leaf(State::TemperatureControl), Trigger::Click(Key::A), None,
+        Some(State::Soldering), None),
+    row(Node::Leaf(State::TemperatureControl), Trigger::Click(Key::B), None,
+        Some(State::Soldering), None),
+    row(Node::Leaf(State::Boost), Trigger::Click(Key::A), None, Some(State::Soldering), None),
+    row(Node::Leaf(State::Boost), Trigger::Click(Key::B), None, Some(State::Soldering), None),
+    row(Node::Leaf(State::Boost), Trigger::Gesture(Gesture::DoubleTap), None,
+        Some(State::Soldering), None),
+    row(Node::Leaf(State::Boost), Trigger::Second, Some(boost_over as Guard),
+        Some(State::Soldering), None),
+    row(Node::Active, Trigger::Second, Some(sleep_due as Guard), Some(State::Sleep), None),
+
+    row(Node::Leaf(State::Sleep), Trigger::Click(Key::A), None, Some(State::Soldering), None),
+    row(Node::Leaf(State::Sleep), Trigger::Click(Key::B), None, Some(State::Soldering), None),
+    row(Node::Leaf(State::Sleep), Trigger::Gesture(Gesture::DoubleTap), None,
+        Some(State::Soldering), None),
+    row(Node::Leaf(State::Sleep), Trigger::Motion(MotionEvent::Started), None,
+        Some(State::Soldering), None),
+    row(Node::Heating, Trigger::Click(Key::AB), None, Some(State::Idle), None),
+    row(Node::Heating, Trigger::LongPress(Key::AB), None, Some(State::Idle), None),
+];
+
+/// Runs the entry action of `node`
+fn enter(machine: &mut StateMachine, node: Node) {
+    match node {
+        Node::Heating => machine.idle_seconds = 0,
+        Node::Menu => machine.push_action(Action::StartMenuTimeout),
+        Node::Leaf(State::Config(ConfigPage::Events)) => machine.push_action(Action::ShowEvents),
+        _ => {}
+    }
+}
+
+/// Runs the exit action of `node`
+fn exit(machine: &mut StateMachine, node: Node) {
+    if let Node::Heating = node {
+        machine.push_action(Action::HeaterOff);
+    }
+}
+
+/// A transition taken at `seconds` after boot
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LogEntry {
+    pub seconds: u32,
+    pub from: State,
+    pub to: State,
+    pub trigger: Trigger,
+}
+
+/// The last `LOG_SIZE` transitions, the oldest are overwritten
+pub struct TransitionLog {
+    entries: [LogEntry; LOG_SIZE],
+    head: usize,
+    len: usize,
+}
+
+impl TransitionLog {
+    const fn new() -> Self {
+        TransitionLog {
+            entries: [LogEntry {
+                seconds: 0,
+                from: State::Idle,
+                to: State::Idle,
+                trigger: Trigger::Second,
+            }; LOG_SIZE],
+            head: 0,
+            len: 0,
+        }
+    }
+
+    fn push(&mut self, entry: LogEntry) {
+        let tail = (self.head + self.len) % LOG_SIZE;
+        self.entries[tail] = entry;
+        if self.len == LOG_SIZE {
+            self.head = (self.head + 1) % LOG_SIZE;
+        } else {
+            self.len += 1;
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Iterates the entries, oldest first
+    pub fn iter<'a>(&'a self) -> LogIter<'a> {
+        LogIter { log: self, index: 0 }
+    }
+}
+
+pub struct LogIter<'a> {
+    log: &'a TransitionLog,
+    index: usize,
+}
+
+impl<'a> Iterator for LogIter<'a> {
+    type Item = LogEntry;
+
+    fn next(&mut self) -> Option<LogEntry> {
+        if self.index == self.log.len {
+            return None;
+        }
+
+        let entry = self.log.entries[(self.log.head + self.index) % LOG_SIZE];
+        self.index += 1;
+        Some(entry)
+    }
+}
+
+pub struct StateMachine {
+    accel: Accel,
+    accel_present: bool,
+    moving: bool,
+    idle_seconds: u32,
+    state_seconds: u32,
+    uptime: u32,
+    state: State,
+    actions: [Action; ACTIONS],
+    action_count: usize,
+    log: TransitionLog,
+}
+
+impl StateMachine {
+    #[cfg_attr(clippy, allow(clippy::new_without_default))]
+    pub const fn new() -> Self {
+        StateMachine {
+            accel: Accel { x: 0, y: 0, z: 0},
+            accel_present: false,
+            moving: false,
+            idle_seconds: 0,
+            state_seconds: 0,
+            uptime: 0,
+            state: State::Idle,
+            actions: [Action::HeaterOff; ACTIONS],
+            action_count: 0,
+            log: TransitionLog::new(),
+        }
+    }
+
+    /// Starts in `state` instead of `Idle` without running entry actions,
+    /// for checking transitions on the host
+    pub fn starting_in(state: State) -> Self {
+        let mut machine = StateMachine::new();
+        machine.state = state;
+        machine
+    }
+
+    /// Selects motion based sleep if an accelerometer is present, otherwise
+    /// only key presses count as activity
+    pub fn set_accel_present(&mut self, present: bool) {
+        self.accel_present = present;
+    }
+
+    pub fn accel_present(&self) -> bool {
+        self.accel_present
+    }
+
+    pub fn get_accel(&self) -> Accel {
+        self.accel
+    }
+
+    pub fn current_state(&self) -> State {
+        self.state
+    }
+
+    pub fn log(&self) -> &TransitionLog {
+        &self.log
+    }
+
+    /// Returns and removes the oldest pending action, call until `None`
+    pub fn take_action(&mut self) -> Option<Action> {
+        if self.action_count == 0 {
+            return None;
+        }
+
+        let action = self.actions[0];
+        for i in 1..self.action_count {
+            self.actions[i - 1] = self.actions[i];
+        }
+        self.action_count -= 1;
+        Some(action)
+    }
+
+    /// Handles a single event, the only way the state changes at runtime
+    ///
+    /// The actions it queues have to be taken before the next event.
+    pub fn update_state(&mut self, event: Event) {
+        let trigger = match event {
+            Event::Key(key) => self.update_key(key),
+            Event::Gesture(gesture) => {
+                self.idle_seconds = 0;
+                Some(Trigger::Gesture(gesture))
+            }
+            Event::Motion(motion) => self.update_motion(motion),
+            Event::Accel(accel) => {
+                self.accel = accel;
+                None
+            }
+            Event::Timer(TimerId::Second) => {
+                self.tick();
+                Some(Trigger::Second)
+            }
+            Event::Timer(TimerId::MenuTimeout) => Some(Trigger::MenuTimeout),
+            Event::Timer(TimerId::Refresh) | Event::Timer(TimerId::Sample) => None,
+            Event::AccelError(_) => {
+                self.accel_present = false;
+                self.moving = false;
+                None
+            }
+        };
+
+        if let Some(trigger) = trigger {
+            self.dispatch(trigger);
+        }
+    }
+
+    /// Counts key activity and turns clicks and long presses into triggers,
+    /// presses, releases and repeats only count as activity
+    ///
+    /// The first button of a chord is released without a click, so `AB`
+    /// never also triggers `Click(A)` or `Click(B)`.
+    fn update_key(&mut self, event: KeyEvent) -> Option<Trigger> {
+        self.idle_seconds = 0;
+        let trigger = match event.action {
+            KeyAction::Click => Some(Trigger::Click(event.key)),
+            KeyAction::LongPress => Some(Trigger::LongPress(event.key)),
+            KeyAction::Press | KeyAction::Release | KeyAction::Repeat => None,
+        };
+
+        // restarted once per click or long press, not for each of its
+        // presses and releases
+        if let (Some(_), State::Config(_)) = (trigger, self.state) {
+            self.push_action(Action::StartMenuTimeout);
+        }
+        trigger
+    }
+
+    fn update_motion(&mut self, motion: MotionEvent) -> Option<Trigger> {
+        if !self.accel_present {
+            return None;
+        }
+
+        self.idle_seconds = 0;
+        self.moving = motion == MotionEvent::Started;
+        Some(Trigger::Motion(motion))
+    }
+
+    /// Advances the timeouts by one second, the iron doesn't fall asleep
+    /// while it's moving
+    fn tick(&mut self) {
+        if !self.moving {
+            self.idle_seconds = self.idle_seconds.saturating_add(1);
+        }
+        self.state_seconds = self.state_seconds.saturating_add(1);
+        self.uptime = self.uptime.wrapping_add(1);
+    }
+
+    /// Takes the first matching transition of the current state or its
+    /// parents
+    fn dispatch(&mut self, trigger: Trigger) {
+        let mut node = Some(Node::Leaf(self.state));
+
+        while let Some(source) = node {
+            for transition in TRANSITIONS.iter() {
+                if transition.source != source || transition.trigger != trigger {
+                    continue;
+                }
+                if let Some(guard) = transition.guard {
+                    if !guard(self) {
+                        continue;
+                    }
+                }
+
+                if let Some(target) = transition.target {
+                    self.transition(source, target, trigger);
+                }
+                if let Some(action) = transition.action {
+                    self.push_action(action);
+                }
+                return;
+            }
+            node = source.parent();
+        }
+    }
+
+    /// Leaves the current state for `target`, `source` is the state or
+    /// parent the transition belongs to
+    fn transition(&mut self, source: Node, target: State, trigger: Trigger) {
+        let from = self.state;
+        let target_node = Node::Leaf(target);
+
+        // the innermost state containing source and target, a transition
+        // to the source itself or one of its children exits the source
+        let mut common = source.parent();
+        while let Some(node) = common {
+            if node.contains(target_node) {
+                break;
+            }
+            common = node.parent();
+        }
+        let common = common.unwrap_or(Node::Top);
+
+        let mut node = Node::Leaf(from);
+        while node != common {
+            exit(self, node);
+            node = match node.parent() {
+                Some(parent) => parent,
+                None => break,
+            };
+        }
+
+        // enter from the outermost state below `common` down to the target
+        let depth = common.depth();
+        for level in depth + 1..target_node.depth() + 1 {
+            let mut node = target_node;
+            while node.depth() > level {
+                node = node.parent().unwrap_or(Node::Top);
+            }
+            enter(self, node);
+        }
+
+        self.state = target;
+        self.state_seconds = 0;
+        self.log.push(LogEntry {
+            seconds: self.uptime,
+            from,
+            to: target,
+            trigger,
+        });
+    }
+
+    /// Queues `action`, `ACTIONS` holds all of a single event, so it's only
+    /// dropped if the actions of earlier events weren't taken
+    fn push_action(&mut self, action: Action) {
+        debug_assert!(self.action_count < ACTIONS, "{:?} dropped, take the actions", action);
+        if self.action_count < ACTIONS {
+            self.actions[self.action_count] = action;
+            self.action_count += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use self::std::vec::Vec;
+    use super::*;
+    use accel;
+    use control::{self, Control, TIP_MAX};
+    use proptest::prelude::*;
+
+    fn key(key: Key, action: KeyAction) -> Event {
+        Event::Key(KeyEvent { key, action })
+    }
+
+    fn actions(machine: &mut StateMachine) -> Vec<Action> {
+        let mut actions = Vec::new();
+        while let Some(action) = machine.take_action() {
+            actions.push(action);
+        }
+        actions
+    }
+
+    fn event(trigger: Trigger) -> Event {
+        match trigger {
+            Trigger::Click(k) => key(k, KeyAction::Click),
+            Trigger::LongPress(k) => key(k, KeyAction::LongPress),
+            Trigger::Gesture(gesture) => Event::Gesture(gesture),
+            Trigger::Motion(motion) => Event::Motion(motion),
+            Trigger::Second => Event::Timer(TimerId::Second),
+            Trigger::MenuTimeout => Event::Timer(TimerId::MenuTimeout),
+        }
+    }
+
+    fn heating(state: State) -> bool {
+        matches!(state, State::Soldering | State::Boost | State::TemperatureControl | State::Sleep)
+    }
+
+    /// Target and actions of `trigger` in `from` written out from the
+    /// hierarchy in the module docs, with an accelerometer and no timeout due
+    fn expected(from: State, trigger: Trigger) -> (Option<State>, Vec<Action>) {
+        use super::State::*;
+
+        let restart = Action::StartMenuTimeout;
+        let mut actions = Vec::new();
+        let target = match (from, trigger) {
+            (Idle, Trigger::Click(Key::A)) | (Cooling, Trigger::Click(Key::A)) => Some(Soldering),
+            (Idle, Trigger::Click(Key::B)) => Some(Thermometer),
+            (Cooling, Trigger::Click(Key::B)) | (Thermometer, Trigger::Click(Key::A)) => Some(Idle),
+            (Thermometer, Trigger::Click(Key::B)) => {
+                actions.push(restart);
+                Some(Config(ConfigPage::Calibrate))
+            }
+
+            (Config(page), Trigger::Click(k)) => {
+                actions.push(restart);
+                match (page, k) {
+                    (ConfigPage::Calibrate, Key::A) => {
+                        actions.push(Action::CalibrateAccel);
+                        None
+                    }
+                    (ConfigPage::Calibrate, Key::B) => Some(Config(ConfigPage::Save)),
+                    (ConfigPage::Save, Key::A) => {
+                        actions.push(Action::SaveSettings);
+                        Some(Idle)
+                    }
+                    (ConfigPage::Save, Key::B) => {
+                        actions.push(Action::ShowEvents);
+                        Some(Config(ConfigPage::Events))
+                    }
+                    (ConfigPage::Events, Key::A) => {
+                        actions.push(Action::OlderEvent);
+                        None
+                    }
+                    (ConfigPage::Events, Key::B) => Some(Idle),
+                    (_, Key::AB) => None,
+                }
+            }
+            (Config(_), Trigger::LongPress(k)) => {
+                actions.push(restart);
+                if k == Key::AB { Some(Idle) } else { None }
+            }
+            (Config(_), Trigger::MenuTimeout) => Some(Idle),
+
+            (Soldering, Trigger::Click(Key::A)) | (Soldering, Trigger::Click(Key::B)) => {
+                Some(TemperatureControl)
+            }
+            (Soldering, Trigger::Gesture(Gesture::DoubleTap)) => Some(Boost),
+            (TemperatureControl, Trigger::Click(Key::A)) |
+            (TemperatureControl, Trigger::Click(Key::B)) |
+            (Boost, Trigger::Click(Key::A)) |
+            (Boost, Trigger::Click(Key::B)) |
+            (Boost, Trigger::Gesture(Gesture::DoubleTap)) |
+            (Sleep, Trigger::Click(Key::A)) |
+            (Sleep, Trigger::Click(Key::B)) |
+            (Sleep, Trigger::Gesture(Gesture::DoubleTap)) |
+            (Sleep, Trigger::Motion(MotionEvent::Started)) => Some(Soldering),
+            (heater, Trigger::Click(Key::AB)) |
+            (heater, Trigger::LongPress(Key::AB)) if heating(heater) => {
+                actions.push(Action::HeaterOff);
+                Some(Idle)
+            }
+            _ => None,
+        };
+
+        (target, actions)
+    }
+
+    #[test]
+    fn every_trigger_in_every_state() {
+        for &from in STATES.iter() {
+            for &trigger in TRIGGERS.iter() {
+                let mut machine = StateMachine::starting_in(from);
+                machine.set_accel_present(true);
+                machine.update_state(event(trigger));
+
+                let (target, expected_actions) = expected(from, trigger);
+                let to = machine.current_state();
+                assert_eq!(to, target.unwrap_or(from), "{:?} {:?}", from, trigger);
+                assert_eq!(actions(&mut machine), expected_actions, "{:?} {:?}", from, trigger);
+
+                let log: Vec<_> = machine.log().iter().collect();
+                if target.is_some() {
+                    assert_eq!(log,
+                               [LogEntry { seconds: 0, from, to, trigger }],
+                               "{:?} {:?}",
+                               from,
+                               trigger);
+                } else {
+                    assert!(log.is_empty(), "{:?} {:?}: {:?}", from, trigger, log);
+                }
+            }
+        }
+    }
+
+    /// Feeds `count` seconds, returns the state after each
+    fn seconds(machine: &mut StateMachine, count: u32) -> Vec<State> {
+        (0..count)
+            .map(|_| {
+                machine.update_state(Event::Timer(TimerId::Second));
+                actions(machine);
+                machine.current_state()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn active_states_fall_asleep_without_activity() {
+        for &from in &[State::Soldering, State::TemperatureControl] {
+            let mut machine = StateMachine::starting_in(from);
+            let states = seconds(&mut machine, SLEEP_TIMEOUT);
+            assert!(states[..SLEEP_TIMEOUT as usize - 1].iter().all(|&state| state == from));
+            assert_eq!(machine.current_state(), State::Sleep);
+
+            let entry = machine.log().iter().last().unwrap();
+            assert_eq!(entry,
+                       LogEntry {
+                           seconds: SLEEP_TIMEOUT,
+                           from,
+                           to: State::Sleep,
+                           trigger: Trigger::Second,
+                       });
+        }
+    }
+
+    #[test]
+    fn activity_postpones_sleep() {
+        let mut machine = StateMachine::starting_in(State::Soldering);
+        seconds(&mut machine, SLEEP_TIMEOUT - 1);
+        machine.update_state(key(Key::A, KeyAction::Press));
+        seconds(&mut machine, SLEEP_TIMEOUT - 1);
+        machine.update_state(Event::Gesture(Gesture::Tap));
+        seconds(&mut machine, SLEEP_TIMEOUT - 1);
+        assert_eq!(machine.current_state(), State::Soldering);
+        seconds(&mut machine, 1);
+        assert_eq!(machine.current_state(), State::Sleep);
+    }
+
+    #[test]
+    fn moving_postpones_sleep() {
+        let mut machine = StateMachine::starting_in(State::Soldering);
+        machine.set_accel_present(true);
+        machine.update_state(Event::Motion(MotionEvent::Started));
+        seconds(&mut machine, 2 * SLEEP_TIMEOUT);
+        assert_eq!(machine.current_state(), State::Soldering);
+
+        machine.update_state(Event::Motion(MotionEvent::Stopped));
+        seconds(&mut machine, SLEEP_TIMEOUT);
+        assert_eq!(machine.current_state(), State::Sleep);
+    }
+
+    #[test]
+    fn motion_needs_an_accelerometer() {
+        let mut machine = StateMachine::starting_in(State::Sleep);
+        machine.update_state(Event::Motion(MotionEvent::Started));
+        assert_eq!(machine.current_state(), State::Sleep);
+
+        machine.set_accel_present(true);
+        machine.update_state(Event::AccelError(::accel::Error::NotPresent));
+        assert!(!machine.accel_present());
+        machine.update_state(Event::Motion(MotionEvent::Started));
+        assert_eq!(machine.current_state(), State::Sleep);
+    }
+
+    #[test]
+    fn boost_falls_back_after_its_timeout() {
+        let mut machine = StateMachine::starting_in(State::Soldering);
+        machine.update_state(Event::Gesture(Gesture::DoubleTap));
+        let states = seconds(&mut machine, BOOST_TIMEOUT);
+        assert!(states[..BOOST_TIMEOUT as usize - 1].iter().all(|&state| state == State::Boost));
+        assert_eq!(machine.current_state(), State::Soldering);
+        assert_eq!(actions(&mut machine), []);
+    }
+
+    #[test]
+    fn the_log_keeps_the_latest_transitions() {
+        let mut machine = StateMachine::starting_in(State::Soldering);
+        for _ in 0..LOG_SIZE + 4 {
+            machine.update_state(key(Key::A, KeyAction::Click));
+        }
+        let log: Vec<_> = machine.log().iter().collect();
+        assert_eq!(log.len(), LOG_SIZE);
+        // the first four of the toggles were overwritten
+        assert_eq!(log[0].from, State::Soldering);
+        assert!(log.windows(2).all(|pair| pair[0].to == pair[1].from));
+        assert_eq!(log[LOG_SIZE - 1].to, State::Soldering);
+    }
+
+    #[test]
+    fn a_click_in_the_menu_restarts_its_timeout_once() {
+        let mut machine = StateMachine::starting_in(State::Config(ConfigPage::Calibrate));
+        machine.update_state(key(Key::B, KeyAction::Press));
+        machine.update_state(key(Key::B, KeyAction::Release));
+        assert_eq!(actions(&mut machine), []);
+
+        machine.update_state(key(Key::B, KeyAction::Click));
+        assert_eq!(actions(&mut machine), [Action::StartMenuTimeout]);
+        assert_eq!(machine.current_state(), State::Config(ConfigPage::Save));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "take the actions")]
+    fn untaken_actions_overflow_the_queue() {
+        let mut machine = StateMachine::starting_in(State::Config(ConfigPage::Events));
+        for _ in 0..ACTIONS {
+            machine.update_state(key(Key::A, KeyAction::Click));
+        }
+    }
+
+    /// Seconds of a quiet period, the sleep and boost timeouts are never
+    /// reached otherwise
+    const QUIET_MAX: u32 = 90;
+
+    fn any_event() -> impl Strategy<Value = Event> {
+        let keys = prop::sample::select(&[Key::A, Key::B, Key::AB][..]);
+        let actions = prop::sample::select(&[
+            KeyAction::Press,
+            KeyAction::Release,
+            KeyAction::Click,
+            KeyAction::LongPress,
+            KeyAction::Repeat,
+        ][..]);
+        let timers = prop::sample::select(&[
+            TimerId::Second,
+            TimerId::Refresh,
+            TimerId::MenuTimeout,
+            TimerId::Sample,
+        ][..]);
+
+        prop_oneof![
+            6 => (keys, actions).prop_map(|(key, action)| Event::Key(KeyEvent { key, action })),
+            1 => prop::sample::select(&[Gesture::Tap, Gesture::DoubleTap][..])
+                .prop_map(Event::Gesture),
+            1 => prop::sample::select(&[MotionEvent::Started, MotionEvent::Stopped][..])
+                .prop_map(Event::Motion),
+            1 => Just(Event::Accel(Accel { x: 0, y: 0, z: 1000 })),
+            1 => Just(Event::AccelError(accel::Error::NoData)),
+            // seconds dominate so the timeouts get a chance to expire
+            6 => timers.prop_map(Event::Timer),
+        ]
+    }
+
+    /// Tip readings from far below ambient to beyond the runaway limit,
+    /// close to the limits of the states more often than not
+    fn any_tip() -> impl Strategy<Value = i16> {
+        const LIMITS: [i16; 2] = [control::SLEEP, TIP_MAX];
+        prop_oneof![
+            1 => -50i16..550,
+            2 => (prop::sample::select(&LIMITS[..]), -5i16..6).prop_map(|(limit, d)| limit + d),
+        ]
+    }
+
+    /// An event fed `count` times, each followed by a sample reading `tip`
+    fn any_step() -> impl Strategy<Value = (Event, u32, i16)> {
+        prop_oneof![
+            19 => (any_event(), Just(1), any_tip()),
+            1 => (Just(Event::Timer(TimerId::Second)), 1..QUIET_MAX, any_tip()),
+        ]
+    }
+
+    /// Highest tip temperature the heater may heat to in `state` as required,
+    /// kept apart from `control::allowed_heater_output` which it checks
+    fn required_limit(state: State) -> Option<i16> {
+        match state {
+            State::Idle | State::Cooling | State::Thermometer | State::Config(_) => None,
+            State::Sleep => Some(control::SLEEP),
+            State::Soldering | State::TemperatureControl | State::Boost => Some(TIP_MAX),
+        }
+    }
+
+    proptest! {
+        /// Events and samples fed like the firmware does, failures shrink to
+        /// the shortest sequence
+        #[test]
+        fn the_heater_respects_the_allowed_output(
+            steps in prop::collection::vec(any_step(), 1..300)
+        ) {
+            let mut machine = StateMachine::new();
+            machine.set_accel_present(true);
+            let mut control = Control::new();
+            let mut heating = false;
+
+            for &(event, count, tip) in &steps {
+                for _ in 0..count {
+                    let before = machine.current_state();
+                    machine.update_state(event);
+                    while let Some(action) = machine.take_action() {
+                        if action == Action::HeaterOff {
+                            heating = false;
+                        }
+                    }
+
+                    // off right after leaving a heating state, before the
+                    // next sample
+                    let state = machine.current_state();
+                    let required = required_limit(state);
+                    prop_assert!(!heating || required.is_some(),
+                                 "heater still on after {:?} -> {:?}",
+                                 before,
+                                 state);
+
+                    let allowed = control::allowed_heater_output(state);
+                    let permissive = match (allowed, required) {
+                        (Some(_), None) => true,
+                        (Some(allowed), Some(required)) => allowed > required,
+                        (None, _) => false,
+                    };
+                    prop_assert!(!permissive,
+                                 "{:?} allows {:?} °C instead of {:?} °C",
+                                 state,
+                                 allowed,
+                                 required);
+
+                    heating = control.update(state, tip);
+                    match required {
+                        None => prop_assert!(!heating, "heater on in {:?}", state),
+                        Some(limit) => {
+                            prop_assert!(!heating || tip < limit,
+                                         "heater on at {} °C in {:?}, limit {} °C",
+                                         tip,
+                                         state,
+                                         limit)
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
@@ -0,0 +1,135 @@
+//! Conversion of the raw ADC readings into temperatures and the supply
+//! voltage
+//!
+//! The tip is measured with a thermocouple whose amplified voltage is
+//! relative to the cold junction temperature, which is measured by a TMP36
+//! next to the tip connector. The supply voltage is divided by R20 (75K) and
+//! R21 (10K) onto ADC channel 9 (PB1).
+//!
+//! # Calibration
+//!
+//! The tip reading is the thermocouple rise above the cold junction,
+//! amplified onto ADC channel 8 (PB0) with 12 bits over 3.3 V. The slope of
+//! that chain isn't taken from a datasheet, it has to be measured on the
+//! iron:
+//!
+//! 1. run the firmware under the debugger with the control loop trace (see
+//!    the README), it samples the tip with the heater off
+//! 2. heat the tip externally, e.g. with hot air, and let it settle at two
+//!    temperatures checked with a tip thermometer, e.g. 200 °C and 350 °C
+//! 3. take `tip_raw` and `cold_junction` of the trace at both points, the
+//!    rise is the thermometer reading minus `cold_junction` and the slope
+//!    the change of `tip_raw` over the change of the rise
+//! 4. set `TIP_COUNTS_PER_9_DEGREES` to nine times that slope
+//!
+//! Until that is done for a tip, every tip temperature, and with it
+//! `control::TIP_MAX` and the runaway protection of the journal, is only as
+//! good as the guessed slope. The firmware therefore doesn't drive the
+//! heater yet.
+
+/// Output of the TMP36 at 0 °C and its slope
+const TMP36_OFFSET_MV: i32 = 500;
+const TMP36_MV_PER_DEGREE: i32 = 10;
+const VREF_MV: i32 = 3300;
+const FULL_SCALE: i32 = 4096;
+/// Supply voltage divider, (R20 + R21) / R21 in tenths
+const SUPPLY_DIVIDER_TENTHS: i32 = 85;
+
+/// Slope of the amplified thermocouple voltage in ADC counts per 9 °C
+///
+/// Guessed at about 4.5 counts per °C and not measured yet, see the
+/// calibration above before heating a real tip.
+const TIP_COUNTS_PER_9_DEGREES: i32 = 40;
+
+/// Raw conversion results
+#[derive(Clone, Copy)]
+pub struct Sample {
+    pub tip: u16,
+    pub ambient: u16,
+    pub supply: u16,
+}
+
+impl Sample {
+    /// Raw readings producing the given temperatures in °C and supply
+    /// voltage in mV, the inverse of `tip_celsius`, `ambient_celsius` and
+    /// `supply_mv` up to rounding
+    pub fn from_readings(tip: i16, ambient: i16, supply_mv: u16) -> Self {
+        let mv = i32::from(ambient) * TMP36_MV_PER_DEGREE + TMP36_OFFSET_MV;
+        let rise = i32::from(tip) - i32::from(ambient);
+        let full_scale_mv = VREF_MV * SUPPLY_DIVIDER_TENTHS;
+        let supply = (i32::from(supply_mv) * 10 * FULL_SCALE + full_scale_mv / 2) / full_scale_mv;
+        Sample {
+            tip: clamp_raw(rise * TIP_COUNTS_PER_9_DEGREES / 9),
+            ambient: clamp_raw(mv * FULL_SCALE / VREF_MV),
+            supply: clamp_raw(supply),
+        }
+    }
+
+    /// Supply voltage in mV
+    pub fn supply_mv(&self) -> u16 {
+        (i32::from(self.supply) * VREF_MV * SUPPLY_DIVIDER_TENTHS / (10 * FULL_SCALE)) as u16
+    }
+
+    /// Cold junction temperature in °C
+    pub fn ambient_celsius(&self) -> i16 {
+        let mv = i32::from(self.ambient) * VREF_MV / FULL_SCALE;
+        ((mv - TMP36_OFFSET_MV) / TMP36_MV_PER_DEGREE) as i16
+    }
+
+    /// Tip temperature in °C, compensated for the cold junction
+    pub fn tip_celsius(&self) -> i16 {
+        let rise = i32::from(self.tip) * 9 / TIP_COUNTS_PER_9_DEGREES;
+        self.ambient_celsius() + rise as i16
+    }
+}
+
+fn clamp_raw(value: i32) -> u16 {
+    if value < 0 {
+        0
+    } else if value >= FULL_SCALE {
+        (FULL_SCALE - 1) as u16
+    } else {
+        value as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_celsius_inverts_the_conversion() {
+        for &ambient in &[0, 25, 40] {
+            for &tip in &[ambient, 150, 320, 400] {
+                let sample = Sample::from_readings(tip, ambient, 19_000);
+                assert!((sample.ambient_celsius() - ambient).abs() <= 1, "ambient {}", ambient);
+                assert!((sample.tip_celsius() - tip).abs() <= 2, "tip {} at {}", tip, ambient);
+            }
+        }
+    }
+
+    #[test]
+    fn raw_readings_saturate() {
+        let sample = Sample::from_readings(-100, 25, 0);
+        assert_eq!(sample.tip, 0);
+        let sample = Sample::from_readings(2000, 25, 0);
+        assert_eq!(sample.tip, (FULL_SCALE - 1) as u16);
+        let sample = Sample::from_readings(25, 25, 30_000);
+        assert_eq!(sample.supply, (FULL_SCALE - 1) as u16);
+    }
+
+    #[test]
+    fn supply_through_the_divider() {
+        // 19 V are 2.235 V at the ADC
+        let sample = Sample { tip: 0, ambient: 0, supply: 2774 };
+        assert!((sample.supply_mv() as i32 - 19_000).abs() < 10, "{}", sample.supply_mv());
+        // full scale is 28.05 V
+        let sample = Sample { tip: 0, ambient: 0, supply: (FULL_SCALE - 1) as u16 };
+        assert_eq!(sample.supply_mv(), 28_043);
+
+        for &mv in &[0, 9_000, 12_000, 19_000, 24_000] {
+            let supply = Sample::from_readings(25, 25, mv).supply_mv();
+            assert!(supply <= mv + 7 && supply + 7 >= mv, "{} mV read as {}", mv, supply);
+        }
+    }
+}
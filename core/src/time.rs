@@ -0,0 +1,242 @@
+//! Millisecond timebase and software timers
+//!
+//! `SYS_TICK` fires every millisecond and advances a monotonic counter which
+//! can be read with `now()` from any task. The counter wraps after about 49
+//! days, so deadlines must only be compared with `reached` and durations
+//! computed with `elapsed`, which both stay correct across the wrap as long
+//! as the compared times are less than 2^31 ms apart.
+
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+/// Milliseconds since boot, wrapping
+static NOW: AtomicUsize = AtomicUsize::new(0);
+
+/// Advances the timebase by one millisecond, only called by `SYS_TICK`
+pub fn tick() -> u32 {
+    (NOW.fetch_add(1, Ordering::Relaxed) as u32).wrapping_add(1)
+}
+
+/// Milliseconds since boot, wrapping
+pub fn now() -> u32 {
+    NOW.load(Ordering::Relaxed) as u32
+}
+
+/// `true` if `now` is at or after `deadline`
+pub fn reached(now: u32, deadline: u32) -> bool {
+    (now.wrapping_sub(deadline) as i32) >= 0
+}
+
+/// Milliseconds from `since` to `now`
+pub fn elapsed(now: u32, since: u32) -> u32 {
+    now.wrapping_sub(since)
+}
+
+/// Software timers, each id has a single slot
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TimerId {
+    /// Periodic one second tick driving the state machine timeouts
+    Second = 0,
+    /// Periodic redraw of the screen
+    Refresh = 1,
+    /// Leaves the configuration menu when no key was pressed for a while
+    MenuTimeout = 2,
+    /// Periodic sampling of the tip temperature
+    Sample = 3,
+}
+
+const TIMERS: usize = 4;
+
+const IDS: [TimerId; TIMERS] = [
+    TimerId::Second,
+    TimerId::Refresh,
+    TimerId::MenuTimeout,
+    TimerId::Sample,
+];
+
+#[derive(Clone, Copy)]
+struct Timer {
+    deadline: u32,
+    /// Reload interval of a periodic timer
+    period: Option<u32>,
+    running: bool,
+}
+
+pub struct Timers {
+    timers: [Timer; TIMERS],
+}
+
+impl Timers {
+    #[cfg_attr(clippy, allow(clippy::new_without_default))]
+    pub const fn new() -> Self {
+        Timers {
+            timers: [Timer {
+                deadline: 0,
+                period: None,
+                running: false,
+            }; TIMERS],
+        }
+    }
+
+    /// Fires `id` once `ms` after `now`, restarts it if it's already running
+    pub fn start(&mut self, id: TimerId, now: u32, ms: u32) {
+        self.timers[id as usize] = Timer {
+            deadline: now.wrapping_add(ms),
+            period: None,
+            running: true,
+        };
+    }
+
+    /// Fires `id` every `ms` starting `ms` after `now`
+    pub fn start_periodic(&mut self, id: TimerId, now: u32, ms: u32) {
+        self.timers[id as usize] = Timer {
+            deadline: now.wrapping_add(ms),
+            period: Some(ms),
+            running: true,
+        };
+    }
+
+    /// Returns the next timer expired at `now`
+    ///
+    /// One shot timers stop, periodic timers are rearmed relative to their
+    /// deadline so they don't drift. Call repeatedly until `None`.
+    pub fn expired(&mut self, now: u32) -> Option<TimerId> {
+        for (timer, id) in self.timers.iter_mut().zip(IDS.iter()) {
+            if timer.running && reached(now, timer.deadline) {
+                match timer.period {
+                    Some(period) => timer.deadline = timer.deadline.wrapping_add(period),
+                    None => timer.running = false,
+                }
+                return Some(*id);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use self::std::vec::Vec;
+    use super::*;
+
+    const HALF: u32 = 1 << 31;
+
+    #[test]
+    fn reached_across_the_wrap() {
+        let deadline = u32::MAX - 5;
+        assert!(!reached(deadline - 1, deadline));
+        assert!(reached(deadline, deadline));
+        assert!(reached(u32::MAX, deadline));
+        assert!(reached(0, deadline));
+        assert!(reached(100, deadline));
+
+        let deadline = 10;
+        assert!(!reached(u32::MAX - 5, deadline));
+        assert!(!reached(9, deadline));
+        assert!(reached(10, deadline));
+    }
+
+    #[test]
+    fn elapsed_across_the_wrap() {
+        assert_eq!(elapsed(4, u32::MAX - 5), 10);
+        assert_eq!(elapsed(0, u32::MAX), 1);
+        assert_eq!(elapsed(1000, 1000), 0);
+    }
+
+    #[test]
+    fn deadlines_less_than_half_the_range_apart() {
+        for &deadline in &[0, 1000, HALF - 1, HALF, u32::MAX] {
+            assert!(reached(deadline.wrapping_add(HALF - 1), deadline));
+            // further than 2^31 ms looks like the deadline is still ahead
+            assert!(!reached(deadline.wrapping_add(HALF), deadline));
+            assert!(!reached(deadline.wrapping_sub(1), deadline));
+            assert!(!reached(deadline.wrapping_sub(HALF - 1), deadline));
+        }
+    }
+
+    #[test]
+    fn tick_wraps() {
+        NOW.store(u32::MAX as usize, Ordering::Relaxed);
+        assert_eq!(now(), u32::MAX);
+        assert_eq!(tick(), 0);
+        assert_eq!(now(), 0);
+        assert_eq!(tick(), 1);
+    }
+
+    #[test]
+    fn one_shot_straddling_the_wrap() {
+        let mut timers = Timers::new();
+        let start = u32::MAX - 100;
+        timers.start(TimerId::MenuTimeout, start, 200);
+
+        assert_eq!(timers.expired(u32::MAX), None);
+        assert_eq!(timers.expired(98), None);
+        assert_eq!(timers.expired(99), Some(TimerId::MenuTimeout));
+        assert_eq!(timers.expired(99), None);
+        assert_eq!(timers.expired(1000), None);
+    }
+
+    #[test]
+    fn periodic_rearms_across_the_wrap_without_drift() {
+        let mut timers = Timers::new();
+        let start = u32::MAX - 250;
+        timers.start_periodic(TimerId::Sample, start, 100);
+
+        let mut fired = [0; 6];
+        let mut count = 0;
+        let mut now = start;
+        while count < fired.len() {
+            now = now.wrapping_add(1);
+            while let Some(id) = timers.expired(now) {
+                assert_eq!(id, TimerId::Sample);
+                fired[count] = now;
+                count += 1;
+            }
+        }
+        // the third deadline is the first after the wrap, at 49
+        let expected: Vec<u32> = (1..7).map(|n| start.wrapping_add(n * 100)).collect();
+        assert_eq!(fired[..], expected[..]);
+        assert_eq!(fired[2], 49);
+    }
+
+    #[test]
+    fn late_polls_catch_up_periods() {
+        let mut timers = Timers::new();
+        let start = u32::MAX - 150;
+        timers.start_periodic(TimerId::Refresh, start, 100);
+
+        // 300 ms late, across the wrap
+        let now = start.wrapping_add(300);
+        assert_eq!(timers.expired(now), Some(TimerId::Refresh));
+        assert_eq!(timers.expired(now), Some(TimerId::Refresh));
+        assert_eq!(timers.expired(now), Some(TimerId::Refresh));
+        assert_eq!(timers.expired(now), None);
+        assert_eq!(timers.expired(now.wrapping_add(100)), Some(TimerId::Refresh));
+    }
+
+    #[test]
+    fn restarting_replaces_the_deadline() {
+        let mut timers = Timers::new();
+        timers.start_periodic(TimerId::Second, u32::MAX - 10, 1000);
+        timers.start(TimerId::Second, u32::MAX - 10, 20);
+
+        assert_eq!(timers.expired(9), Some(TimerId::Second));
+        assert_eq!(timers.expired(2000), None);
+    }
+
+    #[test]
+    fn expired_reports_every_due_timer() {
+        let mut timers = Timers::new();
+        let now = u32::MAX;
+        timers.start(TimerId::Refresh, now, 1);
+        timers.start(TimerId::Second, now, 1);
+        timers.start(TimerId::Sample, now, 2);
+
+        assert_eq!(timers.expired(0), Some(TimerId::Second));
+        assert_eq!(timers.expired(0), Some(TimerId::Refresh));
+        assert_eq!(timers.expired(0), None);
+        assert_eq!(timers.expired(1), Some(TimerId::Sample));
+    }
+}
@@ -0,0 +1,283 @@
+//! Binary control loop records for tuning
+//!
+//! A `Record` is captured after every tip sample, 50 times a second, and
+//! streamed over RTT while a debugger is attached. Records are small and
+//! framed so a reader can join the stream at any point and skip damaged
+//! frames:
+//!
+//! - the payload is `VERSION` followed by the fields in little endian in
+//!   the order of `Record` and a CRC-8 over all of it
+//! - the payload is COBS encoded, so it contains no zero bytes, and each
+//!   frame ends with a zero byte
+//!
+//! The controller is a plain on/off controller, the control error and the
+//! heater decision therefore take the place of PID terms. New fields are
+//! appended with a new version.
+
+use state::{State, STATES};
+use temperature::Sample;
+
+/// Layout of the payload, readers skip other versions
+pub const VERSION: u8 = 1;
+/// Payload bytes including version and CRC
+pub const PAYLOAD_LEN: usize = 1 + 4 + 2 + 2 + 2 + 2 + 2 + 1 + 1 + 1 + 1;
+/// Longest encoded frame including the delimiter
+pub const FRAME_MAX: usize = PAYLOAD_LEN + 2;
+
+const FLAG_HEATING: u8 = 1 << 0;
+const FLAG_SETPOINT: u8 = 1 << 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Record {
+    /// Milliseconds since boot
+    pub time: u32,
+    /// Tip temperature in °C
+    pub tip: i16,
+    /// Raw ADC reading of the thermocouple
+    pub tip_raw: u16,
+    /// Handle temperature in °C, the cold junction of the thermocouple
+    pub cold_junction: i16,
+    /// Setpoint in °C, `None` while the heater has to be off
+    pub setpoint: Option<i16>,
+    /// Setpoint minus tip temperature, 0 without setpoint
+    pub error: i16,
+    /// Averaged heater duty cycle in percent
+    pub duty: u8,
+    /// Heater output for the next sample period
+    pub heating: bool,
+    /// Index of the state in `state::STATES`
+    pub state: u8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DecodeError {
+    /// The frame is longer than any record
+    TooLong,
+    /// The COBS encoding is broken
+    Framing,
+    Checksum,
+    /// A record of another version or length
+    UnknownVersion(u8),
+}
+
+impl Record {
+    /// Record of the controller decision after `sample`
+    pub fn capture(time: u32,
+                   sample: &Sample,
+                   state: State,
+                   setpoint: Option<i16>,
+                   heating: bool,
+                   duty: u8)
+                   -> Record {
+        let tip = sample.tip_celsius();
+        Record {
+            time,
+            tip,
+            tip_raw: sample.tip,
+            cold_junction: sample.ambient_celsius(),
+            setpoint,
+            error: setpoint.map(|target| target.saturating_sub(tip)).unwrap_or(0),
+            duty,
+            heating,
+            state: STATES.iter().position(|&s| s == state).unwrap_or(0) as u8,
+        }
+    }
+
+    /// The state the record was taken in
+    pub fn state(&self) -> Option<State> {
+        STATES.get(usize::from(self.state)).cloned()
+    }
+
+    /// Encodes the record into a complete frame, returns its length
+    pub fn encode(&self, frame: &mut [u8; FRAME_MAX]) -> usize {
+        let mut flags = 0;
+        if self.heating {
+            flags |= FLAG_HEATING;
+        }
+        if self.setpoint.is_some() {
+            flags |= FLAG_SETPOINT;
+        }
+
+        let mut payload = [0; PAYLOAD_LEN];
+        payload[0] = VERSION;
+        put_u32(&mut payload[1..5], self.time);
+        put_u16(&mut payload[5..7], self.tip as u16);
+        put_u16(&mut payload[7..9], self.tip_raw);
+        put_u16(&mut payload[9..11], self.cold_junction as u16);
+        put_u16(&mut payload[11..13], self.setpoint.unwrap_or(0) as u16);
+        put_u16(&mut payload[13..15], self.error as u16);
+        payload[15] = self.duty;
+        payload[16] = flags;
+        payload[17] = self.state;
+        payload[PAYLOAD_LEN - 1] = crc8(&payload[..PAYLOAD_LEN - 1]);
+
+        let len = cobs_encode(&payload, &mut frame[..FRAME_MAX - 1]);
+        frame[len] = 0;
+        len + 1
+    }
+
+    /// Decodes a frame without its delimiter
+    pub fn decode(frame: &[u8]) -> Result<Record, DecodeError> {
+        let mut payload = [0; PAYLOAD_LEN + 1];
+        if frame.len() > FRAME_MAX - 1 {
+            return Err(DecodeError::TooLong);
+        }
+        let len = cobs_decode(frame, &mut payload).ok_or(DecodeError::Framing)?;
+        if len == 0 {
+            return Err(DecodeError::Framing);
+        }
+        if payload[0] != VERSION || len != PAYLOAD_LEN {
+            return Err(DecodeError::UnknownVersion(payload[0]));
+        }
+        if crc8(&payload[..PAYLOAD_LEN - 1]) != payload[PAYLOAD_LEN - 1] {
+            return Err(DecodeError::Checksum);
+        }
+
+        let flags = payload[16];
+        Ok(Record {
+            time: get_u32(&payload[1..5]),
+            tip: get_u16(&payload[5..7]) as i16,
+            tip_raw: get_u16(&payload[7..9]),
+            cold_junction: get_u16(&payload[9..11]) as i16,
+            setpoint: if flags & FLAG_SETPOINT != 0 {
+                Some(get_u16(&payload[11..13]) as i16)
+            } else {
+                None
+            },
+            error: get_u16(&payload[13..15]) as i16,
+            duty: payload[15],
+            heating: flags & FLAG_HEATING != 0,
+            state: payload[17],
+        })
+    }
+}
+
+/// Splits a byte stream into frames and decodes them
+pub struct Decoder {
+    frame: [u8; FRAME_MAX],
+    len: usize,
+    /// The current frame got too long and is skipped up to its end
+    overflow: bool,
+}
+
+impl Decoder {
+    #[cfg_attr(clippy, allow(clippy::new_without_default))]
+    pub const fn new() -> Self {
+        Decoder {
+            frame: [0; FRAME_MAX],
+            len: 0,
+            overflow: false,
+        }
+    }
+
+    /// Feeds one byte, returns the decoded frame at each delimiter
+    pub fn push(&mut self, byte: u8) -> Option<Result<Record, DecodeError>> {
+        if byte != 0 {
+            if self.len < self.frame.len() {
+                self.frame[self.len] = byte;
+                self.len += 1;
+            } else {
+                self.overflow = true;
+            }
+            return None;
+        }
+
+        let result = if self.overflow {
+            Some(Err(DecodeError::TooLong))
+        } else if self.len == 0 {
+            // delimiters in a row, e.g. when joining a stream
+            None
+        } else {
+            Some(Record::decode(&self.frame[..self.len]))
+        };
+        self.len = 0;
+        self.overflow = false;
+        result
+    }
+}
+
+/// CRC-8 with polynomial 0x07
+pub fn crc8(data: &[u8]) -> u8 {
+    let mut crc = 0u8;
+    for &byte in data {
+        crc ^= byte;
+        for _ in 0..8 {
+            crc = if crc & 0x80 != 0 { (crc << 1) ^ 0x07 } else { crc << 1 };
+        }
+    }
+    crc
+}
+
+/// Consistent overhead byte stuffing of `data` into `out`, which must hold
+/// `data.len() + 1` bytes for data shorter than 254 bytes, returns the
+/// encoded length
+fn cobs_encode(data: &[u8], out: &mut [u8]) -> usize {
+    let mut code_index = 0;
+    let mut code = 1u8;
+    let mut len = 1;
+
+    for &byte in data {
+        if byte == 0 {
+            out[code_index] = code;
+            code_index = len;
+            len += 1;
+            code = 1;
+        } else {
+            out[len] = byte;
+            len += 1;
+            code += 1;
+        }
+    }
+    out[code_index] = code;
+    len
+}
+
+/// Reverses `cobs_encode`, `None` if the encoding is broken or `out` too
+/// short
+fn cobs_decode(data: &[u8], out: &mut [u8]) -> Option<usize> {
+    let mut index = 0;
+    let mut len = 0;
+
+    while index < data.len() {
+        let code = usize::from(data[index]);
+        index += 1;
+        if code == 0 || index + code - 1 > data.len() {
+            return None;
+        }
+
+        for _ in 1..code {
+            if len == out.len() {
+                return None;
+            }
+            out[len] = data[index];
+            len += 1;
+            index += 1;
+        }
+        if code < 0xff && index < data.len() {
+            if len == out.len() {
+                return None;
+            }
+            out[len] = 0;
+            len += 1;
+        }
+    }
+    Some(len)
+}
+
+fn put_u16(out: &mut [u8], value: u16) {
+    out[0] = value as u8;
+    out[1] = (value >> 8) as u8;
+}
+
+fn put_u32(out: &mut [u8], value: u32) {
+    put_u16(&mut out[..2], value as u16);
+    put_u16(&mut out[2..], (value >> 16) as u16);
+}
+
+fn get_u16(data: &[u8]) -> u16 {
+    u16::from(data[0]) | u16::from(data[1]) << 8
+}
+
+fn get_u32(data: &[u8]) -> u32 {
+    u32::from(get_u16(&data[..2])) | u32::from(get_u16(&data[2..])) << 16
+}
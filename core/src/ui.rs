@@ -0,0 +1,280 @@
+//! Screen contents rendered into a framebuffer
+//!
+//! The OLED shows 96x16 pixels organized in two pages of 8 pixel rows, each
+//! byte of a page is one column with the least significant bit at the top.
+//! Text uses the 5x7 font in cells of 6x8 pixels, so there are 16 columns
+//! and 2 lines of text.
+
+use accel;
+use font5x7;
+use format;
+use journal::{Event, Record, ResetCause};
+use state::{ConfigPage, State, StateMachine};
+
+/// Width in pixels
+pub const WIDTH: usize = 96;
+/// Height in pages of 8 pixels
+pub const PAGES: usize = 2;
+/// Text columns per line
+pub const COLUMNS: u8 = 16;
+
+const CELL_WIDTH: usize = 6;
+
+pub struct Framebuffer {
+    buffer: [u8; WIDTH * PAGES],
+}
+
+impl Framebuffer {
+    #[cfg_attr(clippy, allow(clippy::new_without_default))]
+    pub const fn new() -> Self {
+        Framebuffer { buffer: [0; WIDTH * PAGES] }
+    }
+
+    pub fn clear(&mut self) -> &mut Self {
+        self.buffer = [0; WIDTH * PAGES];
+        self
+    }
+
+    /// Columns of `page`, left to right
+    pub fn page(&self, page: usize) -> &[u8] {
+        &self.buffer[page * WIDTH..(page + 1) * WIDTH]
+    }
+
+    /// `true` if the pixel at `x`, `y` (0, 0 is top left) is lit
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.buffer[(y / 8) * WIDTH + x] & (1 << (y % 8)) != 0
+    }
+
+    /// Draws `text` starting at column `x` of line `y`, characters beyond the
+    /// right edge are dropped
+    #[cfg_attr(clippy, allow(clippy::manual_range_contains))]
+    pub fn print(&mut self, x: u8, y: u8, text: &str) -> &mut Self {
+        if y as usize >= PAGES {
+            return self;
+        }
+
+        let start = y as usize * WIDTH;
+        let mut column = x as usize * CELL_WIDTH;
+        for byte in text.as_bytes().iter().cloned() {
+            if column + CELL_WIDTH > WIDTH {
+                break;
+            }
+            // unprintable characters are left blank
+            if byte >= 0x20 && byte < 0x20 + 0x60 {
+                let glyph = (byte - 0x20) as usize * 5;
+                self.buffer[start + column..start + column + 5]
+                    .copy_from_slice(&font5x7::FONT_5X7[glyph..glyph + 5]);
+            }
+            self.buffer[start + column + 5] = 0;
+            column += CELL_WIDTH;
+        }
+
+        self
+    }
+
+    /// Prints `number` right aligned in 6 columns
+    pub fn print_number(&mut self, x: u8, y: u8, number: i16) -> &mut Self {
+        let mut buffer = [b' '; 6];
+        self.print(x, y, format::decimal(i32::from(number), &mut buffer))
+    }
+
+    /// Prints `number` as two hex digits
+    pub fn print_hex(&mut self, x: u8, y: u8, number: u8) -> &mut Self {
+        let mut buffer = [b'0'; 2];
+        self.print(x, y, format::hex(u32::from(number), &mut buffer))
+    }
+}
+
+/// Draws the screen for the current state of `state`
+pub fn render(state: &StateMachine, fb: &mut Framebuffer) {
+    fb.clear();
+
+    if let State::Config(page) = state.current_state() {
+        match page {
+            ConfigPage::Calibrate => fb.print(0, 0, "Calibrate Accel?"),
+            ConfigPage::Save => fb.print(0, 0, "Save and Reset? "),
+            // replaced by `render_event` once the journal is read
+            ConfigPage::Events => fb.print(0, 0, "   Event Log    "),
+        };
+        return;
+    }
+
+    if !state.accel_present() {
+        fb.print(0, 0, "    NO ACCEL    ");
+        return;
+    }
+
+    fb.print(0, 0, "X ");
+    fb.print(8, 0, "Y ");
+    fb.print(0, 1, "Z ");
+
+    let accel = state.get_accel();
+    fb.print_number(2, 0, accel.x);
+    fb.print_number(10, 0, accel.y);
+    fb.print_number(2, 1, accel.z);
+/*
+    match state.current_state() {
+        State::Idle => {
+            fb.print(0, 0, "      IDLE      ");
+        }
+        State::Soldering => {
+            fb.print(0, 0, "    SOLDERING   ");
+        }
+        State::Boost => {
+            fb.print(0, 0, "      BOOST     ");
+        }
+        State::Cooling => {
+            fb.print(0, 0, "     COOLING    ");
+        }
+        State::Sleep => {
+            fb.print(0, 0, "     zZzZzZ     ");
+        }
+        State::TemperatureControl => {
+            fb.print(0, 0, " <    200 C   > ");
+        }
+        State::Thermometer => {
+            fb.print(0, 0, "     200.1 C    ");
+        }
+        State::Config(page) => {
+            match page {
+                ConfigPage::Calibrate => {
+                    fb.print(0, 0, "Calibrate Accel?");
+                }
+                ConfigPage::Save => {
+                    fb.print(0, 0, "Save and Reset? ");
+                }
+            }
+        }
+    }
+    */
+}
+
+/// Draws the record `age` records before the newest one of the journal
+///
+/// ```text
+///   1 RUNAWAY 471C
+/// #   12     3600s
+/// ```
+pub fn render_event(fb: &mut Framebuffer, age: usize, record: Option<Record>) {
+    fb.clear();
+
+    let record = match record {
+        Some(record) => record,
+        None => {
+            fb.print(0, 0, "   NO EVENTS    ");
+            return;
+        }
+    };
+
+    let mut buffer = [b' '; 8];
+    fb.print(0, 0, format::decimal(age as i32 + 1, &mut buffer[..3]));
+    match record.event {
+        Event::Boot(cause) => {
+            fb.print(4, 0, "BOOT ");
+            fb.print(9, 0, match cause {
+                ResetCause::PowerOn => "POWER",
+                ResetCause::Pin => "PIN",
+                ResetCause::Software => "SOFT",
+                ResetCause::IndependentWatchdog | ResetCause::WindowWatchdog => "WDOG",
+                ResetCause::LowPower => "LOWPWR",
+            });
+        }
+        Event::Watchdog(task) => {
+            fb.print(4, 0, "WATCHDOG #");
+            fb.print(14, 0, format::decimal(i32::from(task), &mut buffer[..2]));
+        }
+        Event::ThermalRunaway(tip) => {
+            fb.print(4, 0, "RUNAWAY");
+            fb.print(11, 0, format::decimal(i32::from(tip), &mut buffer[..4]));
+            fb.print(15, 0, "C");
+        }
+        Event::AccelError(error) => {
+            fb.print(4, 0, match error {
+                accel::Error::NotPresent => "ACCEL GONE",
+                accel::Error::UnknownDevice(_) => "ACCEL ID",
+                accel::Error::NoData => "ACCEL NODATA",
+                accel::Error::SelfTestFailed => "ACCEL TEST",
+                accel::Error::InvalidConfig => "ACCEL CONFIG",
+            });
+            if let accel::Error::UnknownDevice(id) = error {
+                fb.print_hex(13, 0, id);
+            }
+        }
+        Event::LowVoltage(supply) => {
+            // in tenths of a volt
+            let tenths = i32::from(supply / 100);
+            fb.print(4, 0, "LOW");
+            fb.print(9, 0, format::decimal(tenths / 10, &mut buffer[..3]));
+            fb.print(12, 0, ".");
+            fb.print(13, 0, format::decimal(tenths % 10, &mut buffer[..1]));
+            fb.print(14, 0, "V");
+        }
+    }
+
+    fb.print(0, 1, "#");
+    fb.print(1, 1, format::decimal(i32::from(record.boot), &mut buffer[..5]));
+    fb.print(7, 1, format::decimal(record.uptime.min(99_999_999) as i32, &mut buffer));
+    fb.print(15, 1, "s");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_blank(columns: &[u8]) -> bool {
+        columns.iter().all(|column| *column == 0)
+    }
+
+    #[test]
+    fn print_places_glyphs_in_cells() {
+        let mut fb = Framebuffer::new();
+        fb.print(1, 1, "A");
+
+        let glyph = (b'A' - 0x20) as usize * 5;
+        let page = fb.page(1);
+        assert_eq!(page[CELL_WIDTH..CELL_WIDTH + 5], font5x7::FONT_5X7[glyph..glyph + 5]);
+        assert!(is_blank(&page[..CELL_WIDTH]));
+        assert!(is_blank(&page[CELL_WIDTH + 5..]));
+        assert!(is_blank(fb.page(0)));
+    }
+
+    #[test]
+    fn print_clips_at_the_edges() {
+        let mut fb = Framebuffer::new();
+        fb.print(COLUMNS - 1, 0, "AB").print(0, PAGES as u8, "C");
+        let glyph = (b'A' - 0x20) as usize * 5;
+        let last = WIDTH - CELL_WIDTH;
+        assert_eq!(fb.page(0)[last..last + 5], font5x7::FONT_5X7[glyph..glyph + 5]);
+        assert!(is_blank(&fb.page(0)[..last]));
+        assert!(is_blank(fb.page(1)));
+    }
+
+    #[test]
+    fn unprintable_characters_are_left_blank() {
+        let mut fb = Framebuffer::new();
+        fb.print(0, 0, "\n\u{e9}");
+        assert!(is_blank(fb.page(0)));
+    }
+
+    #[test]
+    fn pixel_addresses_pages() {
+        let mut fb = Framebuffer::new();
+        // the top row of '|' is lit, in the middle column of the cell
+        fb.print(0, 1, "|");
+        assert!(fb.pixel(2, 8));
+        assert!(!fb.pixel(2, 0));
+        assert!(!fb.pixel(0, 8));
+        fb.clear();
+        assert!(!fb.pixel(2, 8));
+    }
+
+    #[test]
+    fn empty_journal() {
+        let mut fb = Framebuffer::new();
+        render_event(&mut fb, 0, None);
+        let mut expected = Framebuffer::new();
+        expected.print(0, 0, "   NO EVENTS    ");
+        assert_eq!(fb.page(0), expected.page(0));
+        assert!(is_blank(fb.page(1)));
+    }
+}
@@ -0,0 +1,342 @@
+//! USB CDC-ACM device, the part independent of the USB peripheral
+//!
+//! The iron enumerates as a virtual serial port with the usual two
+//! interfaces: a communication interface with an interrupt endpoint for
+//! notifications, which are never sent, and a data interface with a bulk
+//! endpoint in each direction carrying the console.
+//!
+//! `Control` answers the requests on endpoint 0. The driver passes every
+//! received packet in, sends whatever `transmit` hands out and reports the
+//! completion of sent packets, the stages of the transfers are tracked in
+//! here.
+
+/// pid.codes vendor and its test product id, only meant for development
+pub const VENDOR_ID: u16 = 0x1209;
+pub const PRODUCT_ID: u16 = 0x0001;
+
+/// Packet size of endpoint 0 and of the data endpoints
+pub const MAX_PACKET: usize = 64;
+/// Packet size of the notification endpoint
+pub const NOTIFICATION_PACKET: usize = 8;
+/// Endpoint numbers
+pub const DATA_ENDPOINT: u8 = 1;
+pub const NOTIFICATION_ENDPOINT: u8 = 2;
+
+const MANUFACTURER: &str = "wose";
+const PRODUCT: &str = "TS100 console";
+
+const DEVICE_DESCRIPTOR: [u8; 18] = [
+    18, // bLength
+    1, // DEVICE
+    0x00, 0x02, // USB 2.0
+    0x02, // CDC
+    0, 0, // no subclass and protocol on device level
+    MAX_PACKET as u8,
+    VENDOR_ID as u8, (VENDOR_ID >> 8) as u8,
+    PRODUCT_ID as u8, (PRODUCT_ID >> 8) as u8,
+    0x00, 0x01, // device release 1.0
+    1, 2, 0, // manufacturer, product, no serial number
+    1, // configurations
+];
+
+const CONFIGURATION_DESCRIPTOR: [u8; 67] = [
+    9, 2, 67, 0, // CONFIGURATION, total length
+    2, // interfaces
+    1, // bConfigurationValue
+    0, // no string
+    0x80, // bus powered
+    50, // 100 mA
+    // communication interface
+    9, 4, 0, 0, 1, // INTERFACE 0, alternate 0, one endpoint
+    0x02, 0x02, 0x01, // CDC, ACM, AT commands
+    0,
+    5, 0x24, 0x00, 0x10, 0x01, // header, CDC 1.10
+    5, 0x24, 0x01, 0x00, 1, // call management over the data interface 1
+    4, 0x24, 0x02, 0x02, // ACM, line coding and control line state
+    5, 0x24, 0x06, 0, 1, // union of interface 0 and 1
+    7, 5, 0x80 | NOTIFICATION_ENDPOINT, 0x03, NOTIFICATION_PACKET as u8, 0, 255, // interrupt IN
+    // data interface
+    9, 4, 1, 0, 2, // INTERFACE 1, alternate 0, two endpoints
+    0x0A, 0, 0, // CDC data
+    0,
+    7, 5, DATA_ENDPOINT, 0x02, MAX_PACKET as u8, 0, 0, // bulk OUT
+    7, 5, 0x80 | DATA_ENDPOINT, 0x02, MAX_PACKET as u8, 0, 0, // bulk IN
+];
+
+/// US English
+const LANGUAGES: [u8; 4] = [4, 3, 0x09, 0x04];
+
+/// Standard requests
+const GET_STATUS: u8 = 0;
+const CLEAR_FEATURE: u8 = 1;
+const SET_FEATURE: u8 = 3;
+const SET_ADDRESS: u8 = 5;
+const GET_DESCRIPTOR: u8 = 6;
+const GET_CONFIGURATION: u8 = 8;
+const SET_CONFIGURATION: u8 = 9;
+const GET_INTERFACE: u8 = 10;
+const SET_INTERFACE: u8 = 11;
+/// CDC requests
+const SET_LINE_CODING: u8 = 0x20;
+const GET_LINE_CODING: u8 = 0x21;
+const SET_CONTROL_LINE_STATE: u8 = 0x22;
+const SEND_BREAK: u8 = 0x23;
+
+/// Descriptor types
+const DEVICE: u8 = 1;
+const CONFIGURATION: u8 = 2;
+const STRING: u8 = 3;
+
+/// bmRequestType bits
+const DEVICE_TO_HOST: u8 = 0x80;
+const TYPE_MASK: u8 = 0x60;
+const TYPE_STANDARD: u8 = 0x00;
+const TYPE_CLASS: u8 = 0x20;
+
+/// Longest reply, the configuration descriptor
+const BUFFER_SIZE: usize = 80;
+
+/// 115200 baud, 1 stop bit, no parity, 8 data bits, meaningless for USB but
+/// hosts insist on reading it back
+const LINE_CODING: [u8; 7] = [0x00, 0xC2, 0x01, 0x00, 0, 0, 8];
+
+/// A decoded SETUP packet
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Setup {
+    pub request_type: u8,
+    pub request: u8,
+    pub value: u16,
+    pub index: u16,
+    pub length: u16,
+}
+
+impl Setup {
+    /// `None` unless `packet` is 8 bytes long
+    pub fn parse(packet: &[u8]) -> Option<Setup> {
+        if packet.len() != 8 {
+            return None;
+        }
+
+        let half_word = |index: usize| u16::from(packet[index]) | u16::from(packet[index + 1]) << 8;
+        Some(Setup {
+            request_type: packet[0],
+            request: packet[1],
+            value: half_word(2),
+            index: half_word(4),
+            length: half_word(6),
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Stage {
+    Idle,
+    /// Sending the reply from `buffer`
+    DataIn,
+    /// Waiting for the data of a SET_LINE_CODING
+    DataOut,
+    /// A zero length packet acknowledging the request has to be sent
+    StatusIn,
+    /// The acknowledgement has been handed out and is being sent
+    StatusInSent,
+    /// Waiting for the host to acknowledge the reply
+    StatusOut,
+}
+
+/// Control endpoint state of the CDC-ACM device
+pub struct Control {
+    stage: Stage,
+    buffer: [u8; BUFFER_SIZE],
+    len: usize,
+    sent: usize,
+    /// The reply is shorter than requested and ends on a packet boundary,
+    /// a zero length packet marks its end
+    zero_length: bool,
+    /// Applied after the status stage of the SET_ADDRESS
+    address: Option<u8>,
+    configuration: u8,
+    line_coding: [u8; 7],
+    /// Data terminal ready, set while a program has the port open
+    dtr: bool,
+}
+
+impl Control {
+    #[cfg_attr(clippy, allow(clippy::new_without_default))]
+    pub const fn new() -> Self {
+        Control {
+            stage: Stage::Idle,
+            buffer: [0; BUFFER_SIZE],
+            len: 0,
+            sent: 0,
+            zero_length: false,
+            address: None,
+            configuration: 0,
+            line_coding: LINE_CODING,
+            dtr: false,
+        }
+    }
+
+    /// Forgets everything after a bus reset
+    pub fn reset(&mut self) {
+        *self = Control::new();
+    }
+
+    /// `true` if a program on the host opened the serial port
+    pub fn connected(&self) -> bool {
+        self.configuration != 0 && self.dtr
+    }
+
+    /// Handles a SETUP packet, `false` if the request isn't supported and
+    /// the endpoint has to stall
+    #[cfg_attr(clippy, allow(clippy::manual_is_multiple_of))]
+    pub fn setup(&mut self, packet: &[u8]) -> bool {
+        self.stage = Stage::Idle;
+        self.len = 0;
+        self.sent = 0;
+
+        let setup = match Setup::parse(packet) {
+            Some(setup) => setup,
+            None => return false,
+        };
+
+        let handled = match setup.request_type & TYPE_MASK {
+            TYPE_STANDARD => self.standard(&setup),
+            TYPE_CLASS => self.class(&setup),
+            _ => false,
+        };
+        if !handled {
+            self.stage = Stage::Idle;
+            return false;
+        }
+
+        if setup.request_type & DEVICE_TO_HOST != 0 {
+            let requested = usize::from(setup.length);
+            self.zero_length = self.len < requested && self.len % MAX_PACKET == 0;
+            self.len = self.len.min(requested);
+            self.stage = Stage::DataIn;
+        } else if self.stage != Stage::DataOut {
+            self.stage = Stage::StatusIn;
+        }
+        true
+    }
+
+    /// Handles a packet received in the data or status stage
+    pub fn out_packet(&mut self, data: &[u8]) {
+        match self.stage {
+            Stage::DataOut => {
+                if data.len() >= self.line_coding.len() {
+                    let len = self.line_coding.len();
+                    self.line_coding.copy_from_slice(&data[..len]);
+                }
+                self.stage = Stage::StatusIn;
+            }
+            Stage::StatusOut => self.stage = Stage::Idle,
+            _ => {}
+        }
+    }
+
+    /// Writes the next packet to send into `packet`, `None` if nothing has
+    /// to be sent right now
+    pub fn transmit(&mut self, packet: &mut [u8; MAX_PACKET]) -> Option<usize> {
+        match self.stage {
+            Stage::DataIn => {
+                let count = (self.len - self.sent).min(MAX_PACKET);
+                packet[..count].copy_from_slice(&self.buffer[self.sent..self.sent + count]);
+                self.sent += count;
+
+                let last = count < MAX_PACKET || (self.sent == self.len && !self.zero_length);
+                if last {
+                    self.stage = Stage::StatusOut;
+                }
+                Some(count)
+            }
+            Stage::StatusIn => {
+                self.stage = Stage::StatusInSent;
+                Some(0)
+            }
+            _ => None,
+        }
+    }
+
+    /// Called once a packet handed out by `transmit` has been sent, returns
+    /// the address the device has to use from now on
+    pub fn in_complete(&mut self) -> Option<u8> {
+        if self.stage == Stage::StatusInSent {
+            self.stage = Stage::Idle;
+            return self.address.take();
+        }
+        None
+    }
+
+    fn standard(&mut self, setup: &Setup) -> bool {
+        match setup.request {
+            GET_STATUS => self.reply(&[0, 0]),
+            CLEAR_FEATURE | SET_FEATURE | SET_INTERFACE => true,
+            SET_ADDRESS => {
+                self.address = Some((setup.value & 0x7f) as u8);
+                true
+            }
+            GET_DESCRIPTOR => self.descriptor((setup.value >> 8) as u8, setup.value as u8),
+            GET_CONFIGURATION => {
+                let configuration = self.configuration;
+                self.reply(&[configuration])
+            }
+            SET_CONFIGURATION if setup.value <= 1 => {
+                self.configuration = setup.value as u8;
+                self.dtr = false;
+                true
+            }
+            GET_INTERFACE => self.reply(&[0]),
+            _ => false,
+        }
+    }
+
+    fn class(&mut self, setup: &Setup) -> bool {
+        match setup.request {
+            SET_LINE_CODING => {
+                self.stage = Stage::DataOut;
+                true
+            }
+            GET_LINE_CODING => {
+                let line_coding = self.line_coding;
+                self.reply(&line_coding)
+            }
+            SET_CONTROL_LINE_STATE => {
+                self.dtr = setup.value & 1 != 0;
+                true
+            }
+            SEND_BREAK => true,
+            _ => false,
+        }
+    }
+
+    fn descriptor(&mut self, kind: u8, index: u8) -> bool {
+        match (kind, index) {
+            (DEVICE, 0) => self.reply(&DEVICE_DESCRIPTOR),
+            (CONFIGURATION, 0) => self.reply(&CONFIGURATION_DESCRIPTOR),
+            (STRING, 0) => self.reply(&LANGUAGES),
+            (STRING, 1) => self.string(MANUFACTURER),
+            (STRING, 2) => self.string(PRODUCT),
+            _ => false,
+        }
+    }
+
+    fn reply(&mut self, data: &[u8]) -> bool {
+        self.buffer[..data.len()].copy_from_slice(data);
+        self.len = data.len();
+        true
+    }
+
+    /// String descriptor of ASCII `text` in UTF-16
+    fn string(&mut self, text: &str) -> bool {
+        let len = 2 + 2 * text.len();
+        self.buffer[0] = len as u8;
+        self.buffer[1] = STRING;
+        for (index, byte) in text.bytes().enumerate() {
+            self.buffer[2 + 2 * index] = byte;
+            self.buffer[3 + 2 * index] = 0;
+        }
+        self.len = len;
+        true
+    }
+}
@@ -0,0 +1,167 @@
+//! Motion detection on accelerometer traces
+//!
+//! The traces in `motion/` are 100 Hz samples in milli-g of the situations
+//! the detector has to tell apart, their headers describe how they were
+//! made. They include gravity like the unfiltered, offset corrected samples
+//! the firmware feeds the detector. They are synthesized, recordings of the
+//! sensor configured as in the firmware's `init_accel` should replace them
+//! and the margins of `SENSITIVITY_DEFAULT` be checked against those.
+
+extern crate ts100_core;
+
+use ts100_core::accel::Accel;
+use ts100_core::motion::{MotionDetector, MotionEvent, SENSITIVITY_DEFAULT, SENSITIVITY_MAX,
+                         SENSITIVITY_MIN};
+
+const STAND: &str = include_str!("motion/stand.csv");
+const HAND: &str = include_str!("motion/hand.csv");
+const SOLDERING: &str = include_str!("motion/soldering.csv");
+
+/// Samples per second of the traces
+const RATE: usize = 100;
+/// Samples the firmware reads from the FIFO at once
+const BURST: usize = 16;
+
+fn parse(trace: &str) -> Vec<Accel> {
+    trace.lines()
+        .filter(|line| !line.starts_with('#'))
+        .map(|line| {
+            let axes: Vec<i16> = line.split(',').map(|axis| axis.parse().unwrap()).collect();
+            Accel {
+                x: axes[0],
+                y: axes[1],
+                z: axes[2],
+            }
+        })
+        .collect()
+}
+
+/// Events and the index of the sample raising them, fed one by one
+fn events(trace: &str, sensitivity: u8) -> Vec<(usize, MotionEvent)> {
+    let mut detector = MotionDetector::new(sensitivity);
+    parse(trace)
+        .iter()
+        .enumerate()
+        .filter_map(|(index, sample)| detector.update(&[*sample]).map(|event| (index, event)))
+        .collect()
+}
+
+fn sensitivities() -> Vec<u8> {
+    (SENSITIVITY_MIN..SENSITIVITY_MAX + 1).collect()
+}
+
+#[test]
+fn resting_on_the_stand_is_still() {
+    for sensitivity in sensitivities() {
+        assert_eq!(events(STAND, sensitivity), vec![], "sensitivity {}", sensitivity);
+    }
+}
+
+#[test]
+fn held_in_hand_keeps_moving() {
+    // lifted at 2 s, the hand's tremor never lets it stop
+    for sensitivity in sensitivities() {
+        let events = events(HAND, sensitivity);
+        assert_eq!(events.len(), 1, "sensitivity {}: {:?}", sensitivity, events);
+        assert_eq!(events[0].1, MotionEvent::Started);
+        assert!(events[0].0 >= 2 * RATE && events[0].0 < 2 * RATE + RATE / 10,
+                "sensitivity {}: started at {}",
+                sensitivity,
+                events[0].0);
+    }
+}
+
+#[test]
+fn soldering_moves_until_back_on_the_stand() {
+    // lifted at 2 s, strokes and holds until it's back on the stand at 24.4 s
+    for sensitivity in sensitivities() {
+        let events = events(SOLDERING, sensitivity);
+        assert_eq!(events.iter().map(|&(_, event)| event).collect::<Vec<_>>(),
+                   vec![MotionEvent::Started, MotionEvent::Stopped],
+                   "sensitivity {}",
+                   sensitivity);
+        assert!(events[0].0 >= 2 * RATE && events[0].0 < 2 * RATE + RATE / 10);
+        assert!(events[1].0 >= 24 * RATE + 4 * RATE / 10 && events[1].0 < 25 * RATE + RATE / 2,
+                "sensitivity {}: stopped at {}",
+                sensitivity,
+                events[1].0);
+    }
+}
+
+#[test]
+fn higher_sensitivity_starts_earlier_and_stops_later() {
+    for trace in &[HAND, SOLDERING] {
+        let runs: Vec<_> = sensitivities().into_iter().map(|s| events(trace, s)).collect();
+        for pair in runs.windows(2) {
+            assert!(pair[1][0].0 <= pair[0][0].0, "started {:?}", pair);
+            if pair[0].len() > 1 {
+                assert!(pair[1][1].0 >= pair[0][1].0, "stopped {:?}", pair);
+            }
+        }
+        // the extremes differ, the setting isn't ignored
+        let (least, most) = (&runs[0], &runs[runs.len() - 1]);
+        assert!(most[0].0 < least[0].0 || (most.len() > 1 && most[1].0 > least[1].0));
+    }
+}
+
+#[test]
+fn bursts_report_the_same_events() {
+    // the firmware drains the FIFO in bursts, `update` reports the last
+    // event of each
+    for trace in &[STAND, HAND, SOLDERING] {
+        let samples = parse(trace);
+        let mut detector = MotionDetector::new(SENSITIVITY_DEFAULT);
+        let bursts: Vec<_> = samples.chunks(BURST)
+            .enumerate()
+            .filter_map(|(index, burst)| detector.update(burst).map(|event| (index, event)))
+            .collect();
+        let expected: Vec<_> = events(trace, SENSITIVITY_DEFAULT)
+            .into_iter()
+            .map(|(index, event)| (index / BURST, event))
+            .collect();
+        assert_eq!(bursts, expected);
+    }
+}
+
+#[test]
+fn out_of_range_sensitivities_are_clamped() {
+    assert_eq!(MotionDetector::new(0).threshold(), MotionDetector::new(SENSITIVITY_MIN).threshold());
+    for &sensitivity in &[SENSITIVITY_MAX + 1, 200, 255] {
+        let detector = MotionDetector::new(sensitivity);
+        assert_eq!(detector.threshold(), MotionDetector::new(SENSITIVITY_MAX).threshold());
+        assert_eq!(events(SOLDERING, sensitivity), events(SOLDERING, SENSITIVITY_MAX));
+    }
+
+    let mut detector = MotionDetector::new(5);
+    detector.set_sensitivity(255);
+    assert_eq!(detector.threshold(), MotionDetector::new(SENSITIVITY_MAX).threshold());
+}
+
+#[test]
+fn default_sensitivity_keeps_a_margin() {
+    // the default starts well above the noise on the stand and releases well
+    // below the quietest hold while soldering
+    let mut detector = MotionDetector::new(SENSITIVITY_DEFAULT);
+    let mut resting = 0;
+    for sample in parse(STAND) {
+        detector.update(&[sample]);
+        resting = resting.max(detector.activity());
+    }
+    assert!(detector.threshold() >= 4 * resting,
+            "threshold {}, resting {}",
+            detector.threshold(),
+            resting);
+
+    let mut detector = MotionDetector::new(SENSITIVITY_DEFAULT);
+    let mut holding = u32::MAX;
+    let mut release = 0;
+    for (index, sample) in parse(SOLDERING).into_iter().enumerate() {
+        detector.update(&[sample]);
+        // from settled in the hand until put down at 24 s
+        if (3 * RATE..24 * RATE).contains(&index) {
+            holding = holding.min(detector.activity());
+            release = detector.threshold() / 2;
+        }
+    }
+    assert!(2 * release <= holding, "release {}, holding {}", release, holding);
+}
@@ -0,0 +1,16 @@
+/* STM32F103T8 of the TS100: 64 KiB flash and 20 KiB RAM
+ *
+ * The top of the flash is reserved and left out of FLASH, the linker fails
+ * instead of placing the firmware there:
+ *
+ *   0x0800F000  event journal, two pages, src/journal.rs
+ *   0x0800F800  crash record, src/crash.rs
+ *   0x0800FC00  settings, src/settings.rs
+ *
+ * ld looks for memory.x in the crate directory before the one the blue-pill
+ * crate puts on the search path. */
+MEMORY
+{
+  FLASH : ORIGIN = 0x08000000, LENGTH = 60K
+  RAM : ORIGIN = 0x20000000, LENGTH = 20K
+}
@@ -0,0 +1,78 @@
+//! Temperature and supply voltage sampling with ADC1
+//!
+//! The thermocouple amplifier of the tip (PB0, channel 8), the TMP36 next
+//! to the tip connector (PA5, channel 5) and the supply voltage divider (PB1,
+//! channel 9) are converted as an injected sequence started by software.
+//! Once all results are in, the end of injected conversion flag raises the
+//! `ADC1_2` interrupt.
+
+use blue_pill::stm32f103xx::ADC1;
+use cortex_m;
+use ts100_core::temperature::Sample;
+
+const TIP_CHANNEL: u32 = 8;
+const AMBIENT_CHANNEL: u32 = 5;
+const SUPPLY_CHANNEL: u32 = 9;
+
+/// 239.5 cycles, the TMP36 output is fairly high impedance
+const SAMPLE_TIME: u32 = 0b111;
+
+const SR_JEOC: u32 = 1 << 2;
+const CR1_JEOCIE: u32 = 1 << 7;
+const CR1_SCAN: u32 = 1 << 8;
+const CR2_ADON: u32 = 1 << 0;
+const CR2_CAL: u32 = 1 << 2;
+const CR2_RSTCAL: u32 = 1 << 3;
+const CR2_JEXTSEL_JSWSTART: u32 = 0b111 << 12;
+const CR2_JEXTTRIG: u32 = 1 << 15;
+const CR2_JSWSTART: u32 = 1 << 21;
+/// Three conversions, a sequence of length n uses JSQ(5-n) to JSQ4
+const JSQR_JL_3: u32 = 2 << 20;
+
+/// Powers up and calibrates the ADC, the ADC clock and the analog inputs
+/// have to be configured already
+pub fn init(adc1: &ADC1) {
+    adc1.cr2.write(|w| unsafe { w.bits(CR2_ADON) });
+    // at least two ADC clock cycles before starting the calibration
+    for _ in 0..100 {
+        cortex_m::asm::nop();
+    }
+
+    adc1.cr2.modify(|r, w| unsafe { w.bits(r.bits() | CR2_RSTCAL) });
+    while adc1.cr2.read().bits() & CR2_RSTCAL != 0 {}
+    adc1.cr2.modify(|r, w| unsafe { w.bits(r.bits() | CR2_CAL) });
+    while adc1.cr2.read().bits() & CR2_CAL != 0 {}
+
+    adc1.smpr2.write(|w| unsafe {
+        w.bits(SAMPLE_TIME << (3 * TIP_CHANNEL) | SAMPLE_TIME << (3 * AMBIENT_CHANNEL)
+               | SAMPLE_TIME << (3 * SUPPLY_CHANNEL))
+    });
+    adc1.jsqr.write(|w| unsafe {
+        w.bits(JSQR_JL_3 | TIP_CHANNEL << 5 | AMBIENT_CHANNEL << 10 | SUPPLY_CHANNEL << 15)
+    });
+    adc1.cr1.write(|w| unsafe { w.bits(CR1_SCAN | CR1_JEOCIE) });
+    adc1.cr2.write(|w| unsafe {
+        w.bits(CR2_ADON | CR2_JEXTSEL_JSWSTART | CR2_JEXTTRIG)
+    });
+}
+
+/// Starts converting the tip and ambient temperature and the supply voltage
+pub fn start(adc1: &ADC1) {
+    adc1.cr2.modify(|r, w| unsafe { w.bits(r.bits() | CR2_JSWSTART) });
+}
+
+/// Returns the results of a finished conversion and clears the interrupt
+pub fn take(adc1: &ADC1) -> Option<Sample> {
+    if adc1.sr.read().bits() & SR_JEOC == 0 {
+        return None;
+    }
+
+    let sample = Sample {
+        tip: adc1.jdr1.read().bits() as u16,
+        ambient: adc1.jdr2.read().bits() as u16,
+        supply: adc1.jdr3.read().bits() as u16,
+    };
+    adc1.sr.modify(|r, w| unsafe { w.bits(r.bits() & !SR_JEOC) });
+
+    Some(sample)
+}
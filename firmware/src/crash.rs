@@ -0,0 +1,228 @@
+//! Panic and hard fault handling
+//!
+//! Both handlers force the heater off before anything else, then store a
+//! `Crash` record in a reserved flash page and show it on the OLED. The
+//! watchdog isn't fed anymore and resets the iron, the record is shown
+//! again on the next boot until it's cleared.
+
+use blue_pill::stm32f103xx::{FLASH, I2C1};
+use core::{fmt, ptr, str};
+use cortex_m;
+use fault::{self, Fault};
+use flash;
+use heater;
+use i2c;
+use ts100_core::i2c::I2cBus;
+use ts100_core::settings;
+use ts100_core::ssd1306::SSD1306;
+
+/// Second to last 1 KiB page, right below the settings and excluded from
+/// FLASH in memory.x as well
+pub const CRASH_ADDRESS: u32 = 0x0800_F800;
+
+const MAGIC: u16 = 0xC4A5;
+/// Bytes of the file name kept, the directory is dropped
+const FILE_LEN: usize = 16;
+/// Half-words of the encoded record including magic and checksum
+const ENCODED_LEN: usize = 2 + 2 + 3 * 2 + FILE_LEN / 2 + 1;
+
+/// System control block fault status registers
+const CFSR: u32 = 0xE000_ED28;
+const HFSR: u32 = 0xE000_ED2C;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Kind {
+    Panic = 1,
+    HardFault = 2,
+}
+
+#[derive(Clone, Copy)]
+pub struct Crash {
+    pub kind: Kind,
+    /// Source line of a panic
+    pub line: u32,
+    /// Source file name of a panic, padded with zeros
+    pub file: [u8; FILE_LEN],
+    /// Faulting instruction of a hard fault
+    pub pc: u32,
+    /// Configurable and hard fault status registers
+    pub cfsr: u32,
+    pub hfsr: u32,
+}
+
+/// Registers pushed on the stack on exception entry
+#[repr(C)]
+pub struct StackFrame {
+    pub r0: u32,
+    pub r1: u32,
+    pub r2: u32,
+    pub r3: u32,
+    pub r12: u32,
+    pub lr: u32,
+    pub pc: u32,
+    pub xpsr: u32,
+}
+
+impl Crash {
+    fn new(kind: Kind) -> Self {
+        Crash {
+            kind: kind,
+            line: 0,
+            file: [0; FILE_LEN],
+            pc: 0,
+            cfsr: read_register(CFSR),
+            hfsr: read_register(HFSR),
+        }
+    }
+
+    /// Reads the record of the last crash
+    pub fn load() -> Option<Self> {
+        let mut data = [0; ENCODED_LEN];
+        flash::read(CRASH_ADDRESS, &mut data);
+        Crash::decode(&data)
+    }
+
+    fn save(&self, flash: &FLASH) {
+        flash::unlock(flash);
+        flash::erase_page(flash, CRASH_ADDRESS);
+        flash::program(flash, CRASH_ADDRESS, &self.encode());
+        flash::lock(flash);
+    }
+
+    /// Erases the record once it has been reported
+    pub fn clear(flash: &FLASH) {
+        flash::unlock(flash);
+        flash::erase_page(flash, CRASH_ADDRESS);
+        flash::lock(flash);
+    }
+
+    /// Shows the record on both lines of the OLED
+    ///
+    /// ```text
+    /// PANIC      123       FAULT PC08000D3E
+    /// state.rs             CFSR 00000400
+    /// ```
+    pub fn show<B: I2cBus>(&self, oled: &SSD1306<B>) {
+        oled.print(0, 0, "                ");
+        oled.print(0, 1, "                ");
+
+        match self.kind {
+            Kind::Panic => {
+                oled.print(0, 0, "PANIC");
+                oled.print_number(10, 0, self.line as i16);
+                let len = self.file.iter().position(|&b| b == 0).unwrap_or(FILE_LEN);
+                if let Ok(file) = str::from_utf8(&self.file[..len]) {
+                    oled.print(0, 1, file);
+                }
+            }
+            Kind::HardFault => {
+                oled.print(0, 0, "FAULT PC");
+                print_hex32(oled, 8, 0, self.pc);
+                oled.print(0, 1, "CFSR");
+                print_hex32(oled, 5, 1, self.cfsr);
+            }
+        }
+    }
+
+    fn encode(&self) -> [u16; ENCODED_LEN] {
+        let mut data = [0; ENCODED_LEN];
+        data[0] = MAGIC;
+        data[1] = self.kind as u16;
+        for (i, word) in [self.line, self.pc, self.cfsr, self.hfsr].iter().enumerate() {
+            data[2 + 2 * i] = *word as u16;
+            data[3 + 2 * i] = (*word >> 16) as u16;
+        }
+        for (i, pair) in self.file.chunks(2).enumerate() {
+            data[10 + i] = u16::from(pair[0]) | u16::from(pair[1]) << 8;
+        }
+        data[ENCODED_LEN - 1] = settings::checksum(&data[..ENCODED_LEN - 1]);
+        data
+    }
+
+    fn decode(data: &[u16; ENCODED_LEN]) -> Option<Self> {
+        if data[0] != MAGIC || data[ENCODED_LEN - 1] != settings::checksum(&data[..ENCODED_LEN - 1]) {
+            return None;
+        }
+
+        let kind = match data[1] {
+            1 => Kind::Panic,
+            2 => Kind::HardFault,
+            _ => return None,
+        };
+        let word = |i: usize| u32::from(data[2 + 2 * i]) | u32::from(data[3 + 2 * i]) << 16;
+        let mut file = [0; FILE_LEN];
+        for (i, pair) in file.chunks_mut(2).enumerate() {
+            pair[0] = data[10 + i] as u8;
+            pair[1] = (data[10 + i] >> 8) as u8;
+        }
+
+        Some(Crash {
+            kind: kind,
+            line: word(0),
+            file: file,
+            pc: word(1),
+            cfsr: word(2),
+            hfsr: word(3),
+        })
+    }
+}
+
+fn read_register(address: u32) -> u32 {
+    unsafe { ptr::read_volatile(address as *const u32) }
+}
+
+fn print_hex32<B: I2cBus>(oled: &SSD1306<B>, x: u8, y: u8, value: u32) {
+    for i in 0..4 {
+        oled.print_hex(x + 2 * i as u8, y, (value >> (24 - 8 * i)) as u8);
+    }
+}
+
+/// Makes the iron safe, records and shows the crash and waits for the
+/// watchdog
+fn crash(crash: &Crash, fault: Fault) -> ! {
+    heater::force_off();
+    cortex_m::interrupt::disable();
+    fault::record(fault);
+
+    // the peripherals are taken without tokens, nothing else runs anymore
+    let flash = unsafe { &*FLASH.get() };
+    crash.save(flash);
+    let bus = i2c::Bus(unsafe { &*I2C1.get() });
+    crash.show(&SSD1306(::OLED_ADDR, &bus));
+
+    loop {
+        heater::force_off();
+    }
+}
+
+#[lang = "panic_fmt"]
+#[no_mangle]
+pub extern "C" fn rust_begin_unwind(_args: fmt::Arguments, file: &'static str, line: u32) -> ! {
+    heater::force_off();
+
+    let mut record = Crash::new(Kind::Panic);
+    record.line = line;
+    let name = file.rsplit('/').next().unwrap_or(file).as_bytes();
+    let len = name.len().min(FILE_LEN);
+    record.file[..len].copy_from_slice(&name[..len]);
+
+    crash(&record, Fault::Panic)
+}
+
+/// Passes the stack frame pushed on exception entry to `hard_fault`
+#[naked]
+#[export_name = "HARD_FAULT"]
+pub unsafe extern "C" fn hard_fault_entry() {
+    asm!("mrs r0, MSP
+          b hard_fault" :::: "volatile");
+}
+
+#[no_mangle]
+pub extern "C" fn hard_fault(frame: &StackFrame) -> ! {
+    heater::force_off();
+
+    let mut record = Crash::new(Kind::HardFault);
+    record.pc = frame.pc;
+
+    crash(&record, Fault::HardFault)
+}
@@ -0,0 +1,110 @@
+//! Fault log kept in the backup registers
+//!
+//! The backup data registers survive every reset except a loss of power, so
+//! faults recorded right before a reset can be reported on the next boot.
+//! The registers are accessed by address, recording has to work from any
+//! context.
+//!
+//! | Register | Content                           |
+//! |----------|-----------------------------------|
+//! | DR1      | `MAGIC` if the log is initialized |
+//! | DR2      | last fault, kind and detail byte  |
+//! | DR3      | watchdog resets since power on    |
+
+use blue_pill::stm32f103xx::{PWR, RCC};
+use core::ptr;
+use rcc::ResetCause;
+use watchdog::Task;
+
+/// Address of DR1, DRn is at `BKP_DR1 + 4 * (n - 1)`
+const BKP_DR1: u32 = 0x4000_6C04;
+
+const MAGIC: u16 = 0x7510;
+
+const MAGIC_REGISTER: u32 = 1;
+const FAULT_REGISTER: u32 = 2;
+const WATCHDOG_RESETS_REGISTER: u32 = 3;
+
+const KIND_NONE: u16 = 0;
+const KIND_WATCHDOG: u16 = 1;
+const KIND_PANIC: u16 = 2;
+const KIND_HARD_FAULT: u16 = 3;
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum Fault {
+    /// The watchdog reset the iron after `Task` missed its deadline
+    Watchdog(Task),
+    /// The firmware panicked, see `crash` for details
+    Panic,
+    /// The core raised a hard fault, see `crash` for details
+    HardFault,
+}
+
+impl Fault {
+    fn encode(&self) -> u16 {
+        match *self {
+            Fault::Watchdog(task) => KIND_WATCHDOG << 8 | task as u16,
+            Fault::Panic => KIND_PANIC << 8,
+            Fault::HardFault => KIND_HARD_FAULT << 8,
+        }
+    }
+
+    fn decode(value: u16) -> Option<Fault> {
+        match value >> 8 {
+            KIND_WATCHDOG => Task::from_index(value & 0xff).map(Fault::Watchdog),
+            KIND_PANIC => Some(Fault::Panic),
+            KIND_HARD_FAULT => Some(Fault::HardFault),
+            _ => None,
+        }
+    }
+}
+
+fn read(register: u32) -> u16 {
+    unsafe { ptr::read_volatile((BKP_DR1 + 4 * (register - 1)) as *const u32) as u16 }
+}
+
+fn write(register: u32, value: u16) {
+    unsafe {
+        ptr::write_volatile((BKP_DR1 + 4 * (register - 1)) as *mut u32, u32::from(value));
+    }
+}
+
+/// Enables write access to the backup domain and initializes the log after
+/// a loss of power
+pub fn init(rcc: &RCC, pwr: &PWR) {
+    rcc.apb1enr.modify(|_, w| w.pwren().enabled().bkpen().enabled());
+    pwr.cr.modify(|_, w| w.dbp().set_bit());
+
+    if read(MAGIC_REGISTER) != MAGIC {
+        write(FAULT_REGISTER, KIND_NONE);
+        write(WATCHDOG_RESETS_REGISTER, 0);
+        write(MAGIC_REGISTER, MAGIC);
+    }
+}
+
+/// Records `fault` as the last fault, needs `init`
+pub fn record(fault: Fault) {
+    write(FAULT_REGISTER, fault.encode());
+}
+
+/// Counts the reset and returns the recorded fault which caused it
+///
+/// Faults only recorded before a reset by something else are dropped.
+pub fn boot(cause: ResetCause) -> Option<Fault> {
+    let fault = Fault::decode(read(FAULT_REGISTER));
+    write(FAULT_REGISTER, KIND_NONE);
+
+    match cause {
+        ResetCause::IndependentWatchdog => {
+            let resets = read(WATCHDOG_RESETS_REGISTER);
+            write(WATCHDOG_RESETS_REGISTER, resets.saturating_add(1));
+            fault
+        }
+        _ => None,
+    }
+}
+
+/// Watchdog resets since the last loss of power
+pub fn watchdog_resets() -> u16 {
+    read(WATCHDOG_RESETS_REGISTER)
+}
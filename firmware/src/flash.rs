@@ -0,0 +1,60 @@
+//! Programming of the internal flash
+//!
+//! The flash is organized in 1 KiB pages which have to be erased (all bits
+//! set) before they can be programmed one half-word at a time.
+
+use blue_pill::stm32f103xx::FLASH;
+use core::ptr;
+
+/// Size of an erasable page
+pub const PAGE_SIZE: u32 = 1024;
+
+const KEY1: u32 = 0x4567_0123;
+const KEY2: u32 = 0xCDEF_89AB;
+
+fn wait_ready(flash: &FLASH) {
+    while flash.sr.read().bsy().bit_is_set() {}
+}
+
+/// Unlocks the flash program and erase controller
+pub fn unlock(flash: &FLASH) {
+    if flash.cr.read().lock().bit_is_set() {
+        flash.keyr.write(|w| unsafe { w.bits(KEY1) });
+        flash.keyr.write(|w| unsafe { w.bits(KEY2) });
+    }
+}
+
+pub fn lock(flash: &FLASH) {
+    flash.cr.modify(|_, w| w.lock().set_bit());
+}
+
+/// Erases the page containing `address`, needs to be unlocked
+pub fn erase_page(flash: &FLASH, address: u32) {
+    wait_ready(flash);
+    flash.cr.modify(|_, w| w.per().set_bit());
+    flash.ar.write(|w| unsafe { w.bits(address) });
+    flash.cr.modify(|_, w| w.strt().set_bit());
+    wait_ready(flash);
+    flash.cr.modify(|_, w| w.per().clear_bit());
+}
+
+/// Programs `data` starting at the half-word aligned `address`, needs to be
+/// unlocked and erased
+pub fn program(flash: &FLASH, address: u32, data: &[u16]) {
+    wait_ready(flash);
+    flash.cr.modify(|_, w| w.pg().set_bit());
+    for (offset, half_word) in data.iter().enumerate() {
+        unsafe {
+            ptr::write_volatile((address + 2 * offset as u32) as *mut u16, *half_word);
+        }
+        wait_ready(flash);
+    }
+    flash.cr.modify(|_, w| w.pg().clear_bit());
+}
+
+/// Reads half-words starting at `address`
+pub fn read(address: u32, data: &mut [u16]) {
+    for (offset, half_word) in data.iter_mut().enumerate() {
+        *half_word = unsafe { ptr::read_volatile((address + 2 * offset as u32) as *const u16) };
+    }
+}
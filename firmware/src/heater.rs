@@ -0,0 +1,32 @@
+//! Heater MOSFET driven by PB4, high turns the tip on
+//!
+//! PB4 doubles as JTAG NJTRST which is released by the SWD only debug
+//! configuration in `init`.
+//!
+//! The tip temperature conversion isn't calibrated yet (see
+//! `ts100_core::temperature`), so the firmware only ever switches the MOSFET
+//! off.
+
+use blue_pill::stm32f103xx::GPIOB;
+use core::ptr;
+
+/// GPIOB_BSRR and the reset bit of PB4
+const GPIOB_BSRR: u32 = 0x4001_0C10;
+const BR4: u32 = 1 << (16 + 4);
+
+/// Configures PB4 as push pull output with the heater off
+pub fn init(gpiob: &GPIOB) {
+    off(gpiob);
+    gpiob.crl.modify(|_, w| w.mode4().output2().cnf4().push());
+}
+
+pub fn off(gpiob: &GPIOB) {
+    gpiob.bsrr.write(|w| w.br4().set_bit());
+}
+
+/// Turns the heater off without a `GPIOB` token, for fault handlers
+pub fn force_off() {
+    unsafe {
+        ptr::write_volatile(GPIOB_BSRR as *mut u32, BR4);
+    }
+}
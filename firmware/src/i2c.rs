@@ -1,4 +1,90 @@
 use blue_pill::stm32f103xx::I2C1;
+use cortex_m;
+use rcc::Clocks;
+use ts100_core::i2c::I2cBus;
+
+/// The I2C1 peripheral as bus of the drivers in `ts100_core`
+pub struct Bus<'a>(pub &'a I2C1);
+
+impl<'a> I2cBus for Bus<'a> {
+    fn read(&self, slave: u8, register: u8, bytes: &mut [u8]) {
+        read(self.0, slave, register, bytes)
+    }
+
+    fn write(&self, slave: u8, register: u8, value: u8) {
+        write(self.0, slave, register, value)
+    }
+
+    fn probe(&self, slave: u8) -> bool {
+        probe(self.0, slave)
+    }
+
+    fn delay(&self, cycles: u32) {
+        for _ in 0..cycles {
+            cortex_m::asm::nop();
+        }
+    }
+}
+
+/// Sets up standard mode (100 kHz) master operation for the APB1 clock
+pub fn init(i2c1: &I2C1, clocks: &Clocks) {
+    i2c1.cr1.write(|w| w.pe().clear_bit());
+
+    i2c1.cr2.modify(|_, w| unsafe { w.freq().bits(clocks.i2c_freq()) });
+    i2c1.trise.modify(|_, w| unsafe { w.trise().bits(clocks.i2c_trise()) });
+    i2c1.ccr.modify(|_, w| unsafe {
+        w.f_s().clear_bit().duty().clear_bit().ccr().bits(clocks.i2c_ccr())
+    });
+
+    i2c1.cr1.modify(|_, w| {
+        w.nostretch()
+            .clear_bit()
+            .ack()
+            .set_bit()
+            .smbus()
+            .clear_bit()
+    });
+
+    i2c1.cr1.write(|w| w.pe().set_bit());
+    i2c1.oar1.write(|w| unsafe {
+        w.addmode()
+            .clear_bit()
+            .add0()
+            .clear_bit()
+            .add7()
+            .bits(0)
+            .add10()
+            .bits(0)
+    });
+}
+
+pub fn probe(i2c1: &I2C1, slave: u8) -> bool {
+    while i2c1.sr2.read().busy().bit_is_set() {}
+
+    i2c1.cr1.modify(|_, w| w.start().set_bit());
+    while i2c1.sr1.read().sb().bit_is_clear() {}
+
+    i2c1.dr.write(|w| unsafe { w.dr().bits(slave << 1) });
+    // wait for either ACK (ADDR) or NACK (AF)
+    let ack = loop {
+        let sr1 = i2c1.sr1.read();
+        if sr1.addr().bit_is_set() {
+            break true;
+        }
+        if sr1.af().bit_is_set() {
+            break false;
+        }
+    };
+
+    // clear ADDR and AF
+    let _ = i2c1.sr2.read().bits();
+    i2c1.sr1.modify(|_, w| w.af().clear_bit());
+
+    i2c1.cr1.modify(|_, w| w.stop().set_bit());
+    while i2c1.cr1.read().stop().bit_is_set() {}
+
+    ack
+}
 
 pub fn read(i2c1: &I2C1, slave: u8, register: u8, bytes: &mut [u8]) {
     // wait for idle i2c interface
@@ -0,0 +1,39 @@
+//! Flash pages of the event journal
+//!
+//! The ring of `ts100_core::journal` lives in the two 1 KiB pages right
+//! below the crash record.
+
+use blue_pill::stm32f103xx::FLASH;
+use flash;
+use ts100_core::journal::{Flash, PAGES, PAGE_LEN};
+
+/// First page of the journal, the firmware must stay below
+pub const JOURNAL_ADDRESS: u32 = 0x0800_F000;
+
+/// The journal pages, unlocked for every erase and program
+pub struct JournalFlash<'a>(pub &'a FLASH);
+
+fn address(page: usize, offset: usize) -> u32 {
+    debug_assert!(page < PAGES && offset < PAGE_LEN);
+    JOURNAL_ADDRESS + page as u32 * flash::PAGE_SIZE + 2 * offset as u32
+}
+
+impl<'a> Flash for JournalFlash<'a> {
+    fn read(&self, page: usize, offset: usize) -> u16 {
+        let mut data = [0];
+        flash::read(address(page, offset), &mut data);
+        data[0]
+    }
+
+    fn erase(&mut self, page: usize) {
+        flash::unlock(self.0);
+        flash::erase_page(self.0, address(page, 0));
+        flash::lock(self.0);
+    }
+
+    fn program(&mut self, page: usize, offset: usize, value: u16) {
+        flash::unlock(self.0);
+        flash::program(self.0, address(page, offset), &[value]);
+        flash::lock(self.0);
+    }
+}
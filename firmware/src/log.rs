@@ -0,0 +1,138 @@
+//! Backends of the log
+//!
+//! Tasks log with the macros of `ts100_core::log` into `LOG`, which is
+//! drained by the backend selected with a feature of the firmware:
+//!
+//! | Feature           | Backend                                           |
+//! |-------------------|---------------------------------------------------|
+//! | `log-rtt`         | text on RTT channel 1, see `rtt`                  |
+//! | `log-semihosting` | text on the OpenOCD console, halts the core for   |
+//! |                   | every line and hangs without debugger             |
+//! | `log-usb`         | `L` lines on the serial console                   |
+//! | none              | `HISTORY` keeps the latest entries for a debugger |
+//!
+//! `flush` drains the log in `idle`, so formatting never delays a task. The
+//! USB console is a resource of its task, so with `log-usb` `flush` only
+//! pends that task and `drain` runs there.
+
+// each backend only uses some of the helpers
+#![allow(dead_code)]
+
+#[cfg(any(all(feature = "log-rtt", feature = "log-semihosting"),
+          all(feature = "log-rtt", feature = "log-usb"),
+          all(feature = "log-semihosting", feature = "log-usb")))]
+compile_error!("select at most one log backend");
+
+use core::fmt::{self, Write};
+use ts100_core::console::Console;
+use ts100_core::log::{Arg, Entry, History, Level, Metadata, LOG};
+use ts100_core::time;
+
+/// Longest formatted entry, longer ones are cut
+const LINE_MAX: usize = 96;
+
+static DROPPED: Metadata = Metadata {
+    level: Level::Warn,
+    format: "{} log entries dropped",
+};
+
+/// Latest entries of the RAM backend, `print firmware::log::HISTORY` in gdb
+pub static mut HISTORY: History = History::new();
+
+struct Line {
+    data: [u8; LINE_MAX],
+    len: usize,
+}
+
+impl Write for Line {
+    fn write_str(&mut self, text: &str) -> fmt::Result {
+        for &byte in text.as_bytes() {
+            if self.len < self.data.len() {
+                self.data[self.len] = byte;
+                self.len += 1;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `entry` as a line, the last byte is kept free for a terminator
+fn format(entry: &Entry) -> Line {
+    let mut line = Line {
+        data: [0; LINE_MAX],
+        len: 0,
+    };
+    let _ = write!(line, "{}", entry);
+    line.len = line.len.min(LINE_MAX - 2);
+    line.data[line.len] = b'\n';
+    line.len += 1;
+    line
+}
+
+/// The queued entries and a note about dropped ones
+fn pop() -> Option<Entry> {
+    LOG.pop().or_else(|| {
+        let dropped = LOG.take_dropped();
+        if dropped > 0 {
+            Some(Entry::new(&DROPPED, time::now(), &[Arg::from(dropped)]))
+        } else {
+            None
+        }
+    })
+}
+
+/// Hands the queued entries to the backend, only called from `idle`
+#[cfg(feature = "log-rtt")]
+pub fn flush() {
+    use rtt;
+
+    while let Some(entry) = pop() {
+        let line = format(&entry);
+        rtt::write(rtt::LOG, &line.data[..line.len]);
+    }
+}
+
+#[cfg(feature = "log-semihosting")]
+pub fn flush() {
+    const SYS_WRITE0: u32 = 0x04;
+
+    while let Some(entry) = pop() {
+        let mut line = format(&entry);
+        line.data[line.len] = 0;
+        // r0 returns the result, which SYS_WRITE0 leaves undefined
+        let _result: u32;
+        unsafe {
+            asm!("bkpt 0xab"
+                 : "={r0}"(_result)
+                 : "{r0}"(SYS_WRITE0), "{r1}"(line.data.as_ptr())
+                 : "memory"
+                 : "volatile");
+        }
+    }
+}
+
+#[cfg(feature = "log-usb")]
+pub fn flush() {
+    use blue_pill::stm32f103xx::Interrupt;
+    use rtfm;
+
+    if !LOG.is_empty() {
+        rtfm::set_pending(Interrupt::USB_LP_CAN1_RX0);
+    }
+}
+
+#[cfg(not(any(feature = "log-rtt", feature = "log-semihosting", feature = "log-usb")))]
+pub fn flush() {
+    while let Some(entry) = pop() {
+        unsafe { HISTORY.push(entry) };
+    }
+}
+
+/// Queues the entries on the serial console, only called from its task
+pub fn drain(console: &mut Console) {
+    if cfg!(feature = "log-usb") {
+        while let Some(entry) = pop() {
+            console.send_log(&entry);
+        }
+    }
+}
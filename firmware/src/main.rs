@@ -1,69 +1,161 @@
 #![feature(asm)]
 #![feature(const_fn)]
+#![feature(lang_items)]
+#![feature(naked_functions)]
 #![feature(proc_macro)]
 #![no_std]
 
-extern crate cast;
 extern crate cortex_m;
 extern crate cortex_m_rtfm as rtfm;
 extern crate blue_pill;
-extern crate numtoa;
+#[macro_use]
+extern crate ts100_core;
 
 use blue_pill::stm32f103xx::Interrupt;
+use core::ptr;
 use cortex_m::peripheral::SystClkSource;
 use rtfm::{app, Threshold};
 
-mod font5x7;
+mod adc;
+mod crash;
+mod fault;
+mod flash;
+mod heater;
 mod i2c;
-mod mma8652fc;
-mod ssd1306;
-mod state;
-
-use mma8652fc::MMA8652FC;
-use ssd1306::SSD1306;
-use state::{ConfigPage, Keys, State, StateMachine};
+mod journal;
+mod log;
+mod rcc;
+mod rtt;
+mod settings;
+mod usb;
+mod watchdog;
+
+use crash::Crash;
+use fault::Fault;
+use journal::JournalFlash;
+use rcc::{ClockConfig, ResetCause};
+use ts100_core::{accelerometer, mma8652fc, motion, time, ui};
+use ts100_core::accelerometer::{Accel, Accelerometer, Driver, Sensor, TapConfig};
+use ts100_core::accelerometer::Error as AccelError;
+use ts100_core::i2c::I2cBus;
+use ts100_core::lis2dh12::LIS2DH12;
+use ts100_core::mma8652fc::{DataRate, FifoConfig, FifoMode, Oversampling, Range, MMA8652FC};
+use ts100_core::ssd1306::SSD1306;
+use ts100_core::console::{Console, Request, Telemetry};
+use ts100_core::event::{Event, Queue};
+use ts100_core::input::{Button, Input};
+use ts100_core::journal::{Journal, Monitor};
+use ts100_core::journal::Event as JournalEvent;
+use ts100_core::motion::MotionDetector;
+use ts100_core::settings::Settings;
+use ts100_core::state::{Action, ConfigPage, State, StateMachine};
+use ts100_core::temperature::Sample;
+use ts100_core::time::{TimerId, Timers};
+use ts100_core::trace::{self, Record};
+use ts100_core::ui::Framebuffer;
+use ts100_core::usb::Control as UsbControl;
+use watchdog::{Supervisor, Task};
 
 const OLED_ADDR: u8 = 0x3c;
+/// Motion interrupt threshold in milli-g
+const MOTION_WAKE_THRESHOLD: u16 = 126;
+/// Samples above the threshold before the motion interrupt fires
+const MOTION_WAKE_COUNT: u8 = 2;
+/// Buffered samples raising the FIFO interrupt, 160 ms at 100 Hz
+const FIFO_WATERMARK: u8 = 16;
+/// 48 MHz, `ClockConfig::low_power()` trades speed for battery life
+const CLOCK_CONFIG: ClockConfig = ClockConfig::new();
+/// Screen redraw interval in ms
+const REFRESH_INTERVAL: u32 = 200;
+/// Inactivity in ms until the configuration menu is left
+const MENU_TIMEOUT: u32 = 30_000;
+/// Tip temperature sampling and control interval in ms
+const SAMPLE_INTERVAL: u32 = 20;
+/// SCB_AIRCR and the key to write it with
+const SCB_AIRCR: u32 = 0xE000_ED0C;
+const AIRCR_VECTKEY: u32 = 0x05FA << 16;
+const AIRCR_SYSRESETREQ: u32 = 1 << 2;
+
+/// Events of `tick` and of the accelerometer interrupt, dispatched by
+/// `update_ui`, one queue per producer as they run at different priorities
+static TICK_EVENTS: Queue = Queue::new();
+static EVENTS: Queue = Queue::new();
 
 app! {
     device: blue_pill::stm32f103xx,
 
     resources: {
-        static TICKS: u32 = 0;
+        static TIMERS: Timers = Timers::new();
         static STATE: StateMachine = StateMachine::new();
+        static INPUT: Input = Input::new();
+        static MOTION: MotionDetector = MotionDetector::new(motion::SENSITIVITY_DEFAULT);
+        static ACCEL: Option<Sensor> = None;
+        static SETTINGS: Settings = Settings::new();
+        static SAMPLE: Sample = Sample { tip: 0, ambient: 0, supply: 0 };
+        static SUPERVISOR: Supervisor = Supervisor::new();
+        static USB: UsbControl = UsbControl::new();
+        static CONSOLE: Console = Console::new();
+        static JOURNAL: Journal = Journal::new();
+        static MONITOR: Monitor = Monitor::new();
+        // record shown on the `Config(Events)` page
+        static EVENT_AGE: usize = 0;
     },
 
+    // the clock and the sampling preempt the heater output, which preempts
+    // the blocking I2C and USB work, so neither loses ticks nor samples
     tasks: {
         SYS_TICK: {
             path: tick,
-            resources: [TICKS],
+            priority: 3,
+            resources: [TIMERS, INPUT, SUPERVISOR, IWDG, ADC1, GPIOB],
+        },
+        ADC1_2: {
+            path: sampled,
+            priority: 3,
+            resources: [ADC1, SAMPLE, SUPERVISOR],
         },
+        EXTI1: {
+            path: regulate,
+            priority: 2,
+            resources: [STATE, SAMPLE, SUPERVISOR, GPIOB, CONSOLE],
+        },
+        // the I2C1 users share one priority so a transfer is never interrupted
+        // by another
         EXTI0: {
             path: update_ui,
-            resources: [I2C1, STATE],
+            priority: 1,
+            resources: [I2C1, STATE, TIMERS, SUPERVISOR, ACCEL, SETTINGS, FLASH, GPIOB, SAMPLE,
+                        JOURNAL, MONITOR, EVENT_AGE],
         },
         EXTI9_5: {
             path: exti9_5,
-            resources: [I2C1, STATE, GPIOA, GPIOB, EXTI],
+            priority: 1,
+            resources: [I2C1, INPUT, MOTION, ACCEL, GPIOA, GPIOB, EXTI],
+        },
+        USB_LP_CAN1_RX0: {
+            path: usb_console,
+            priority: 1,
+            resources: [USB, CONSOLE, SETTINGS, MOTION, FLASH, JOURNAL],
         },
     },
 }
 
-fn init(p: init::Peripherals, _r: init::Resources) {
-    // 48Mhz
-    p.FLASH.acr.modify(
-        |_, w| w.prftbe().enabled().latency().one(),
-    );
-
-    p.RCC.cfgr.modify(|_, w| unsafe { w.bits(0x0068840A) });
-    p.RCC.cr.modify(
-        |_, w| w.pllon().set_bit().hsion().set_bit(),
-    );
-    while p.RCC.cr.read().pllrdy().bit_is_clear() {}
-    while p.RCC.cr.read().hsirdy().bit_is_clear() {}
+fn init(p: init::Peripherals, r: init::Resources) {
+    let clocks = match CLOCK_CONFIG.apply(&p.RCC, &p.FLASH) {
+        Ok(clocks) => clocks,
+        // keep running from HSI rather than with an invalid clock tree
+        Err(_) => ClockConfig::low_power().apply(&p.RCC, &p.FLASH).unwrap(),
+    };
 
     p.RCC.apb2enr.modify(|_, w| {
-        w.iopaen().enabled().iopben().enabled().afioen().enabled()
+        w.iopaen()
+            .enabled()
+            .iopben()
+            .enabled()
+            .afioen()
+            .enabled()
+            .adc1en()
+            .enabled()
     });
 
     p.AFIO.mapr.modify(|_, w| unsafe {
@@ -72,9 +164,12 @@ fn init(p: init::Peripherals, _r: init::Resources) {
 
     p.RCC.apb1enr.modify(|_, w| w.i2c1en().enabled());
 
-    p.I2C1.cr1.write(|w| w.pe().clear_bit());
+    heater::init(&p.GPIOB);
 
-    p.GPIOA.crl.modify(|_, w| w.mode6().input());
+    // PA5 analog TMP36
+    p.GPIOA.crl.modify(|_, w| unsafe {
+        w.mode5().input().cnf5().bits(0).mode6().input()
+    });
     p.GPIOA.crh.modify(|_, w| {
         w.mode8().output50().cnf8().push().mode9().input()
     });
@@ -82,8 +177,17 @@ fn init(p: init::Peripherals, _r: init::Resources) {
         w.bs6().set_bit().bs8().set_bit().bs9().set_bit()
     });
 
-    p.GPIOB.crl.modify(|_, w| {
-        w.mode5()
+    // PB0 analog tip temperature, PB1 analog supply voltage
+    p.GPIOB.crl.modify(|_, w| unsafe {
+        w.mode0()
+            .input()
+            .cnf0()
+            .bits(0)
+            .mode1()
+            .input()
+            .cnf1()
+            .bits(0)
+            .mode5()
             .input()
             .mode6()
             .output50()
@@ -112,141 +216,397 @@ fn init(p: init::Peripherals, _r: init::Resources) {
         w.tr5().set_bit().tr6().set_bit().tr9().set_bit()
     });
 
-    p.I2C1.cr2.modify(|_, w| unsafe { w.freq().bits(24) });
-    p.I2C1.cr1.modify(|_, w| w.pe().clear_bit());
-    p.I2C1.trise.modify(
-        |_, w| unsafe { w.trise().bits(24 + 1) },
-    );
-    p.I2C1.ccr.modify(|_, w| unsafe {
-        w.f_s().clear_bit().duty().clear_bit().ccr().bits(120)
-    });
-
-    p.I2C1.cr1.modify(|_, w| {
-        w.nostretch()
-            .clear_bit()
-            .ack()
-            .set_bit()
-            .smbus()
-            .clear_bit()
-    });
-
-    p.I2C1.cr1.write(|w| w.pe().set_bit());
-    p.I2C1.oar1.write(|w| unsafe {
-        w.addmode()
-            .clear_bit()
-            .add0()
-            .clear_bit()
-            .add7()
-            .bits(0)
-            .add10()
-            .bits(0)
-    });
+    i2c::init(&p.I2C1, &clocks);
+    adc::init(&p.ADC1);
+    // a first sample, the monitor would take the empty one for a low supply
+    adc::start(&p.ADC1);
+    **r.SAMPLE = loop {
+        if let Some(sample) = adc::take(&p.ADC1) {
+            break sample;
+        }
+    };
+    rtt::init();
+    // without USB the iron works as before, just without console
+    if !usb::init(&p.RCC, &p.GPIOA, &clocks) {
+        warn!("no USB clock, console disabled");
+    }
 
-    let oled = SSD1306(OLED_ADDR, &p.I2C1);
+    let bus = i2c::Bus(&p.I2C1);
+    let oled = SSD1306(OLED_ADDR, &bus);
     oled.init();
     oled.print(0, 0, "                ");
     oled.print(0, 1, "                ");
 
-    let accel = MMA8652FC(&p.I2C1);
-    accel.init();
+    fault::init(&p.RCC, &p.PWR);
+    let cause = rcc::reset_cause(&p.RCC);
+    let fault = fault::boot(cause);
+    info!("boot, reset cause {}", cause as u8);
+    let crash = Crash::load();
+
+    let mut journal_flash = JournalFlash(&p.FLASH);
+    **r.JOURNAL = Journal::open(&journal_flash);
+    r.JOURNAL.append(&mut journal_flash, JournalEvent::Boot(cause), uptime());
+    if let Some(Fault::Watchdog(task)) = fault {
+        r.JOURNAL.append(&mut journal_flash, JournalEvent::Watchdog(task as u8), uptime());
+    }
+
+    if let Some(crash) = crash {
+        crash.show(&oled);
+    } else if cause == ResetCause::IndependentWatchdog {
+        oled.print(0, 0, "WATCHDOG RESET");
+        if let Some(Fault::Watchdog(task)) = fault {
+            oled.print(0, 1, task.name());
+            error!("watchdog reset, task {} was overdue", task as u8);
+        }
+    }
+
+    if crash.is_some() || cause == ResetCause::IndependentWatchdog {
+        for _ in 0..10_000_000 {
+            cortex_m::asm::nop();
+        }
+        if crash.is_some() {
+            Crash::clear(&p.FLASH);
+        }
+        oled.print(0, 0, "                ");
+        oled.print(0, 1, "                ");
+    }
+
+    **r.SETTINGS = settings::load();
+    r.MOTION.set_sensitivity(r.SETTINGS.motion_sensitivity);
+    let sensor = init_accel(&bus, &**r.SETTINGS);
+
+    match sensor {
+        Ok(sensor) => {
+            **r.ACCEL = Some(sensor);
+            r.STATE.set_accel_present(true);
+        }
+        Err(error) => {
+            // fall back to time based sleep
+            r.STATE.set_accel_present(false);
+            warn!("accelerometer unavailable, timed sleep");
+            r.JOURNAL.append(&mut journal_flash, JournalEvent::AccelError(error), uptime());
+            match error {
+                AccelError::NotPresent => {
+                    oled.print(0, 0, "ACCEL MISSING");
+                }
+                AccelError::UnknownDevice(id) => {
+                    oled.print(0, 0, "ACCEL ID 0x");
+                    oled.print_hex(11, 0, id);
+                }
+                AccelError::NoData | AccelError::SelfTestFailed => {
+                    oled.print(0, 0, "ACCEL FAULTY");
+                }
+                AccelError::InvalidConfig => {
+                    oled.print(0, 0, "ACCEL CONFIG");
+                }
+            }
+            oled.print(0, 1, "TIMED SLEEP");
+            for _ in 0..10_000_000 {
+                cortex_m::asm::nop();
+            }
+        }
+    }
+
+    let now = time::now();
+    r.TIMERS.start_periodic(TimerId::Second, now, 1000);
+    r.TIMERS.start_periodic(TimerId::Refresh, now, REFRESH_INTERVAL);
+    r.TIMERS.start_periodic(TimerId::Sample, now, SAMPLE_INTERVAL);
+
+    r.SUPERVISOR.start(now);
+    watchdog::start(&p.IWDG);
 
     p.SYST.set_clock_source(SystClkSource::Core);
-    p.SYST.set_reload(48_000_000);
+    p.SYST.set_reload(clocks.systick_reload(1000));
     p.SYST.enable_interrupt();
     p.SYST.enable_counter();
 }
 
+/// Probes and configures the accelerometer including motion wake and taps
+fn init_accel<B: I2cBus>(bus: &B, settings: &Settings) -> Result<Sensor, AccelError> {
+    let sensor = accelerometer::probe(bus)?;
+
+    match sensor {
+        Sensor::Mma8652fc => {
+            let config = mma8652fc::Config::new()
+                .range(Range::G2)
+                .data_rate(DataRate::Hz100)
+                .oversampling(Oversampling::HighResolution)
+                // unfiltered, so the calibrated offsets reach the motion
+                // detector and the screen
+                .high_pass(None)
+                .auto_sleep(None)
+                .offsets(settings.accel_offset);
+            MMA8652FC(bus).init(&config)?;
+            MMA8652FC(bus).configure_fifo(&FifoConfig {
+                mode: FifoMode::Circular,
+                watermark: FIFO_WATERMARK,
+                triggers: 0,
+            });
+        }
+        Sensor::Lis2dh12 => LIS2DH12(bus).init()?,
+    }
+
+    let driver = Driver::new(sensor, bus);
+    driver.configure_motion_wake(MOTION_WAKE_THRESHOLD, MOTION_WAKE_COUNT);
+    driver.configure_taps(&TapConfig::new());
+
+    Ok(sensor)
+}
+
 fn idle() -> ! {
     rtfm::set_pending(Interrupt::EXTI0);
 
     loop {
+        log::flush();
         rtfm::wfi();
     }
 }
 
 fn tick(_t: &mut Threshold, r: SYS_TICK::Resources) {
-    **r.TICKS += 1;
-}
+    let now = time::tick();
 
-fn update_ui(_t: &mut Threshold, r: EXTI0::Resources) {
-    let i2c1 = &**r.I2C1;
-    let oled = SSD1306(OLED_ADDR, &i2c1);
-    let am = MMA8652FC(&i2c1);
-    r.STATE.update_accel(am.accel());
-
-    r.STATE.update_state();
-
-    oled.print(0, 0, "X ");
-    oled.print(8, 0, "Y ");
-    oled.print(0, 1, "Z ");
-
-    let accel = r.STATE.get_accel();
-    oled.print_number(2, 0, accel.x);
-    oled.print_number(10, 0, accel.y);
-    oled.print_number(2, 1, accel.z);
-/*
-    match r.STATE.current_state() {
-        State::Idle => {
-            oled.print(0, 0, "      IDLE      ");
-            oled.print(0, 1, "                ");
-        }
-        State::Soldering => {
-            oled.print(0, 0, "    SOLDERING   ");
-            oled.print(0, 1, "                ");
-        }
-        State::Cooling => {
-            oled.print(0, 0, "     COOLING    ");
-            oled.print(0, 1, "                ");
-        }
-        State::Sleep => {
-            oled.print(0, 0, "     zZzZzZ     ");
-            oled.print(0, 1, "                ");
-        }
-        State::TemperatureControl => {
-            oled.print(0, 0, " <    200 C   > ");
+    r.INPUT.poll(now);
+    while let Some(key) = r.INPUT.pop() {
+        let _ = TICK_EVENTS.push(Event::Key(key));
+    }
+
+    while let Some(id) = r.TIMERS.expired(now) {
+        match id {
+            // the thermocouple can only be read with the heater off
+            TimerId::Sample => {
+                heater::off(&**r.GPIOB);
+                adc::start(&**r.ADC1);
+            }
+            _ => {
+                let _ = TICK_EVENTS.push(Event::Timer(id));
+            }
         }
-        State::Thermometer => {
-            oled.print(0, 0, "     200.1 C    ");
+    }
+
+    match r.SUPERVISOR.overdue(now) {
+        None => watchdog::feed(&**r.IWDG),
+        Some(task) => {
+            // starve the watchdog, the heater stays off until it bites
+            heater::off(&**r.GPIOB);
+            fault::record(Fault::Watchdog(task));
         }
-        State::Config(page) => {
-            match page {
-                ConfigPage::Save => {
-                    oled.print(0, 0, "Save and Reset? ");
+    }
+
+    if !TICK_EVENTS.is_empty() {
+        rtfm::set_pending(Interrupt::EXTI0);
+    }
+}
+
+fn update_ui(t: &mut Threshold, r: EXTI0::Resources) {
+    let i2c1 = &i2c::Bus(&**r.I2C1);
+    let oled = SSD1306(OLED_ADDR, i2c1);
+    r.SUPERVISOR.claim_mut(t, |supervisor, _| supervisor.check_in(Task::Ui, time::now()));
+
+    // claimed per step, the heater output only waits for the state machine
+    // and never for the I2C transfers in between, the actions of an event
+    // are taken before the next one
+    while let Some(event) = TICK_EVENTS.pop().or_else(|| EVENTS.pop()) {
+        r.STATE.claim_mut(t, |state, _| state.update_state(event));
+
+        while let Some(action) = r.STATE.claim_mut(t, |state, _| state.take_action()) {
+            match action {
+                Action::CalibrateAccel => {
+                    // only the MMA8652FC has offset correction registers
+                    let sensor = **r.ACCEL;
+                    if let Some(Sensor::Mma8652fc) = sensor {
+                        oled.print(0, 0, "  CALIBRATING   ");
+                        if let Ok(offsets) = MMA8652FC(i2c1).calibrate() {
+                            r.SETTINGS.accel_offset = offsets;
+                            settings::save(&**r.SETTINGS, &**r.FLASH);
+                        }
+                        match init_accel(i2c1, &**r.SETTINGS) {
+                            Ok(sensor) => {
+                                **r.ACCEL = Some(sensor);
+                                r.STATE.claim_mut(t, |state, _| state.set_accel_present(true));
+                            }
+                            Err(error) => {
+                                **r.ACCEL = None;
+                                r.STATE.claim_mut(t, |state, _| {
+                                    state.update_state(Event::AccelError(error))
+                                });
+                                r.JOURNAL.append(&mut JournalFlash(&**r.FLASH),
+                                                 JournalEvent::AccelError(error),
+                                                 uptime());
+                            }
+                        }
+                    }
+                }
+                Action::SaveSettings => {
+                    settings::save(&**r.SETTINGS, &**r.FLASH);
+                    info!("settings saved");
+                }
+                Action::HeaterOff => r.GPIOB.claim(t, |gpiob, _| heater::off(gpiob)),
+                Action::StartMenuTimeout => {
+                    let now = time::now();
+                    r.TIMERS.claim_mut(t, |timers, _| {
+                        timers.start(TimerId::MenuTimeout, now, MENU_TIMEOUT)
+                    })
+                }
+                Action::ShowEvents => **r.EVENT_AGE = 0,
+                Action::OlderEvent => {
+                    // wraps around to the newest after the oldest
+                    let len = r.JOURNAL.len(&JournalFlash(&**r.FLASH));
+                    **r.EVENT_AGE = if **r.EVENT_AGE + 1 < len { **r.EVENT_AGE + 1 } else { 0 };
                 }
             }
         }
     }
-    */
+
+    let sample = r.SAMPLE.claim(t, |sample, _| **sample);
+    if let Some(event) = r.MONITOR.update(sample.tip_celsius(), sample.supply_mv()) {
+        r.JOURNAL.append(&mut JournalFlash(&**r.FLASH), event, uptime());
+    }
+
+    let mut fb = Framebuffer::new();
+    if r.STATE.claim(t, |state, _| state.current_state()) == State::Config(ConfigPage::Events) {
+        let record = r.JOURNAL.get(&JournalFlash(&**r.FLASH), **r.EVENT_AGE);
+        ui::render_event(&mut fb, **r.EVENT_AGE, record);
+    } else {
+        r.STATE.claim(t, |state, _| ui::render(state, &mut fb));
+    }
+    // the flush takes tens of ms, the higher priority tasks keep running
+    oled.flush(&fb);
+}
+
+/// Seconds since boot for the journal
+fn uptime() -> u32 {
+    time::now() / 1000
+}
+
+fn sampled(_t: &mut Threshold, r: ADC1_2::Resources) {
+    if let Some(sample) = adc::take(&**r.ADC1) {
+        **r.SAMPLE = sample;
+        r.SUPERVISOR.check_in(Task::Adc, time::now());
+        rtfm::set_pending(Interrupt::EXTI1);
+    }
+}
+
+/// Sets the heater output after each sample, off until the tip conversion is
+/// calibrated, and reports the sample
+fn regulate(t: &mut Threshold, r: EXTI1::Resources) {
+    let state = r.STATE.current_state();
+    let sample = r.SAMPLE.claim(t, |sample, _| **sample);
+    let tip = sample.tip_celsius();
+    r.GPIOB.claim(t, |gpiob, _| heater::off(gpiob));
+
+    let now = time::now();
+    r.SUPERVISOR.claim_mut(t, |supervisor, _| supervisor.check_in(Task::Control, now));
+
+    // dropped while no debugger reads the channel
+    let record = Record::capture(now, &sample, state, None, false, 0);
+    let mut frame = [0; trace::FRAME_MAX];
+    let len = record.encode(&mut frame);
+    rtt::write(rtt::TRACE, &frame[..len]);
+
+    if r.CONSOLE.telemetry_due(now) {
+        let accel = r.STATE.get_accel();
+        r.CONSOLE.send_telemetry(&Telemetry {
+            time: now,
+            tip: tip,
+            setpoint: None,
+            duty: 0,
+            vin: Some(sample.supply_mv()),
+            accel: [accel.x, accel.y, accel.z],
+        });
+        rtfm::set_pending(Interrupt::USB_LP_CAN1_RX0);
+    }
+}
+
+fn usb_console(t: &mut Threshold, r: USB_LP_CAN1_RX0::Resources) {
+    let usb = &mut **r.USB;
+    let settings = &mut **r.SETTINGS;
+    let journal = &**r.JOURNAL;
+    let flash = &**r.FLASH;
+
+    let request = r.CONSOLE.claim_mut(t, |console, _| {
+        log::drain(console);
+        usb::poll(usb, console, settings);
+        // sends the records of `events` a packet at a time, the task runs
+        // again once a packet was sent
+        console.continue_export(journal, &JournalFlash(flash));
+        usb::transmit(usb, console);
+        console.take_request()
+    });
+    r.MOTION.set_sensitivity(settings.motion_sensitivity);
+
+    match request {
+        Some(Request::SaveSettings) => settings::save(settings, flash),
+        Some(Request::Reset) => {
+            r.CONSOLE.claim_mut(t, |console, _| usb::flush(usb, console));
+            reset();
+        }
+        None => {}
+    }
+}
+
+fn usb_console(_t: &mut Threshold, r: USB_LP_CAN1_RX0::Resources) {
+    log::drain(&mut **r.CONSOLE);
+    usb::poll(&mut **r.USB, &mut **r.CONSOLE, &mut **r.SETTINGS);
+    // sends the records of `events` a packet at a time, the task runs again
+    // once a packet was sent
+    r.CONSOLE.continue_export(&**r.JOURNAL, &JournalFlash(&**r.FLASH));
+    usb::transmit(&**r.USB, &mut **r.CONSOLE);
+    r.MOTION.set_sensitivity(r.SETTINGS.motion_sensitivity);
+
+    match r.CONSOLE.take_request() {
+        Some(Request::SaveSettings) => settings::save(&**r.SETTINGS, &**r.FLASH),
+        Some(Request::Reset) => {
+            usb::flush(&**r.USB, &mut **r.CONSOLE);
+            reset();
+        }
+        None => {}
+    }
+}
+
+/// Requests a system reset through the SCB
+fn reset() -> ! {
+    unsafe { ptr::write_volatile(SCB_AIRCR as *mut u32, AIRCR_VECTKEY | AIRCR_SYSRESETREQ) };
+    loop {
+        cortex_m::asm::nop();
+    }
 }
 
-fn exti9_5(_t: &mut Threshold, r: EXTI9_5::Resources) {
+fn exti9_5(t: &mut Threshold, r: EXTI9_5::Resources) {
     let exti = &**r.EXTI;
     let gpioa = &**r.GPIOA;
-    let gpiob = &**r.GPIOB;
-    let i2c1 = &**r.I2C1;
+    let i2c1 = &i2c::Bus(&**r.I2C1);
+
+    let now = time::now();
 
     // Button A
     if exti.pr.read().pr6().bit_is_set() {
-        if gpioa.idr.read().idr6().bit_is_clear() {
-            r.STATE.update_keys(Keys::A);
-        } else {
-            r.STATE.update_keys(Keys::None);
-        };
+        let pressed = gpioa.idr.read().idr6().bit_is_clear();
+        r.INPUT.claim_mut(t, |input, _| input.edge(Button::A, pressed, now));
         exti.pr.write(|w| w.pr6().set_bit());
     // Button B
     } else if exti.pr.read().pr9().bit_is_set() {
-        if gpioa.idr.read().idr9().bit_is_clear() {
-           r.STATE.update_keys(Keys::B)
-        } else {
-            r.STATE.update_keys(Keys::None)
-        };
+        let pressed = gpioa.idr.read().idr9().bit_is_clear();
+        r.INPUT.claim_mut(t, |input, _| input.edge(Button::B, pressed, now));
         exti.pr.write(|w| w.pr9().set_bit());
     // Movement
     // interrupt doesn't fire
     } else if exti.pr.read().pr5().bit_is_set() {
-        if gpiob.idr.read().idr5().bit_is_clear() {
-            let am = MMA8652FC(&i2c1);
-            r.STATE.update_accel(am.accel());
+        if let Some(sensor) = **r.ACCEL {
+            if r.GPIOB.claim(t, |gpiob, _| gpiob.idr.read().idr5().bit_is_clear()) {
+                let driver = Driver::new(sensor, i2c1);
+                if let Some(gesture) = driver.gesture() {
+                    let _ = EVENTS.push(Event::Gesture(gesture));
+                }
+
+                let mut samples = [Accel { x: 0, y: 0, z: 0 }; mma8652fc::FIFO_SIZE];
+                let count = driver.drain(&mut samples);
+                if let Some(motion) = r.MOTION.update(&samples[..count]) {
+                    let _ = EVENTS.push(Event::Motion(motion));
+                }
+                if count > 0 {
+                    let _ = EVENTS.push(Event::Accel(samples[count - 1]));
+                }
+            }
         }
         exti.pr.write(|w| w.pr5().set_bit());
     }
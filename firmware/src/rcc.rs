@@ -0,0 +1,348 @@
+//! Clock tree configuration
+//!
+//! The TS100 has no crystal, so the clocks are derived from the internal 8
+//! MHz RC oscillator (HSI), either directly or through the PLL which is fed
+//! with HSI/2. The fastest possible system clock is therefore 64 MHz
+//! (HSI/2 x 16), 72 MHz would need an external crystal.
+
+use blue_pill::stm32f103xx::{FLASH, RCC};
+
+/// Frequency of the internal RC oscillator
+pub const HSI: u32 = 8_000_000;
+
+/// Limits from the datasheet
+const SYSCLK_MAX: u32 = 72_000_000;
+const PCLK1_MAX: u32 = 36_000_000;
+const ADCCLK_MAX: u32 = 14_000_000;
+/// Lowest APB1 clock the I2C peripheral works with in standard mode
+const PCLK1_I2C_MIN: u32 = 2_000_000;
+
+/// I2C bus clock in standard mode
+const I2C_STANDARD: u32 = 100_000;
+
+const ACR_LATENCY: u32 = 0b111;
+const ACR_PRFTBE: u32 = 1 << 4;
+const CFGR_SW_PLL: u32 = 0b10;
+const CFGR_SWS: u32 = 0b11 << 2;
+const CFGR_SWS_PLL: u32 = 0b10 << 2;
+const CFGR_USBPRE: u32 = 1 << 22;
+
+/// System clock source
+#[derive(Clone, Copy, PartialEq)]
+pub enum SysClk {
+    /// HSI directly, 8 MHz
+    Hsi,
+    /// PLL fed by HSI/2, multiplied by 2 to 16
+    HsiPll(u8),
+}
+
+/// AHB prescaler dividing the system clock
+#[derive(Clone, Copy, PartialEq)]
+pub enum AhbPrescaler {
+    Div1 = 0b0000,
+    Div2 = 0b1000,
+    Div4 = 0b1001,
+    Div8 = 0b1010,
+    Div16 = 0b1011,
+    Div64 = 0b1100,
+    Div128 = 0b1101,
+    Div256 = 0b1110,
+    Div512 = 0b1111,
+}
+
+impl AhbPrescaler {
+    pub fn divisor(&self) -> u32 {
+        match *self {
+            AhbPrescaler::Div1 => 1,
+            AhbPrescaler::Div2 => 2,
+            AhbPrescaler::Div4 => 4,
+            AhbPrescaler::Div8 => 8,
+            AhbPrescaler::Div16 => 16,
+            AhbPrescaler::Div64 => 64,
+            AhbPrescaler::Div128 => 128,
+            AhbPrescaler::Div256 => 256,
+            AhbPrescaler::Div512 => 512,
+        }
+    }
+}
+
+/// APB1 and APB2 prescaler dividing the AHB clock
+#[derive(Clone, Copy, PartialEq)]
+pub enum ApbPrescaler {
+    Div1 = 0b000,
+    Div2 = 0b100,
+    Div4 = 0b101,
+    Div8 = 0b110,
+    Div16 = 0b111,
+}
+
+impl ApbPrescaler {
+    pub fn divisor(&self) -> u32 {
+        match *self {
+            ApbPrescaler::Div1 => 1,
+            ApbPrescaler::Div2 => 2,
+            ApbPrescaler::Div4 => 4,
+            ApbPrescaler::Div8 => 8,
+            ApbPrescaler::Div16 => 16,
+        }
+    }
+}
+
+/// ADC prescaler dividing the APB2 clock
+#[derive(Clone, Copy, PartialEq)]
+pub enum AdcPrescaler {
+    Div2 = 0b00,
+    Div4 = 0b01,
+    Div6 = 0b10,
+    Div8 = 0b11,
+}
+
+impl AdcPrescaler {
+    pub fn divisor(&self) -> u32 {
+        match *self {
+            AdcPrescaler::Div2 => 2,
+            AdcPrescaler::Div4 => 4,
+            AdcPrescaler::Div6 => 6,
+            AdcPrescaler::Div8 => 8,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClockError {
+    /// PLL multiplier outside of 2 to 16
+    PllMultiplier,
+    SysClkTooHigh,
+    Pclk1TooHigh,
+    /// APB1 is too slow for the I2C peripheral
+    Pclk1TooLow,
+    AdcClkTooHigh,
+}
+
+/// Desired clock tree
+#[derive(Clone, Copy)]
+pub struct ClockConfig {
+    sysclk: SysClk,
+    ahb: AhbPrescaler,
+    apb1: ApbPrescaler,
+    apb2: ApbPrescaler,
+    adc: AdcPrescaler,
+}
+
+impl ClockConfig {
+    /// 48 MHz from HSI/2 x 12, APB1 at 24 MHz, ADC at 8 MHz, the only
+    /// configuration USB works with
+    pub const fn new() -> Self {
+        ClockConfig {
+            sysclk: SysClk::HsiPll(12),
+            ahb: AhbPrescaler::Div1,
+            apb1: ApbPrescaler::Div2,
+            apb2: ApbPrescaler::Div1,
+            adc: AdcPrescaler::Div6,
+        }
+    }
+
+    /// 64 MHz from HSI/2 x 16, the fastest clock without a crystal
+    pub fn fastest() -> Self {
+        ClockConfig::new().sysclk(SysClk::HsiPll(16))
+    }
+
+    /// 8 MHz straight from HSI with the PLL turned off
+    pub fn low_power() -> Self {
+        ClockConfig::new()
+            .sysclk(SysClk::Hsi)
+            .apb1(ApbPrescaler::Div1)
+            .adc(AdcPrescaler::Div2)
+    }
+
+    pub fn sysclk(mut self, sysclk: SysClk) -> Self {
+        self.sysclk = sysclk;
+        self
+    }
+
+    pub fn ahb(mut self, ahb: AhbPrescaler) -> Self {
+        self.ahb = ahb;
+        self
+    }
+
+    pub fn apb1(mut self, apb1: ApbPrescaler) -> Self {
+        self.apb1 = apb1;
+        self
+    }
+
+    pub fn apb2(mut self, apb2: ApbPrescaler) -> Self {
+        self.apb2 = apb2;
+        self
+    }
+
+    pub fn adc(mut self, adc: AdcPrescaler) -> Self {
+        self.adc = adc;
+        self
+    }
+
+    /// Computes the resulting frequencies and checks them against the
+    /// limits of the chip
+    pub fn clocks(&self) -> Result<Clocks, ClockError> {
+        let sysclk = match self.sysclk {
+            SysClk::Hsi => HSI,
+            SysClk::HsiPll(multiplier) => {
+                if multiplier < 2 || multiplier > 16 {
+                    return Err(ClockError::PllMultiplier);
+                }
+                HSI / 2 * u32::from(multiplier)
+            }
+        };
+        let hclk = sysclk / self.ahb.divisor();
+        let pclk1 = hclk / self.apb1.divisor();
+        let pclk2 = hclk / self.apb2.divisor();
+        let adcclk = pclk2 / self.adc.divisor();
+        // the USB prescaler is always 1, see `cfgr`
+        let usbclk = match self.sysclk {
+            SysClk::Hsi => None,
+            SysClk::HsiPll(_) => Some(sysclk),
+        };
+
+        if sysclk > SYSCLK_MAX {
+            Err(ClockError::SysClkTooHigh)
+        } else if pclk1 > PCLK1_MAX {
+            Err(ClockError::Pclk1TooHigh)
+        } else if pclk1 < PCLK1_I2C_MIN {
+            Err(ClockError::Pclk1TooLow)
+        } else if adcclk > ADCCLK_MAX {
+            Err(ClockError::AdcClkTooHigh)
+        } else {
+            Ok(Clocks {
+                sysclk: sysclk,
+                hclk: hclk,
+                pclk1: pclk1,
+                pclk2: pclk2,
+                adcclk: adcclk,
+                usbclk: usbclk,
+            })
+        }
+    }
+
+    /// Switches the clock tree to this configuration
+    ///
+    /// Must run on HSI, which is the case after reset. Nothing is changed if
+    /// the configuration is invalid.
+    pub fn apply(&self, rcc: &RCC, flash: &FLASH) -> Result<Clocks, ClockError> {
+        let clocks = self.clocks()?;
+
+        rcc.cr.modify(|_, w| w.hsion().set_bit());
+        while rcc.cr.read().hsirdy().bit_is_clear() {}
+
+        // wait states have to be increased before raising the clock
+        flash.acr.modify(|r, w| unsafe {
+            w.bits((r.bits() & !ACR_LATENCY) | ACR_PRFTBE | clocks.flash_latency())
+        });
+
+        // prescalers with HSI as system clock, the PLL can only be
+        // configured while it's off
+        rcc.cfgr.write(|w| unsafe { w.bits(self.cfgr()) });
+
+        if let SysClk::HsiPll(_) = self.sysclk {
+            rcc.cr.modify(|_, w| w.pllon().set_bit());
+            while rcc.cr.read().pllrdy().bit_is_clear() {}
+
+            rcc.cfgr.write(|w| unsafe { w.bits(self.cfgr() | CFGR_SW_PLL) });
+            while rcc.cfgr.read().bits() & CFGR_SWS != CFGR_SWS_PLL {}
+        }
+
+        Ok(clocks)
+    }
+
+    /// CFGR value selecting HSI as system clock
+    fn cfgr(&self) -> u32 {
+        let pllmul = match self.sysclk {
+            SysClk::Hsi => 0,
+            SysClk::HsiPll(multiplier) => u32::from(multiplier - 2),
+        };
+
+        // PLLSRC = 0 selects HSI/2, USBPRE set to clock USB with the PLL
+        // output undivided, which is 48 MHz in the default configuration
+        CFGR_USBPRE | pllmul << 18 | (self.adc as u32) << 14 | (self.apb2 as u32) << 11 |
+            (self.apb1 as u32) << 8 | (self.ahb as u32) << 4
+    }
+}
+
+/// Frequencies in Hz resulting from a `ClockConfig`
+#[derive(Clone, Copy)]
+pub struct Clocks {
+    pub sysclk: u32,
+    pub hclk: u32,
+    pub pclk1: u32,
+    pub pclk2: u32,
+    pub adcclk: u32,
+    /// USB clock, `None` while the PLL is off
+    pub usbclk: Option<u32>,
+}
+
+impl Clocks {
+    /// Flash wait states needed at `sysclk`
+    pub fn flash_latency(&self) -> u32 {
+        if self.sysclk <= 24_000_000 {
+            0
+        } else if self.sysclk <= 48_000_000 {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// SysTick reload value for interrupts at `hz` using the core clock
+    pub fn systick_reload(&self, hz: u32) -> u32 {
+        self.hclk / hz - 1
+    }
+
+    /// Value of the I2C CR2 FREQ field, APB1 clock in MHz
+    pub fn i2c_freq(&self) -> u8 {
+        (self.pclk1 / 1_000_000) as u8
+    }
+
+    /// I2C CCR for standard mode, 100 kHz with a 50% duty cycle
+    pub fn i2c_ccr(&self) -> u16 {
+        (self.pclk1 / (2 * I2C_STANDARD)) as u16
+    }
+
+    /// I2C TRISE for the 1000 ns maximum rise time of standard mode
+    pub fn i2c_trise(&self) -> u8 {
+        self.i2c_freq() + 1
+    }
+}
+
+/// Reason of the last reset, recorded in the journal
+pub use ts100_core::journal::ResetCause;
+
+const CSR_RMVF: u32 = 1 << 24;
+const CSR_PINRSTF: u32 = 1 << 26;
+const CSR_PORRSTF: u32 = 1 << 27;
+const CSR_SFTRSTF: u32 = 1 << 28;
+const CSR_IWDGRSTF: u32 = 1 << 29;
+const CSR_WWDGRSTF: u32 = 1 << 30;
+const CSR_LPWRRSTF: u32 = 1 << 31;
+
+/// Reads and clears the reset flags
+///
+/// The pin flag is set along with every other cause, so it's only reported
+/// if no other flag is set.
+pub fn reset_cause(rcc: &RCC) -> ResetCause {
+    let csr = rcc.csr.read().bits();
+    rcc.csr.modify(|r, w| unsafe { w.bits(r.bits() | CSR_RMVF) });
+
+    if csr & CSR_IWDGRSTF != 0 {
+        ResetCause::IndependentWatchdog
+    } else if csr & CSR_WWDGRSTF != 0 {
+        ResetCause::WindowWatchdog
+    } else if csr & CSR_SFTRSTF != 0 {
+        ResetCause::Software
+    } else if csr & CSR_LPWRRSTF != 0 {
+        ResetCause::LowPower
+    } else if csr & CSR_PORRSTF != 0 {
+        ResetCause::PowerOn
+    } else if csr & CSR_PINRSTF != 0 {
+        ResetCause::Pin
+    } else {
+        ResetCause::PowerOn
+    }
+}
@@ -0,0 +1,132 @@
+//! SEGGER RTT up channels for the control loop records and the log
+//!
+//! RTT needs nothing but SWD: the firmware writes into a ring buffer in RAM
+//! and the debugger reads it out in the background, the control block
+//! holding the buffer description is found by its id. The TS100 doesn't
+//! expose SWO, so ITM would need an extra wire. With OpenOCD 0.11 or newer:
+//!
+//! ```text
+//! rtt setup 0x20000000 0x5000 "SEGGER RTT"
+//! rtt start
+//! rtt server start 9090 0
+//! ```
+//!
+//! Channel 0 carries the `trace` records on port 9090, the log is on
+//! channel 1 with `rtt server start 9091 1`. Writes never block, data
+//! which doesn't fit is dropped as a whole, so nothing changes without a
+//! debugger reading.
+
+use core::ptr;
+
+/// Up channel of the control loop records
+pub const TRACE: usize = 0;
+/// Up channel of the log as text, only used with the `log-rtt` feature
+pub const LOG: usize = 1;
+
+const CHANNELS: usize = 2;
+const ID: &'static [u8; 10] = b"SEGGER RTT";
+const NAMES: [&'static [u8]; CHANNELS] = [b"Telemetry\0", b"Log\0"];
+
+#[repr(C)]
+struct Channel {
+    name: u32,
+    buffer: u32,
+    size: u32,
+    /// Written by the target only
+    write: u32,
+    /// Written by the debugger only
+    read: u32,
+    /// 0 drops data which doesn't fit
+    flags: u32,
+}
+
+#[repr(C)]
+struct ControlBlock {
+    id: [u8; 16],
+    up_channels: u32,
+    down_channels: u32,
+    up: [Channel; CHANNELS],
+}
+
+#[no_mangle]
+static mut _SEGGER_RTT: ControlBlock = ControlBlock {
+    id: [0; 16],
+    up_channels: 0,
+    down_channels: 0,
+    up: [UNUSED, UNUSED],
+};
+
+const UNUSED: Channel = Channel {
+    name: 0,
+    buffer: 0,
+    size: 0,
+    write: 0,
+    read: 0,
+    flags: 0,
+};
+
+/// About a second of records
+static mut TRACE_BUFFER: [u8; 1024] = [0; 1024];
+static mut LOG_BUFFER: [u8; 256] = [0; 256];
+
+/// The buffer of `channel`
+unsafe fn buffer(channel: usize) -> &'static mut [u8] {
+    if channel == TRACE {
+        &mut TRACE_BUFFER
+    } else {
+        &mut LOG_BUFFER
+    }
+}
+
+/// Fills in the control block, the id comes last so a debugger never sees
+/// a half initialized block. The accesses are volatile to keep their order.
+pub fn init() {
+    unsafe {
+        ptr::write_volatile(&mut _SEGGER_RTT.up_channels, CHANNELS as u32);
+        ptr::write_volatile(&mut _SEGGER_RTT.down_channels, 0);
+        for channel in 0..CHANNELS {
+            ptr::write_volatile(&mut _SEGGER_RTT.up[channel],
+                                Channel {
+                                    name: NAMES[channel].as_ptr() as u32,
+                                    buffer: buffer(channel).as_ptr() as u32,
+                                    size: buffer(channel).len() as u32,
+                                    write: 0,
+                                    read: 0,
+                                    flags: 0,
+                                });
+        }
+        for (index, &byte) in ID.iter().enumerate() {
+            ptr::write_volatile(&mut _SEGGER_RTT.id[index], byte);
+        }
+    }
+}
+
+/// Appends `data` to the buffer of `channel`, `false` if it didn't fit
+///
+/// Each channel must only be written from one context.
+pub fn write(channel: usize, data: &[u8]) -> bool {
+    unsafe {
+        let up = &mut _SEGGER_RTT.up[channel];
+        let buffer = buffer(channel);
+        let size = buffer.len();
+        let read = ptr::read_volatile(&up.read) as usize;
+        let mut write = up.write as usize;
+        let free = if read > write {
+            read - write - 1
+        } else {
+            size - (write - read) - 1
+        };
+        if data.len() > free {
+            return false;
+        }
+
+        // volatile, the data has to be in place before the debugger sees
+        // the index
+        for &byte in data {
+            ptr::write_volatile(&mut buffer[write], byte);
+            write = (write + 1) % size;
+        }
+        ptr::write_volatile(&mut up.write, write as u32);
+    }
+    true
+}
@@ -0,0 +1,23 @@
+//! User settings persisted in the last flash page
+
+use blue_pill::stm32f103xx::FLASH;
+use flash;
+use ts100_core::settings::{Settings, ENCODED_LEN};
+
+/// Last 1 KiB page of the 64 KiB flash, excluded from FLASH in memory.x
+pub const SETTINGS_ADDRESS: u32 = 0x0800_FC00;
+
+/// Reads the settings from flash, falls back to defaults if the page is
+/// erased, corrupted or of an older version
+pub fn load() -> Settings {
+    let mut data = [0; ENCODED_LEN];
+    flash::read(SETTINGS_ADDRESS, &mut data);
+    Settings::decode(&data).unwrap_or(Settings::new())
+}
+
+pub fn save(settings: &Settings, flash: &FLASH) {
+    flash::unlock(flash);
+    flash::erase_page(flash, SETTINGS_ADDRESS);
+    flash::program(flash, SETTINGS_ADDRESS, &settings.encode());
+    flash::lock(flash);
+}
@@ -0,0 +1,299 @@
+//! USB full speed peripheral running the CDC-ACM serial console
+//!
+//! Endpoint 0 is answered by `ts100_core::usb::Control`, endpoint 1 carries
+//! the console in both directions and endpoint 2 is the notification
+//! endpoint the CDC class requires but which is never used.
+//!
+//! The USB clock has to be exactly 48 MHz, which only the PLL at HSI/2 x 12
+//! provides. The HSI is trimmed to 1 % while USB full speed asks for 0.25 %,
+//! most hosts accept the port anyway but enumeration may fail on some, the
+//! console is then simply not available. The TS100 has a fixed pull-up on
+//! D+, so the host is made to notice a reset by pulling D+ low for a moment
+//! before the peripheral is enabled.
+
+use blue_pill::stm32f103xx::{GPIOA, RCC};
+use core::ptr;
+use cortex_m;
+use rcc::Clocks;
+use ts100_core::console::Console;
+use ts100_core::settings::Settings;
+use ts100_core::usb::{Control, DATA_ENDPOINT, MAX_PACKET, NOTIFICATION_ENDPOINT};
+
+/// Required USB clock
+pub const USB_CLOCK: u32 = 48_000_000;
+
+const APB1ENR_USBEN: u32 = 1 << 23;
+
+const USB_BASE: u32 = 0x4000_5C00;
+const CNTR: u32 = USB_BASE + 0x40;
+const ISTR: u32 = USB_BASE + 0x44;
+const DADDR: u32 = USB_BASE + 0x4C;
+const BTABLE: u32 = USB_BASE + 0x50;
+/// Packet memory, 16 bit words at a 32 bit stride
+const PMA_BASE: u32 = 0x4000_6000;
+
+const CNTR_CTRM: u32 = 1 << 15;
+const CNTR_RESETM: u32 = 1 << 10;
+const CNTR_FRES: u32 = 1 << 0;
+const ISTR_CTR: u32 = 1 << 15;
+const ISTR_RESET: u32 = 1 << 10;
+const ISTR_EP_ID: u32 = 0xf;
+const DADDR_EF: u32 = 1 << 7;
+
+/// Endpoint register bits, `CTR_*` are cleared by writing 0, `DTOG_*` and
+/// `STAT_*` are toggled by writing 1, the rest is plain read write
+const EP_CTR_RX: u32 = 1 << 15;
+const EP_DTOG_RX: u32 = 1 << 14;
+const EP_STAT_RX: u32 = 0b11 << 12;
+const EP_SETUP: u32 = 1 << 11;
+const EP_PLAIN: u32 = 0b111 << 8 | 0xf;
+const EP_CTR_TX: u32 = 1 << 7;
+const EP_DTOG_TX: u32 = 1 << 6;
+const EP_STAT_TX: u32 = 0b11 << 4;
+const EP_TYPE_BULK: u32 = 0b00 << 9;
+const EP_TYPE_CONTROL: u32 = 0b01 << 9;
+const EP_TYPE_INTERRUPT: u32 = 0b11 << 9;
+
+/// Endpoint status
+const STALL: u32 = 0b01;
+const NAK: u32 = 0b10;
+const VALID: u32 = 0b11;
+
+/// Buffer offsets in the packet memory, the buffer table comes first
+const EP0_RX: u16 = 0x40;
+const EP0_TX: u16 = 0x80;
+const EP1_RX: u16 = 0xC0;
+const EP1_TX: u16 = 0x100;
+const EP2_TX: u16 = 0x140;
+/// COUNT_RX for 64 byte buffers, 2 blocks of 32 bytes
+const COUNT_RX_64: u16 = 1 << 15 | 1 << 10;
+
+/// Loop iterations of the D+ reset pulse, about 10 ms
+const DETACH_DELAY: u32 = 200_000;
+/// Loop iterations waiting for the last reply before a reset
+const FLUSH_TIMEOUT: u32 = 500_000;
+
+/// Enables the peripheral, `false` if the USB clock isn't 48 MHz
+pub fn init(rcc: &RCC, gpioa: &GPIOA, clocks: &Clocks) -> bool {
+    if clocks.usbclk != Some(USB_CLOCK) {
+        return false;
+    }
+
+    // PA12 is D+, low until the host noticed the detach
+    gpioa.bsrr.write(|w| w.br12().set_bit());
+    gpioa.crh.modify(|_, w| w.mode12().output2().cnf12().push());
+    for _ in 0..DETACH_DELAY {
+        cortex_m::asm::nop();
+    }
+    gpioa.crh.modify(|_, w| unsafe { w.mode12().input().cnf12().bits(1) });
+
+    rcc.apb1enr.modify(|r, w| unsafe { w.bits(r.bits() | APB1ENR_USBEN) });
+
+    // clearing PDWN powers up, the analog part needs 1 µs until the reset
+    // may be released
+    write(CNTR, CNTR_FRES);
+    for _ in 0..100 {
+        cortex_m::asm::nop();
+    }
+    write(CNTR, 0);
+    write(ISTR, 0);
+    write(CNTR, CNTR_CTRM | CNTR_RESETM);
+
+    true
+}
+
+/// Handles all pending USB events, received bytes are executed by the
+/// console and its replies are sent
+pub fn poll(control: &mut Control, console: &mut Console, settings: &mut Settings) {
+    loop {
+        let istr = read(ISTR);
+        if istr & ISTR_RESET != 0 {
+            write(ISTR, !ISTR_RESET);
+            reset(control);
+            continue;
+        }
+        if istr & ISTR_CTR == 0 {
+            break;
+        }
+
+        let endpoint = istr & ISTR_EP_ID;
+        let epr = read_ep(endpoint);
+        match endpoint {
+            0 => control_transfer(control, epr),
+            1 => {
+                if epr & EP_CTR_RX != 0 {
+                    clear_ctr(endpoint, EP_CTR_RX);
+                    let mut packet = [0; MAX_PACKET];
+                    let count = read_packet(EP1_RX, 1, &mut packet);
+                    console.receive(&packet[..count], settings);
+                    set_stat(endpoint, EP_STAT_RX, VALID << 12);
+                }
+                if epr & EP_CTR_TX != 0 {
+                    clear_ctr(endpoint, EP_CTR_TX);
+                }
+            }
+            _ => clear_ctr(endpoint, EP_CTR_RX | EP_CTR_TX),
+        }
+    }
+
+    transmit(control, console);
+}
+
+/// Sends the next console packet unless the last one is still pending,
+/// output is discarded while no program has the port open
+pub fn transmit(control: &Control, console: &mut Console) {
+    let mut packet = [0; MAX_PACKET];
+    if !control.connected() {
+        while console.transmit(&mut packet) > 0 {}
+        return;
+    }
+    if read_ep(1) & EP_STAT_TX == VALID << 4 {
+        return;
+    }
+
+    let count = console.transmit(&mut packet);
+    if count > 0 {
+        write_packet(EP1_TX, 1, &packet[..count]);
+        set_stat(1, EP_STAT_TX, VALID << 4);
+    }
+}
+
+/// Waits until the console output has been sent, or for a while
+pub fn flush(control: &Control, console: &mut Console) {
+    for _ in 0..FLUSH_TIMEOUT {
+        transmit(control, console);
+        let sending = read_ep(1) & EP_STAT_TX == VALID << 4;
+        if !sending && !console.has_output() {
+            return;
+        }
+        if read_ep(1) & EP_CTR_TX != 0 {
+            clear_ctr(1, EP_CTR_TX);
+        }
+    }
+}
+
+/// Sets up the endpoints after a bus reset, the device answers address 0
+fn reset(control: &mut Control) {
+    control.reset();
+
+    write(BTABLE, 0);
+    buffer_table(0, EP0_TX, EP0_RX);
+    buffer_table(1, EP1_TX, EP1_RX);
+    buffer_table(2, EP2_TX, 0);
+
+    configure(0, EP_TYPE_CONTROL, VALID, NAK);
+    configure(u32::from(DATA_ENDPOINT), EP_TYPE_BULK, VALID, NAK);
+    configure(u32::from(NOTIFICATION_ENDPOINT), EP_TYPE_INTERRUPT, 0, NAK);
+
+    write(DADDR, DADDR_EF);
+}
+
+fn control_transfer(control: &mut Control, epr: u32) {
+    let mut packet = [0; MAX_PACKET];
+
+    if epr & EP_CTR_RX != 0 {
+        let count = read_packet(EP0_RX, 0, &mut packet);
+        clear_ctr(0, EP_CTR_RX);
+
+        if epr & EP_SETUP != 0 {
+            if !control.setup(&packet[..count]) {
+                set_stat(0, EP_STAT_TX | EP_STAT_RX, STALL << 4 | STALL << 12);
+                return;
+            }
+        } else {
+            control.out_packet(&packet[..count]);
+        }
+        send_control(control, &mut packet);
+        set_stat(0, EP_STAT_RX, VALID << 12);
+    }
+
+    if epr & EP_CTR_TX != 0 {
+        clear_ctr(0, EP_CTR_TX);
+        if let Some(address) = control.in_complete() {
+            write(DADDR, DADDR_EF | u32::from(address));
+        }
+        send_control(control, &mut packet);
+    }
+}
+
+fn send_control(control: &mut Control, packet: &mut [u8; MAX_PACKET]) {
+    if let Some(count) = control.transmit(packet) {
+        write_packet(EP0_TX, 0, &packet[..count]);
+        set_stat(0, EP_STAT_TX, VALID << 4);
+    }
+}
+
+fn buffer_table(endpoint: u16, tx: u16, rx: u16) {
+    let entry = endpoint * 8;
+    write_pma(entry, tx);
+    write_pma(entry + 2, 0);
+    write_pma(entry + 4, rx);
+    write_pma(entry + 6, if rx != 0 { COUNT_RX_64 } else { 0 });
+}
+
+/// Sets type, address and both statuses and clears the data toggles
+fn configure(endpoint: u32, kind: u32, rx: u32, tx: u32) {
+    let toggles = EP_DTOG_RX | EP_STAT_RX | EP_DTOG_TX | EP_STAT_TX;
+    let current = read_ep(endpoint) & toggles;
+    let wanted = rx << 12 | tx << 4;
+    write_ep(endpoint, kind | endpoint | (current ^ wanted));
+}
+
+/// Changes the status bits in `mask` to `status` without touching the rest
+fn set_stat(endpoint: u32, mask: u32, status: u32) {
+    let epr = read_ep(endpoint);
+    write_ep(endpoint,
+             (epr & EP_PLAIN) | EP_CTR_RX | EP_CTR_TX | ((epr & mask) ^ status));
+}
+
+/// Clears the transfer complete flags in `flags`
+fn clear_ctr(endpoint: u32, flags: u32) {
+    let epr = read_ep(endpoint);
+    write_ep(endpoint, (epr & EP_PLAIN) | ((EP_CTR_RX | EP_CTR_TX) & !flags));
+}
+
+/// Copies a received packet out of the packet memory
+fn read_packet(buffer: u16, endpoint: u16, packet: &mut [u8; MAX_PACKET]) -> usize {
+    let count = (usize::from(read_pma(endpoint * 8 + 6)) & 0x3ff).min(MAX_PACKET);
+    for index in 0..(count + 1) / 2 {
+        let half_word = read_pma(buffer + 2 * index as u16);
+        packet[2 * index] = half_word as u8;
+        if 2 * index + 1 < count {
+            packet[2 * index + 1] = (half_word >> 8) as u8;
+        }
+    }
+    count
+}
+
+/// Copies a packet into the packet memory and sets its length
+fn write_packet(buffer: u16, endpoint: u16, data: &[u8]) {
+    for (index, pair) in data.chunks(2).enumerate() {
+        let high = if pair.len() > 1 { u16::from(pair[1]) << 8 } else { 0 };
+        write_pma(buffer + 2 * index as u16, u16::from(pair[0]) | high);
+    }
+    write_pma(endpoint * 8 + 2, data.len() as u16);
+}
+
+fn read(register: u32) -> u32 {
+    unsafe { ptr::read_volatile(register as *const u32) }
+}
+
+fn write(register: u32, value: u32) {
+    unsafe { ptr::write_volatile(register as *mut u32, value) }
+}
+
+fn read_ep(endpoint: u32) -> u32 {
+    read(USB_BASE + 4 * endpoint)
+}
+
+fn write_ep(endpoint: u32, value: u32) {
+    write(USB_BASE + 4 * endpoint, value)
+}
+
+fn read_pma(offset: u16) -> u16 {
+    unsafe { ptr::read_volatile((PMA_BASE + 2 * u32::from(offset)) as *const u32) as u16 }
+}
+
+fn write_pma(offset: u16, value: u16) {
+    unsafe { ptr::write_volatile((PMA_BASE + 2 * u32::from(offset)) as *mut u32, u32::from(value)) }
+}
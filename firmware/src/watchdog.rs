@@ -0,0 +1,105 @@
+//! Independent watchdog supervising the tasks
+//!
+//! The IWDG runs from the LSI and resets the chip if it isn't fed for about
+//! 2 s. It's only fed while every supervised task checked in within its
+//! deadline, so a hang in any of them, e.g. in a blocking I2C loop, resets
+//! the iron instead of leaving the heater on.
+
+use blue_pill::stm32f103xx::IWDG;
+use core::ptr;
+use ts100_core::time;
+
+/// Supervised tasks
+#[derive(Clone, Copy, PartialEq)]
+pub enum Task {
+    Control = 0,
+    Adc = 1,
+    Ui = 2,
+}
+
+impl Task {
+    pub fn from_index(index: u16) -> Option<Task> {
+        match index {
+            0 => Some(Task::Control),
+            1 => Some(Task::Adc),
+            2 => Some(Task::Ui),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Task::Control => "CONTROL",
+            Task::Adc => "ADC",
+            Task::Ui => "UI",
+        }
+    }
+}
+
+const TASKS: usize = 3;
+
+const IDS: [Task; TASKS] = [Task::Control, Task::Adc, Task::Ui];
+
+/// Longest time in ms between two check ins of each task
+const DEADLINES: [u32; TASKS] = [100, 100, 1000];
+
+const KEY_ENABLE: u32 = 0xCCCC;
+const KEY_FEED: u32 = 0xAAAA;
+const KEY_UNLOCK: u32 = 0x5555;
+/// LSI (40 kHz) / 64
+const PRESCALER_64: u32 = 0b100;
+/// 2 s at 625 Hz
+const RELOAD: u32 = 1250;
+
+/// DBGMCU_CR, the watchdog is stopped while the core is halted by a debugger
+const DBGMCU_CR: u32 = 0xE004_2004;
+const DBG_IWDG_STOP: u32 = 1 << 8;
+
+/// Starts the watchdog, it can't be stopped anymore
+pub fn start(iwdg: &IWDG) {
+    unsafe {
+        let cr = ptr::read_volatile(DBGMCU_CR as *const u32);
+        ptr::write_volatile(DBGMCU_CR as *mut u32, cr | DBG_IWDG_STOP);
+    }
+
+    iwdg.kr.write(|w| unsafe { w.bits(KEY_UNLOCK) });
+    iwdg.pr.write(|w| unsafe { w.bits(PRESCALER_64) });
+    iwdg.rlr.write(|w| unsafe { w.bits(RELOAD) });
+    while iwdg.sr.read().bits() != 0 {}
+    iwdg.kr.write(|w| unsafe { w.bits(KEY_ENABLE) });
+}
+
+pub fn feed(iwdg: &IWDG) {
+    iwdg.kr.write(|w| unsafe { w.bits(KEY_FEED) });
+}
+
+pub struct Supervisor {
+    /// Time of the last check in of each task
+    seen: [u32; TASKS],
+}
+
+impl Supervisor {
+    pub const fn new() -> Self {
+        Supervisor { seen: [0; TASKS] }
+    }
+
+    /// Starts all deadlines at `now`
+    pub fn start(&mut self, now: u32) {
+        self.seen = [now; TASKS];
+    }
+
+    pub fn check_in(&mut self, task: Task, now: u32) {
+        self.seen[task as usize] = now;
+    }
+
+    /// Returns the first task which missed its deadline
+    pub fn overdue(&self, now: u32) -> Option<Task> {
+        for ((seen, deadline), task) in self.seen.iter().zip(DEADLINES.iter()).zip(IDS.iter()) {
+            if time::elapsed(now, *seen) > *deadline {
+                return Some(*task);
+            }
+        }
+
+        None
+    }
+}
@@ -0,0 +1,101 @@
+//! Simulated accelerometer
+//!
+//! Produces samples at 100 Hz like the configured MMA8652FC: gravity along
+//! Z while the iron rests in its stand, plus a little sensor noise. Moving
+//! the iron adds a slow swing on X and Y. Samples are buffered in a FIFO
+//! which is drained at the same watermark the firmware uses.
+
+use ts100_core::accel::Accel;
+
+/// Milliseconds between samples
+pub const SAMPLE_PERIOD: u32 = 10;
+/// Buffered samples raising the FIFO interrupt
+pub const FIFO_WATERMARK: usize = 16;
+
+/// Peak to peak sensor noise in milli-g
+const NOISE: u32 = 8;
+/// Amplitude in milli-g and period in ms of the swing while moving
+const SWING: i32 = 250;
+const SWING_PERIOD: u32 = 400;
+
+pub struct Accelerometer {
+    moving: u32,
+    /// Milliseconds until the next sample
+    countdown: u32,
+    rng: u32,
+    fifo: Vec<Accel>,
+}
+
+impl Accelerometer {
+    pub fn new() -> Self {
+        Accelerometer {
+            moving: 0,
+            countdown: SAMPLE_PERIOD,
+            rng: 0x7510_2017,
+            fifo: Vec::with_capacity(FIFO_WATERMARK),
+        }
+    }
+
+    /// Moves the iron for `ms` milliseconds
+    pub fn shake(&mut self, ms: u32) {
+        self.moving = self.moving.max(ms);
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.moving > 0
+    }
+
+    /// Advances one millisecond, returns the FIFO contents once the
+    /// watermark is reached
+    pub fn step(&mut self, now: u32) -> Option<Vec<Accel>> {
+        let swing = if self.moving > 0 {
+            self.moving -= 1;
+            triangle(now, SWING_PERIOD) * SWING / 1000
+        } else {
+            0
+        };
+
+        self.countdown -= 1;
+        if self.countdown > 0 {
+            return None;
+        }
+        self.countdown = SAMPLE_PERIOD;
+
+        let sample = Accel {
+            x: (swing + self.noise()) as i16,
+            y: (swing / 2 + self.noise()) as i16,
+            z: (1000 + self.noise()) as i16,
+        };
+        self.fifo.push(sample);
+
+        if self.fifo.len() < FIFO_WATERMARK {
+            return None;
+        }
+        Some(self.fifo.split_off(0))
+    }
+
+    /// Uniform noise from a xorshift generator, the sequence is the same in
+    /// every run
+    fn noise(&mut self) -> i32 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 17;
+        self.rng ^= self.rng << 5;
+        (self.rng % (NOISE + 1)) as i32 - (NOISE / 2) as i32
+    }
+}
+
+impl Default for Accelerometer {
+    fn default() -> Self {
+        Accelerometer::new()
+    }
+}
+
+/// Triangle wave between -1000 and 1000
+fn triangle(now: u32, period: u32) -> i32 {
+    let phase = (now % period) as i32 * 4000 / period as i32;
+    if phase < 2000 {
+        phase - 1000
+    } else {
+        3000 - phase
+    }
+}
@@ -0,0 +1,46 @@
+//! Runs the scenarios in `simulator/scenarios`
+//!
+//! `ts100-scenario [--bless] [--seed N] [NAME...]` runs the named scenarios
+//! or all of them and exits with an error if any failed. `--bless` rewrites
+//! the golden images from the current screens instead of comparing them,
+//! `--seed` starts the random checks at another seed.
+
+extern crate ts100_simulator;
+
+use std::path::Path;
+use std::process;
+use ts100_simulator::scenario;
+
+fn main() {
+    let mut bless = false;
+    let mut seed = 1;
+    let mut names = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--bless" => bless = true,
+            "--seed" => match args.next().and_then(|seed| seed.parse().ok()) {
+                Some(value) => seed = value,
+                None => {
+                    eprintln!("--seed needs a number");
+                    process::exit(2);
+                }
+            },
+            "-h" | "--help" => {
+                println!("usage: ts100-scenario [--bless] [--seed N] [NAME...]");
+                return;
+            }
+            _ => names.push(arg),
+        }
+    }
+
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("scenarios");
+    match scenario::run_all(&dir, &names, bless, seed) {
+        Ok(0) => {}
+        Ok(_) => process::exit(1),
+        Err(error) => {
+            eprintln!("{}: {}", dir.display(), error);
+            process::exit(2);
+        }
+    }
+}
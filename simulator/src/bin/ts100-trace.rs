@@ -0,0 +1,43 @@
+//! Decodes a recording of the control loop records to CSV
+//!
+//! `ts100-trace [FILE]` reads the RTT stream from `FILE` or stdin and
+//! writes one CSV line per record to stdout, see `trace`. The number of
+//! damaged frames goes to stderr.
+
+extern crate ts100_simulator;
+
+use std::fs::File;
+use std::io::{self, Read};
+use std::process;
+use ts100_simulator::trace;
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") || args.len() > 1 {
+        println!("usage: ts100-trace [FILE]");
+        return;
+    }
+
+    let mut data = Vec::new();
+    let result = match args.first() {
+        Some(path) => File::open(path).and_then(|mut file| file.read_to_end(&mut data)),
+        None => io::stdin().read_to_end(&mut data),
+    };
+    if let Err(error) = result {
+        eprintln!("ts100-trace: {}", error);
+        process::exit(1);
+    }
+
+    let stdout = io::stdout();
+    match trace::write_csv(&data, &mut stdout.lock()) {
+        Ok(stats) => {
+            if stats.invalid > 0 {
+                eprintln!("{} records, {} damaged frames skipped", stats.records, stats.invalid);
+            }
+        }
+        Err(error) => {
+            eprintln!("ts100-trace: {}", error);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,31 @@
+//! Configures and monitors an iron over its serial console
+//!
+//! `ts100ctl [--port PATH | --simulate] COMMAND`, see `ctl` for the
+//! commands.
+
+extern crate ts100_simulator;
+
+use std::io;
+use std::process;
+use ts100_simulator::ctl::{self, Error};
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        println!("{}", ctl::USAGE);
+        return;
+    }
+
+    let stdout = io::stdout();
+    match ctl::run(&args, &mut stdout.lock()) {
+        Ok(()) => {}
+        Err(error @ Error::Usage(_)) => {
+            eprintln!("{}", error);
+            process::exit(2);
+        }
+        Err(error) => {
+            eprintln!("ts100ctl: {}", error);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,372 @@
+//! The firmware logic wired to simulated peripherals
+//!
+//! `Board::step` advances the simulation by one millisecond and does what
+//! the interrupt handlers of the firmware do in that time, in the same
+//! order: button edges and debouncing, timers and tip sampling, the
+//! accelerometer FIFO and finally the event dispatch of `update_ui`. The
+//! serial console is fed directly instead of through USB, the RTT records
+//! are collected in `trace` while it is `Some` and the journal is kept in a
+//! `MemoryFlash`. Time is kept per
+//! board instead of in the global timebase so several boards can run side
+//! by side.
+
+use accelerometer::Accelerometer;
+use flash::MemoryFlash;
+use plant::Plant;
+use std::mem;
+use ts100_core::{control, ui};
+use ts100_core::accel::{self, Gesture};
+use ts100_core::console::{Console, Request, Telemetry};
+use ts100_core::control::Control;
+use ts100_core::event::{Event, Queue};
+use ts100_core::input::{Button, Input};
+use ts100_core::journal::{Event as JournalEvent, Journal, Monitor, ResetCause};
+use ts100_core::motion::MotionDetector;
+use ts100_core::settings::{self, Settings};
+use ts100_core::state::{Action, ConfigPage, State, StateMachine};
+use ts100_core::temperature::Sample;
+use ts100_core::time::{self, TimerId, Timers};
+use ts100_core::trace::{Record, FRAME_MAX};
+use ts100_core::ui::Framebuffer;
+
+/// Same intervals as the firmware
+const REFRESH_INTERVAL: u32 = 200;
+const MENU_TIMEOUT: u32 = 30_000;
+const SAMPLE_INTERVAL: u32 = 20;
+
+/// Room temperature the simulation starts at in °C
+pub const AMBIENT: f32 = 25.0;
+
+pub struct Board {
+    pub state: StateMachine,
+    pub plant: Plant,
+    pub accelerometer: Accelerometer,
+    pub settings: Settings,
+    /// Settings page of the simulated flash
+    pub flash: Option<[u16; settings::ENCODED_LEN]>,
+    pub framebuffer: Framebuffer,
+    pub console: Console,
+    /// RTT stream of the control loop records, recorded while `Some`
+    pub trace: Option<Vec<u8>>,
+    pub journal: Journal,
+    /// Journal pages of the simulated flash
+    pub journal_flash: MemoryFlash,
+    monitor: Monitor,
+    /// Record shown on the `Config(Events)` page
+    event_age: usize,
+    /// Console output sent to the host and not read yet
+    output: Vec<u8>,
+    input: Input,
+    timers: Timers,
+    events: Queue,
+    motion: MotionDetector,
+    control: Control,
+    sample: Sample,
+    heating: bool,
+    /// Scheduled release of each button
+    releases: [Option<u32>; 2],
+    /// Heater on time since `duty_since` and the duty of the last second
+    heating_ms: u32,
+    duty_since: u32,
+    duty: u32,
+    now: u32,
+}
+
+impl Default for Board {
+    fn default() -> Self {
+        Board::new()
+    }
+}
+
+impl Board {
+    pub fn new() -> Self {
+        let settings = Settings::new();
+        let mut board = Board {
+            state: StateMachine::new(),
+            plant: Plant::new(AMBIENT),
+            accelerometer: Accelerometer::new(),
+            settings,
+            flash: None,
+            framebuffer: Framebuffer::new(),
+            console: Console::new(),
+            trace: None,
+            journal: Journal::new(),
+            journal_flash: MemoryFlash::new(),
+            monitor: Monitor::new(),
+            event_age: 0,
+            output: Vec::new(),
+            input: Input::new(),
+            timers: Timers::new(),
+            events: Queue::new(),
+            motion: MotionDetector::new(settings.motion_sensitivity),
+            control: Control::new(),
+            sample: Plant::new(AMBIENT).sample(),
+            heating: false,
+            releases: [None; 2],
+            heating_ms: 0,
+            duty_since: 0,
+            duty: 0,
+            now: 0,
+        };
+
+        board.state.set_accel_present(true);
+        board.timers.start_periodic(TimerId::Second, 0, 1000);
+        board.timers.start_periodic(TimerId::Refresh, 0, REFRESH_INTERVAL);
+        board.timers.start_periodic(TimerId::Sample, 0, SAMPLE_INTERVAL);
+        board.open_journal(ResetCause::PowerOn);
+        ui::render(&board.state, &mut board.framebuffer);
+        board
+    }
+
+    /// Reads the journal from the flash and records the boot like `init`
+    fn open_journal(&mut self, cause: ResetCause) {
+        self.journal = Journal::open(&self.journal_flash);
+        self.append_event(JournalEvent::Boot(cause));
+    }
+
+    fn append_event(&mut self, event: JournalEvent) {
+        let uptime = self.now / 1000;
+        self.journal.append(&mut self.journal_flash, event, uptime);
+    }
+
+    /// Milliseconds since the simulation started
+    pub fn now(&self) -> u32 {
+        self.now
+    }
+
+    pub fn current_state(&self) -> State {
+        self.state.current_state()
+    }
+
+    pub fn is_heating(&self) -> bool {
+        self.heating
+    }
+
+    /// Heater duty cycle of the last full second in percent
+    pub fn duty(&self) -> u32 {
+        self.duty
+    }
+
+    /// Tip temperature as measured by the last sample
+    pub fn tip_celsius(&self) -> i16 {
+        self.sample.tip_celsius()
+    }
+
+    pub fn setpoint(&self) -> Option<i16> {
+        control::setpoint(self.current_state())
+    }
+
+    /// Changes the level of `button` like a (bounce free) edge on its pin
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        self.releases[button as usize] = None;
+        self.input.edge(button, pressed, self.now);
+    }
+
+    /// Presses `button` and releases it `ms` milliseconds later
+    pub fn press(&mut self, button: Button, ms: u32) {
+        self.set_button(button, true);
+        self.releases[button as usize] = Some(self.now.wrapping_add(ms));
+    }
+
+    /// Reports a gesture like the tap interrupt of the accelerometer
+    pub fn gesture(&mut self, gesture: Gesture) {
+        self.push(Event::Gesture(gesture));
+    }
+
+    /// Removes the accelerometer, the iron falls back to timed sleep
+    pub fn disconnect_accelerometer(&mut self) {
+        self.state.set_accel_present(false);
+        self.append_event(JournalEvent::AccelError(accel::Error::NotPresent));
+    }
+
+    /// Passes bytes to the console like a USB packet from the host
+    pub fn console_write(&mut self, data: &[u8]) {
+        self.console.receive(data, &mut self.settings);
+        self.drain_console();
+        self.motion.set_sensitivity(self.settings.motion_sensitivity);
+
+        match self.console.take_request() {
+            Some(Request::SaveSettings) => self.flash = Some(self.settings.encode()),
+            Some(Request::Reset) => self.reboot(),
+            None => {}
+        }
+    }
+
+    /// Takes everything the console sent so far
+    pub fn console_read(&mut self) -> Vec<u8> {
+        self.drain_console();
+        mem::take(&mut self.output)
+    }
+
+    /// Restarts the firmware with the settings from the flash, the tip
+    /// keeps its temperature and the console output sent before is kept
+    pub fn reboot(&mut self) {
+        self.drain_console();
+
+        let mut board = Board::new();
+        board.plant = self.plant;
+        board.flash = self.flash;
+        board.journal_flash = mem::take(&mut self.journal_flash);
+        board.open_journal(ResetCause::Software);
+        if let Some(settings) = self.flash.and_then(|data| Settings::decode(&data)) {
+            board.settings = settings;
+            board.motion.set_sensitivity(settings.motion_sensitivity);
+        }
+        if !self.state.accel_present() {
+            board.disconnect_accelerometer();
+        }
+        board.output = mem::take(&mut self.output);
+        board.trace = self.trace.take();
+        *self = board;
+    }
+
+    /// Moves the queued console output to `output` like the USB endpoint
+    fn drain_console(&mut self) {
+        let mut packet = [0; 64];
+        loop {
+            self.console.continue_export(&self.journal, &self.journal_flash);
+            let count = self.console.transmit(&mut packet);
+            if count == 0 {
+                return;
+            }
+            self.output.extend_from_slice(&packet[..count]);
+        }
+    }
+
+    /// Advances the simulation by `ms` milliseconds
+    pub fn run(&mut self, ms: u32) {
+        for _ in 0..ms {
+            self.step();
+        }
+    }
+
+    /// Advances the simulation by one millisecond
+    pub fn step(&mut self) {
+        self.now = self.now.wrapping_add(1);
+        let now = self.now;
+
+        // SYS_TICK
+        for index in 0..2 {
+            if let Some(release) = self.releases[index] {
+                if time::reached(now, release) {
+                    self.releases[index] = None;
+                    let button = if index == 0 { Button::A } else { Button::B };
+                    self.input.edge(button, false, now);
+                }
+            }
+        }
+
+        self.input.poll(now);
+        while let Some(key) = self.input.pop() {
+            self.push(Event::Key(key));
+        }
+
+        while let Some(id) = self.timers.expired(now) {
+            match id {
+                // the conversion is quick, ADC1_2 and EXTI1 follow at once
+                TimerId::Sample => {
+                    self.sample = self.plant.sample();
+                    let state = self.state.current_state();
+                    let tip = self.sample.tip_celsius();
+                    self.heating = self.control.update(state, tip);
+
+                    if let Some(ref mut trace) = self.trace {
+                        let record = Record::capture(now,
+                                                     &self.sample,
+                                                     state,
+                                                     control::setpoint(state),
+                                                     self.heating,
+                                                     self.control.duty());
+                        let mut frame = [0; FRAME_MAX];
+                        let len = record.encode(&mut frame);
+                        trace.extend_from_slice(&frame[..len]);
+                    }
+
+                    if self.console.telemetry_due(now) {
+                        let accel = self.state.get_accel();
+                        self.console.send_telemetry(&Telemetry {
+                            time: now,
+                            tip,
+                            setpoint: control::setpoint(state),
+                            duty: self.control.duty(),
+                            vin: Some(self.sample.supply_mv()),
+                            accel: [accel.x, accel.y, accel.z],
+                        });
+                        self.drain_console();
+                    }
+                }
+                _ => self.push(Event::Timer(id)),
+            }
+        }
+
+        // EXTI9_5
+        if self.state.accel_present() {
+            if let Some(samples) = self.accelerometer.step(now) {
+                if let Some(motion) = self.motion.update(&samples) {
+                    self.push(Event::Motion(motion));
+                }
+                if let Some(&last) = samples.last() {
+                    self.push(Event::Accel(last));
+                }
+            }
+        }
+
+        self.plant.step(1, self.heating);
+        if self.heating {
+            self.heating_ms += 1;
+        }
+        if time::elapsed(now, self.duty_since) >= 1000 {
+            self.duty_since = now;
+            self.duty = self.heating_ms / 10;
+            self.heating_ms = 0;
+        }
+
+        // EXTI0
+        if !self.events.is_empty() {
+            self.update_ui();
+        }
+    }
+
+    fn push(&mut self, event: Event) {
+        let _ = self.events.push(event);
+    }
+
+    fn update_ui(&mut self) {
+        // the actions of an event are taken before the next one
+        while let Some(event) = self.events.pop() {
+            self.state.update_state(event);
+
+            while let Some(action) = self.state.take_action() {
+                match action {
+                    // the simulated sensor needs no offset correction
+                    Action::CalibrateAccel => {
+                        self.settings.accel_offset = [0; 3];
+                        self.flash = Some(self.settings.encode());
+                    }
+                    Action::SaveSettings => self.flash = Some(self.settings.encode()),
+                    Action::HeaterOff => self.heating = false,
+                    Action::StartMenuTimeout => {
+                        self.timers.start(TimerId::MenuTimeout, self.now, MENU_TIMEOUT)
+                    }
+                    Action::ShowEvents => self.event_age = 0,
+                    Action::OlderEvent => {
+                        let len = self.journal.len(&self.journal_flash);
+                        self.event_age =
+                            if self.event_age + 1 < len { self.event_age + 1 } else { 0 };
+                    }
+                }
+            }
+        }
+
+        if let Some(event) = self.monitor.update(self.sample.tip_celsius(), self.sample.supply_mv()) {
+            self.append_event(event);
+        }
+
+        if self.state.current_state() == State::Config(ConfigPage::Events) {
+            let record = self.journal.get(&self.journal_flash, self.event_age);
+            ui::render_event(&mut self.framebuffer, self.event_age, record);
+        } else {
+            ui::render(&self.state, &mut self.framebuffer);
+        }
+    }
+}
@@ -0,0 +1,238 @@
+//! Fuzzing of the serial console
+//!
+//! Random input, a mix of arbitrary bytes and fragments of valid commands
+//! split at random points, is fed into a console interleaved with telemetry
+//! records. The console must neither panic nor reply with anything but
+//! complete lines of the known forms, and the settings have to stay valid:
+//!
+//! - `ok`, `error: REASON`, `NAME=VALUE` within the range of the setting,
+//!   the `help` text, a telemetry record or a record of the journal
+//! - every reply ends with `ok` or an error, at most one per received line
+//!   terminator
+//! - the settings survive an encode and decode round trip
+//!
+//! A failing run is reported with its seed and input so it can be replayed.
+
+use flash::MemoryFlash;
+use rng::Rng;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use ts100_core::console::{Console, Error, Setting, Telemetry, SETTINGS};
+use ts100_core::journal::{Event, Journal, Record};
+use ts100_core::settings::Settings;
+
+/// Fragments making up most of the input
+const FRAGMENTS: [&str; 27] = [
+    "help", "get", "set", "dump", "save", "defaults", "reset", "telemetry", "off", "events",
+    "motion_sensitivity", "accel_offset_x", "accel_offset_y", "accel_offset_z",
+    " ", "  ", "\t", "\r", "\n", "\r\n", "0", "5", "-128", "127", "9999999999", "20", "x",
+];
+
+/// Every error reason the console may reply with
+const ERRORS: [Error; 9] = [
+    Error::UnknownCommand,
+    Error::UnknownSetting,
+    Error::MissingArgument,
+    Error::TooManyArguments,
+    Error::InvalidNumber,
+    Error::OutOfRange,
+    Error::LineTooLong,
+    Error::InvalidUtf8,
+    Error::Busy,
+];
+
+/// Bytes of input shown for a failure
+const INPUT_SHOWN: usize = 200;
+
+/// A run breaking one of the rules
+pub struct Failure {
+    pub seed: u32,
+    /// Everything received up to the failure
+    pub input: Vec<u8>,
+    pub message: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // the end of the input is what matters, the rest replays with the seed
+        let start = self.input.len().saturating_sub(INPUT_SHOWN);
+        writeln!(f, "seed {}: {}", self.seed, self.message)?;
+        write!(f, "    input ...{:?}", String::from_utf8_lossy(&self.input[start..]))
+    }
+}
+
+/// Numbers around the limits of the settings
+const NUMBERS: [i64; 12] = [-129, -128, -1, 0, 1, 9, 10, 19, 20, 127, 128, 1 << 32];
+
+/// A complete, mostly valid command line
+fn random_command(rng: &mut Rng) -> String {
+    let name = rng.pick(&SETTINGS).0;
+    let number = rng.pick(&NUMBERS);
+    match rng.below(4) {
+        0 => format!("get {}\r\n", name),
+        1 => format!("telemetry {}\n", number),
+        _ => format!("set {} {}\r\n", name, number),
+    }
+}
+
+/// Random input, split into chunks as USB packets would deliver it
+fn random_input(rng: &mut Rng, chunks: usize) -> Vec<Vec<u8>> {
+    let mut input = Vec::new();
+    while input.len() < chunks * 32 {
+        match rng.below(10) {
+            0 => input.push(rng.next_u32() as u8),
+            1 => input.extend(std::iter::repeat_n(b'a', rng.below(100) as usize)),
+            2..=4 => input.extend_from_slice(random_command(rng).as_bytes()),
+            _ => input.extend_from_slice(rng.pick(&FRAGMENTS).as_bytes()),
+        }
+    }
+
+    let mut split = Vec::new();
+    let mut rest = &input[..];
+    while !rest.is_empty() {
+        let len = (1 + rng.below(64) as usize).min(rest.len());
+        split.push(rest[..len].to_vec());
+        rest = &rest[len..];
+    }
+    split
+}
+
+/// Checks a single reply line without terminator
+fn check_line(line: &str) -> Result<(), String> {
+    if line == "ok" || line.starts_with("commands: ") || line.starts_with("settings: ") {
+        return Ok(());
+    }
+    if line.starts_with("T ") {
+        return if Telemetry::parse(line).is_some() {
+            Ok(())
+        } else {
+            Err(format!("malformed telemetry {:?}", line))
+        };
+    }
+    if Record::parse(line).is_some() {
+        return Ok(());
+    }
+    if let Some(reason) = line.strip_prefix("error: ") {
+        return if ERRORS.iter().any(|error| error.reason() == reason) {
+            Ok(())
+        } else {
+            Err(format!("unknown error {:?}", line))
+        };
+    }
+
+    let mut parts = line.splitn(2, '=');
+    let setting = parts.next().and_then(Setting::from_name);
+    let value = parts.next().and_then(|value| value.parse::<i32>().ok());
+    match (setting, value) {
+        (Some(setting), Some(value)) => {
+            let (min, max) = setting.range();
+            if min <= value && value <= max {
+                Ok(())
+            } else {
+                Err(format!("{} out of range", line))
+            }
+        }
+        _ => Err(format!("unexpected reply {:?}", line)),
+    }
+}
+
+/// Feeds about `chunks` random chunks into a fresh console
+fn fuzz_one(seed: u32, chunks: usize, input: &mut Vec<u8>) -> Result<(), String> {
+    let mut rng = Rng(seed.wrapping_mul(2_654_435_761) | 1);
+    let mut console = Console::new();
+    let mut settings = Settings::new();
+    let mut output = Vec::new();
+    let mut now = 0u32;
+
+    // more records than a few packets hold
+    let mut flash = MemoryFlash::new();
+    let mut journal = Journal::open(&flash);
+    for uptime in 0..40 {
+        journal.append(&mut flash, Event::ThermalRunaway(rng.next_u32() as i16), uptime);
+    }
+
+    for chunk in random_input(&mut rng, chunks) {
+        input.extend_from_slice(&chunk);
+        console.receive(&chunk, &mut settings);
+        let _ = console.take_request();
+
+        now = now.wrapping_add(rng.below(100));
+        if console.telemetry_due(now) {
+            console.send_telemetry(&Telemetry {
+                time: now,
+                tip: rng.next_u32() as i16,
+                setpoint: if rng.below(2) == 0 { None } else { Some(rng.next_u32() as i16) },
+                duty: rng.below(101) as u8,
+                vin: if rng.below(2) == 0 { None } else { Some(rng.below(30_000) as u16) },
+                accel: [rng.next_u32() as i16, rng.next_u32() as i16, rng.next_u32() as i16],
+            });
+        }
+
+        // a host reading slowly in small packets
+        console.continue_export(&journal, &flash);
+        let mut packet = [0; 64];
+        let size = 1 + rng.below(64) as usize;
+        let count = console.transmit(&mut packet[..size]);
+        output.extend_from_slice(&packet[..count]);
+
+        if Settings::decode(&settings.encode()).is_none() {
+            return Err("settings don't survive encoding".to_string());
+        }
+        for &(_, setting) in SETTINGS.iter() {
+            let (min, max) = setting.range();
+            let value = setting.get(&settings);
+            if value < min || value > max {
+                return Err(format!("{} is {}", setting.name(), value));
+            }
+        }
+    }
+
+    let mut packet = [0; 64];
+    loop {
+        console.continue_export(&journal, &flash);
+        let count = console.transmit(&mut packet);
+        if count == 0 {
+            break;
+        }
+        output.extend_from_slice(&packet[..count]);
+    }
+
+    let text = String::from_utf8(output).map_err(|_| "reply isn't utf-8".to_string())?;
+    if !text.is_empty() && !text.ends_with("\r\n") {
+        return Err("incomplete reply line".to_string());
+    }
+    for line in text.split_terminator("\r\n") {
+        check_line(line)?;
+    }
+
+    let lines: Vec<_> = text.split_terminator("\r\n")
+        .filter(|line| !line.starts_with("T "))
+        .collect();
+    let ends = lines.iter().filter(|line| **line == "ok" || line.starts_with("error: ")).count();
+    if lines.last().is_some_and(|line| *line != "ok" && !line.starts_with("error: ")) {
+        return Err("reply without ok or error".to_string());
+    }
+
+    let terminators = input.iter().filter(|&&byte| byte == b'\r' || byte == b'\n').count();
+    if ends > terminators {
+        return Err(format!("{} replies to {} lines", ends, terminators));
+    }
+    Ok(())
+}
+
+/// Runs `runs` fuzzing runs of `chunks` chunks each starting at `seed`
+pub fn fuzz(seed: u32, runs: u32, chunks: usize) -> Result<(), Failure> {
+    for run in 0..runs {
+        let seed = seed.wrapping_add(run);
+        let mut input = Vec::new();
+        let result = panic::catch_unwind(AssertUnwindSafe(|| fuzz_one(seed, chunks, &mut input)));
+
+        let message = match result {
+            Ok(Ok(())) => continue,
+            Ok(Err(message)) => message,
+            Err(_) => "console panicked".to_string(),
+        };
+        return Err(Failure { seed, input, message });
+    }
+    Ok(())
+}
@@ -0,0 +1,491 @@
+//! Host side of the serial console, the logic of `ts100ctl`
+//!
+//! `Client` sends commands and collects their replies up to the final `ok`
+//! or error, telemetry records arriving in between are queued. `run`
+//! implements the command line tool on top of it:
+//!
+//! | Command                 | Effect                                           |
+//! |-------------------------|--------------------------------------------------|
+//! | `get NAME`              | prints a setting                                 |
+//! | `set NAME VALUE`        | changes a setting, `save` keeps it               |
+//! | `dump`                  | prints all settings                              |
+//! | `save`                  | writes the settings to the flash of the iron     |
+//! | `backup FILE`           | writes all settings to `FILE`                    |
+//! | `restore FILE`          | sets and saves all settings from `FILE`          |
+//! | `events`                | prints the journal of events, oldest first       |
+//! | `plot [MS] [--count N]` | plots the tip temperature every `MS` ms, for `N` |
+//! |                         | records or until interrupted                     |
+//!
+//! The port is given with `--port PATH`, `/dev/ttyACM0` by default.
+//! `--simulate` talks to a simulated iron on a pseudo terminal instead.
+
+use board::Board;
+use plot::Plot;
+use pty::{self, SimulatedPort};
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+use ts100_core::console::{Setting, Telemetry};
+use ts100_core::journal::Record;
+
+pub const USAGE: &str = "usage: ts100ctl [--port PATH | --simulate] COMMAND\n\
+                         commands: get NAME, set NAME VALUE, dump, save, backup FILE, \
+                         restore FILE, events, plot [MS] [--count N]";
+
+const DEFAULT_PORT: &str = "/dev/ttyACM0";
+/// Longest wait for a reply line
+const REPLY_TIMEOUT: Duration = Duration::from_secs(2);
+/// Output arriving within this time after connecting is stale and dropped
+const SYNC_TIME: Duration = Duration::from_millis(100);
+/// Default telemetry interval of `plot` in ms
+const PLOT_INTERVAL: u32 = 200;
+/// Speed of the simulated iron relative to real time
+const SIMULATION_SPEED: u32 = 1;
+/// First line of a backup file
+const BACKUP_HEADER: &str = "# ts100 settings";
+
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// No complete reply in time
+    Timeout,
+    /// The iron replied with an error
+    Device(String),
+    /// A reply or file which can't be understood
+    Invalid(String),
+    Usage(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref error) => write!(f, "{}", error),
+            Error::Timeout => write!(f, "no reply from the iron"),
+            Error::Device(ref reason) => write!(f, "iron replied: {}", reason),
+            Error::Invalid(ref message) => write!(f, "{}", message),
+            Error::Usage(ref message) => write!(f, "{}\n{}", message, USAGE),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+/// Connection to the console of an iron
+pub struct Client<W: Write> {
+    writer: W,
+    input: Receiver<Vec<u8>>,
+    /// Received bytes not forming a complete line yet
+    pending: Vec<u8>,
+    telemetry: VecDeque<Telemetry>,
+    stop: Arc<AtomicBool>,
+    reader: Option<JoinHandle<()>>,
+}
+
+impl Client<File> {
+    /// Opens a serial port, anything the iron sent before is dropped
+    pub fn open(path: &Path) -> Result<Self, Error> {
+        pty::raw_mode(path)?;
+        let port = OpenOptions::new().read(true).write(true).open(path)?;
+        let reader = port.try_clone()?;
+        let mut client = Client::new(reader, port);
+        client.sync();
+        Ok(client)
+    }
+}
+
+impl<W: Write> Client<W> {
+    /// Talks over `reader` and `writer`, reads have to time out now and
+    /// then so the client can be closed
+    pub fn new<R: Read + Send + 'static>(mut reader: R, writer: W) -> Self {
+        let (sender, input) = mpsc::channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let reader = thread::spawn(move || {
+            let mut buffer = [0; 256];
+            while !stopped.load(Ordering::Relaxed) {
+                match reader.read(&mut buffer) {
+                    Ok(0) => {}
+                    Ok(count) => {
+                        if sender.send(buffer[..count].to_vec()).is_err() {
+                            return;
+                        }
+                    }
+                    Err(_) => return,
+                }
+            }
+        });
+
+        Client {
+            writer,
+            input,
+            pending: Vec::new(),
+            telemetry: VecDeque::new(),
+            stop,
+            reader: Some(reader),
+        }
+    }
+
+    /// Sends `line` and returns the lines of the reply before the `ok`
+    pub fn command(&mut self, line: &str) -> Result<Vec<String>, Error> {
+        self.writer.write_all(format!("{}\r\n", line).as_bytes())?;
+        self.writer.flush()?;
+
+        let mut reply = Vec::new();
+        loop {
+            let line = self.read_line(REPLY_TIMEOUT)?;
+            if line == "ok" {
+                return Ok(reply);
+            } else if let Some(reason) = line.strip_prefix("error: ") {
+                return Err(Error::Device(reason.to_string()));
+            } else if let Some(telemetry) = Telemetry::parse(&line) {
+                self.telemetry.push_back(telemetry);
+            } else if !line.starts_with("L ") {
+                // log entries aren't part of the reply
+                reply.push(line);
+            }
+        }
+    }
+
+    pub fn get(&mut self, name: &str) -> Result<i32, Error> {
+        let reply = self.command(&format!("get {}", name))?;
+        match parse_setting(reply.first().map(|line| line.as_str()).unwrap_or("")) {
+            Some((ref reply_name, value)) if reply_name == name => Ok(value),
+            _ => Err(Error::Invalid(format!("unexpected reply {:?}", reply))),
+        }
+    }
+
+    pub fn set(&mut self, name: &str, value: i32) -> Result<(), Error> {
+        self.command(&format!("set {} {}", name, value)).map(|_| ())
+    }
+
+    /// All settings in the order of the iron
+    pub fn dump(&mut self) -> Result<Vec<(String, i32)>, Error> {
+        self.command("dump")?
+            .iter()
+            .map(|line| {
+                parse_setting(line).ok_or_else(|| Error::Invalid(format!("unexpected {:?}", line)))
+            })
+            .collect()
+    }
+
+    pub fn save(&mut self) -> Result<(), Error> {
+        self.command("save").map(|_| ())
+    }
+
+    /// The records of the journal, oldest first
+    pub fn events(&mut self) -> Result<Vec<Record>, Error> {
+        self.command("events")?
+            .iter()
+            .map(|line| {
+                Record::parse(line).ok_or_else(|| Error::Invalid(format!("unexpected {:?}", line)))
+            })
+            .collect()
+    }
+
+    /// Starts telemetry every `interval` ms, or stops it
+    pub fn telemetry(&mut self, interval: Option<u32>) -> Result<(), Error> {
+        match interval {
+            Some(interval) => self.command(&format!("telemetry {}", interval)),
+            None => self.command("telemetry off"),
+        }.map(|_| ())
+    }
+
+    /// Waits for the next telemetry record
+    pub fn next_telemetry(&mut self) -> Result<Telemetry, Error> {
+        loop {
+            if let Some(telemetry) = self.telemetry.pop_front() {
+                return Ok(telemetry);
+            }
+            let line = self.read_line(REPLY_TIMEOUT)?;
+            if let Some(telemetry) = Telemetry::parse(&line) {
+                self.telemetry.push_back(telemetry);
+            }
+        }
+    }
+
+    /// Drops output left over from earlier connections
+    fn sync(&mut self) {
+        let start = Instant::now();
+        while let Some(left) = SYNC_TIME.checked_sub(start.elapsed()) {
+            if self.input.recv_timeout(left).is_err() {
+                break;
+            }
+        }
+        self.pending.clear();
+    }
+
+    fn read_line(&mut self, timeout: Duration) -> Result<String, Error> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(end) = self.pending.iter().position(|&byte| byte == b'\n') {
+                let line: Vec<u8> = self.pending.drain(..end + 1).collect();
+                let line = String::from_utf8_lossy(&line);
+                let line = line.trim_end_matches(['\r', '\n']);
+                if line.is_empty() {
+                    continue;
+                }
+                return Ok(line.to_string());
+            }
+
+            let left = deadline.checked_duration_since(Instant::now()).ok_or(Error::Timeout)?;
+            match self.input.recv_timeout(left) {
+                Ok(data) => self.pending.extend_from_slice(&data),
+                Err(RecvTimeoutError::Timeout) => return Err(Error::Timeout),
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(Error::Io(io::Error::new(io::ErrorKind::UnexpectedEof,
+                                                        "port closed")))
+                }
+            }
+        }
+    }
+}
+
+impl<W: Write> Drop for Client<W> {
+    fn drop(&mut self) {
+        // the reader must not steal the replies of the next client
+        self.stop.store(true, Ordering::Relaxed);
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.join();
+        }
+    }
+}
+
+/// Parses a `NAME=VALUE` line
+fn parse_setting(line: &str) -> Option<(String, i32)> {
+    let mut parts = line.splitn(2, '=');
+    let name = parts.next()?.trim();
+    let value = parts.next()?.trim().parse().ok()?;
+    Some((name.to_string(), value))
+}
+
+/// Encodes settings for `backup`
+pub fn backup_text(settings: &[(String, i32)]) -> String {
+    let mut text = format!("{}\n", BACKUP_HEADER);
+    for &(ref name, value) in settings {
+        text.push_str(&format!("{}={}\n", name, value));
+    }
+    text
+}
+
+/// Decodes and checks a backup, comments and empty lines are skipped
+pub fn parse_backup(text: &str) -> Result<Vec<(String, i32)>, Error> {
+    let mut settings = Vec::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid = |message: &str| Error::Invalid(format!("line {}: {}", index + 1, message));
+        let (name, value) = parse_setting(line).ok_or_else(|| invalid("expected NAME=VALUE"))?;
+        let setting = Setting::from_name(&name).ok_or_else(|| invalid("unknown setting"))?;
+        let (min, max) = setting.range();
+        if value < min || value > max {
+            return Err(invalid("value out of range"));
+        }
+        settings.push((name, value));
+    }
+    Ok(settings)
+}
+
+struct Options {
+    port: Option<PathBuf>,
+    simulate: bool,
+    command: Vec<String>,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, Error> {
+    let mut options = Options {
+        port: None,
+        simulate: false,
+        command: Vec::new(),
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--port" => match args.next() {
+                Some(port) => options.port = Some(PathBuf::from(port)),
+                None => return Err(Error::Usage("--port needs a path".to_string())),
+            },
+            "--simulate" => options.simulate = true,
+            _ => options.command.push(arg.clone()),
+        }
+    }
+
+    if options.command.is_empty() {
+        return Err(Error::Usage("no command given".to_string()));
+    }
+    Ok(options)
+}
+
+/// Runs `ts100ctl` with `args` excluding the program name, output goes to
+/// `out`
+pub fn run(args: &[String], out: &mut dyn Write) -> Result<(), Error> {
+    let options = parse_args(args)?;
+
+    if options.simulate {
+        let port = SimulatedPort::start(Board::new(), SIMULATION_SPEED)?;
+        let result = run_command(port.path(), &options.command, out);
+        port.stop();
+        return result;
+    }
+
+    let port = options.port.unwrap_or_else(|| PathBuf::from(DEFAULT_PORT));
+    run_command(&port, &options.command, out)
+}
+
+fn run_command(port: &Path, command: &[String], out: &mut dyn Write) -> Result<(), Error> {
+    let words: Vec<&str> = command.iter().map(|word| word.as_str()).collect();
+    let number = |word: &str| {
+        word.parse::<i32>().map_err(|_| Error::Usage(format!("{} isn't a number", word)))
+    };
+    let mut client = Client::open(port)?;
+
+    match words[..] {
+        ["get", name] => writeln!(out, "{}={}", name, client.get(name)?)?,
+        ["set", name, value] => client.set(name, number(value)?)?,
+        ["dump"] => {
+            for (name, value) in client.dump()? {
+                writeln!(out, "{}={}", name, value)?;
+            }
+        }
+        ["save"] => client.save()?,
+        ["backup", path] => {
+            let settings = client.dump()?;
+            File::create(path)?.write_all(backup_text(&settings).as_bytes())?;
+            writeln!(out, "{} settings written to {}", settings.len(), path)?;
+        }
+        ["restore", path] => {
+            let mut text = String::new();
+            File::open(path)?.read_to_string(&mut text)?;
+            // checked completely before anything is changed
+            let settings = parse_backup(&text)?;
+            for &(ref name, value) in &settings {
+                client.set(name, value)?;
+            }
+            client.save()?;
+            writeln!(out, "{} settings restored and saved", settings.len())?;
+        }
+        ["events"] => {
+            for record in client.events()? {
+                writeln!(out, "{}", record)?;
+            }
+        }
+        ["plot", ref rest @ ..] => {
+            let (interval, count) = match *rest {
+                [] => (PLOT_INTERVAL, None),
+                [interval] => (number(interval)? as u32, None),
+                ["--count", count] => (PLOT_INTERVAL, Some(number(count)? as usize)),
+                [interval, "--count", count] => {
+                    (number(interval)? as u32, Some(number(count)? as usize))
+                }
+                _ => return Err(Error::Usage("plot [MS] [--count N]".to_string())),
+            };
+            plot(&mut client, interval, count, out)?;
+        }
+        _ => return Err(Error::Usage(format!("unknown command {}", words.join(" ")))),
+    }
+    Ok(())
+}
+
+/// Redraws the plot for every record, `count` records or forever
+fn plot<W: Write>(client: &mut Client<W>,
+                  interval: u32,
+                  count: Option<usize>,
+                  out: &mut dyn Write)
+                  -> Result<(), Error> {
+    let mut plot = Plot::new();
+    client.telemetry(Some(interval))?;
+
+    let mut received = 0;
+    while count.is_none_or(|count| received < count) {
+        let record = client.next_telemetry()?;
+        plot.push(record);
+        received += 1;
+        write!(out, "\x1b[H\x1b[2J{}", plot.render())?;
+        out.flush()?;
+    }
+
+    client.telemetry(None)
+}
+
+/// Runs `ts100ctl` commands against a simulated iron on a pseudo terminal
+/// and checks their output and the effect on the iron
+pub fn check() -> Result<(), String> {
+    let port = SimulatedPort::start(Board::new(), 10).map_err(|error| error.to_string())?;
+    let path = port.path().to_string_lossy().into_owned();
+    let backup = std::env::temp_dir().join(format!("ts100ctl-check-{}.txt", std::process::id()));
+    let backup_path = backup.to_string_lossy().into_owned();
+
+    let ctl = |command: &str| -> Result<String, String> {
+        let mut args = vec!["--port".to_string(), path.clone()];
+        args.extend(command.split_whitespace().map(|word| word.to_string()));
+        let mut out = Vec::new();
+        run(&args, &mut out).map_err(|error| format!("{}: {}", command, error))?;
+        Ok(String::from_utf8_lossy(&out).into_owned())
+    };
+    let expect = |command: &str, expected: &str| -> Result<(), String> {
+        let out = ctl(command)?;
+        if out == expected {
+            Ok(())
+        } else {
+            Err(format!("{}: printed {:?}, expected {:?}", command, out, expected))
+        }
+    };
+
+    let result = (|| {
+        expect("get motion_sensitivity", "motion_sensitivity=6\n")?;
+        expect("set motion_sensitivity 3", "")?;
+        expect("set accel_offset_y -7", "")?;
+        match ctl("set motion_sensitivity 12") {
+            Err(ref message) if message.ends_with("iron replied: out of range") => {}
+            other => return Err(format!("out of range set: {:?}", other)),
+        }
+
+        expect(&format!("backup {}", backup_path),
+               &format!("4 settings written to {}\n", backup_path))?;
+        let text = std::fs::read_to_string(&backup).map_err(|error| error.to_string())?;
+        let expected = "# ts100 settings\nmotion_sensitivity=3\naccel_offset_x=0\n\
+                        accel_offset_y=-7\naccel_offset_z=0\n";
+        if text != expected {
+            return Err(format!("backup is {:?}", text));
+        }
+
+        expect("set motion_sensitivity 8", "")?;
+        expect(&format!("restore {}", backup_path), "4 settings restored and saved\n")?;
+        expect("dump", "motion_sensitivity=3\naccel_offset_x=0\naccel_offset_y=-7\n\
+                        accel_offset_z=0\n")?;
+
+        let plot = ctl("plot 100 --count 3")?;
+        if !plot.contains("setpoint off") || plot.matches("\x1b[H").count() != 3 {
+            return Err(format!("plot printed {:?}", plot));
+        }
+        // telemetry was stopped, so replies are clean again
+        expect("get accel_offset_y", "accel_offset_y=-7\n")?;
+        expect("events", "1 0 boot cause=power-on\n")
+    })();
+
+    let _ = std::fs::remove_file(&backup);
+    let board = port.stop();
+    result?;
+
+    let saved = board.flash.and_then(|data| ts100_core::settings::Settings::decode(&data));
+    match saved {
+        Some(settings) if settings.motion_sensitivity == 3 && settings.accel_offset[1] == -7 => {
+            Ok(())
+        }
+        _ => Err("restored settings weren't saved to flash".to_string()),
+    }
+}
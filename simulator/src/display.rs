@@ -0,0 +1,114 @@
+//! Output of the 96x16 framebuffer
+//!
+//! The terminal view packs two pixel rows into one line of half block
+//! characters. PNG frames are written as 8 bit grayscale with uncompressed
+//! deflate blocks, which keeps the encoder small and dependency free.
+
+use std::fs::File;
+use std::io::{self, Write};
+use std::path::Path;
+use ts100_core::ui::{Framebuffer, PAGES, WIDTH};
+
+pub const HEIGHT: usize = PAGES * 8;
+
+/// Renders `fb` as `HEIGHT / 2` lines of text with a frame around them
+pub fn terminal(fb: &Framebuffer) -> String {
+    let mut text = String::new();
+    text.push('┌');
+    text.extend((0..WIDTH).map(|_| '─'));
+    text.push_str("┐\n");
+
+    for row in 0..HEIGHT / 2 {
+        text.push('│');
+        for x in 0..WIDTH {
+            text.push(match (fb.pixel(x, 2 * row), fb.pixel(x, 2 * row + 1)) {
+                (true, true) => '█',
+                (true, false) => '▀',
+                (false, true) => '▄',
+                (false, false) => ' ',
+            });
+        }
+        text.push_str("│\n");
+    }
+
+    text.push('└');
+    text.extend((0..WIDTH).map(|_| '─'));
+    text.push('┘');
+    text
+}
+
+/// Writes `fb` as PNG with every pixel enlarged to `scale` x `scale`
+pub fn write_png(fb: &Framebuffer, path: &Path, scale: usize) -> io::Result<()> {
+    let (width, height) = (WIDTH * scale, HEIGHT * scale);
+
+    // every scanline starts with filter type 0
+    let mut raw = Vec::with_capacity((width + 1) * height);
+    for y in 0..height {
+        raw.push(0);
+        for x in 0..width {
+            raw.push(if fb.pixel(x / scale, y / scale) { 0xff } else { 0x00 });
+        }
+    }
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bit grayscale, deflate, adaptive filtering, no interlace
+    header.extend_from_slice(&[8, 0, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    chunk(&mut png, b"IHDR", &header);
+    chunk(&mut png, b"IDAT", &zlib_stored(&raw));
+    chunk(&mut png, b"IEND", &[]);
+
+    File::create(path)?.write_all(&png)
+}
+
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+/// zlib stream of uncompressed deflate blocks
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    const BLOCK: usize = 0xffff;
+
+    let mut out = vec![0x78, 0x01];
+    let mut blocks = data.chunks(BLOCK).peekable();
+    if blocks.peek().is_none() {
+        out.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+    }
+    while let Some(block) = blocks.next() {
+        out.push(if blocks.peek().is_none() { 1 } else { 0 });
+        let len = block.len() as u16;
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&(!len).to_le_bytes());
+        out.extend_from_slice(block);
+    }
+    out.extend_from_slice(&adler32(data).to_be_bytes());
+    out
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { (crc >> 1) ^ 0xedb8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + u32::from(byte)) % 65_521;
+        b = (b + a) % 65_521;
+    }
+    b << 16 | a
+}
@@ -0,0 +1,45 @@
+//! Flash pages of the journal kept in memory
+//!
+//! Behaves like the STM32 flash as far as the journal can tell: erasing
+//! sets every bit of a page, programming a half-word which isn't erased
+//! fails and leaves it as it is.
+
+use ts100_core::journal::{Flash, PAGES, PAGE_LEN};
+
+const ERASED: u16 = 0xffff;
+
+#[derive(Clone)]
+pub struct MemoryFlash {
+    pages: Vec<[u16; PAGE_LEN]>,
+}
+
+impl Default for MemoryFlash {
+    fn default() -> Self {
+        MemoryFlash::new()
+    }
+}
+
+impl MemoryFlash {
+    /// Erased pages as shipped
+    pub fn new() -> Self {
+        MemoryFlash {
+            pages: vec![[ERASED; PAGE_LEN]; PAGES],
+        }
+    }
+}
+
+impl Flash for MemoryFlash {
+    fn read(&self, page: usize, offset: usize) -> u16 {
+        self.pages[page][offset]
+    }
+
+    fn erase(&mut self, page: usize) {
+        self.pages[page] = [ERASED; PAGE_LEN];
+    }
+
+    fn program(&mut self, page: usize, offset: usize, value: u16) {
+        if self.pages[page][offset] == ERASED {
+            self.pages[page][offset] = value;
+        }
+    }
+}
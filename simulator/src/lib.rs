@@ -0,0 +1,23 @@
+//! Host simulation of the ts100 firmware
+//!
+//! Runs the state machine, input handling, motion detection, temperature
+//! control and rendering of `ts100-core` against simulated peripherals, so
+//! the UI and control behavior can be developed without flashing an iron.
+
+#[macro_use]
+extern crate ts100_core;
+
+pub mod accelerometer;
+pub mod board;
+pub mod console;
+pub mod ctl;
+pub mod display;
+pub mod flash;
+pub mod log;
+pub mod plant;
+pub mod plot;
+pub mod pty;
+pub mod rng;
+pub mod scenario;
+pub mod trace;
+pub mod transitions;
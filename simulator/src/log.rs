@@ -0,0 +1,97 @@
+//! Checks of the log queue and the formatting of its entries
+//!
+//! The firmware drains `LOG` into one of its backends, here the entries are
+//! taken directly and compared with the expected lines.
+
+use ts100_core::console::Console;
+use ts100_core::log::{History, Level, HISTORY_SIZE, LOG, MAX_LEVEL};
+
+/// Logs with every macro and checks the queued lines, the overflow of the
+/// queue, the history and the console lines
+pub fn check() -> Result<(), String> {
+    // left over from an earlier run
+    while LOG.pop().is_some() {}
+    LOG.take_dropped();
+
+    info!("tip {} set {} hex {:x} on {}", -12i16, 320u16, 0xbeefu32, true);
+    warn!("{} of {}", 1u8);
+    info!("{} {x} {:?} {}", 1u8, 2u8, 3u8, 4u8, 5u8);
+    error!("no arguments");
+    expect_lines(&["0 INFO tip -12 set 320 hex beef on true",
+                   "0 WARN 1 of ?",
+                   "0 INFO 1 {x} {:?} 2",
+                   "0 ERROR no arguments"])?;
+
+    error!("level");
+    warn!("level");
+    info!("level");
+    debug!("level");
+    trace!("level");
+    let mut logged = 0;
+    while LOG.pop().is_some() {
+        logged += 1;
+    }
+    let enabled = [Level::Error, Level::Warn, Level::Info, Level::Debug, Level::Trace]
+        .iter()
+        .filter(|&&level| level as u8 <= MAX_LEVEL)
+        .count();
+    if logged != enabled {
+        return Err(format!("{} of 5 levels logged, {} enabled", logged, enabled));
+    }
+
+    for index in 0..20u32 {
+        info!("entry {}", index);
+    }
+    let mut kept = Vec::new();
+    while let Some(entry) = LOG.pop() {
+        kept.push(entry.to_string());
+    }
+    let dropped = LOG.take_dropped();
+    let expected: Vec<_> = (0..kept.len()).map(|index| format!("0 INFO entry {}", index)).collect();
+    if kept != expected || kept.len() + dropped != 20 || dropped == 0 {
+        return Err(format!("overflow kept {:?} and dropped {}", kept, dropped));
+    }
+
+    let mut history = History::new();
+    for index in 0..20u32 {
+        info!("entry {}", index);
+        if let Some(entry) = LOG.pop() {
+            history.push(entry);
+        }
+    }
+    let newest = history.get(0).map(|entry| entry.message().to_string());
+    let oldest = history.get(HISTORY_SIZE - 1).map(|entry| entry.message().to_string());
+    if newest.as_deref() != Some("entry 19")
+        || oldest != Some(format!("entry {}", 20 - HISTORY_SIZE))
+        || history.get(HISTORY_SIZE).is_some()
+    {
+        return Err(format!("history from {:?} to {:?}", oldest, newest));
+    }
+
+    warn!("sensor {:x} missing", 0x3au8);
+    let mut console = Console::new();
+    if let Some(entry) = LOG.pop() {
+        console.send_log(&entry);
+    }
+    let mut packet = [0; 64];
+    let count = console.transmit(&mut packet);
+    let line = String::from_utf8_lossy(&packet[..count]);
+    if line != "L 0 WARN sensor 3a missing\r\n" {
+        return Err(format!("console sent {:?}", line));
+    }
+    Ok(())
+}
+
+fn expect_lines(expected: &[&str]) -> Result<(), String> {
+    for &line in expected {
+        match LOG.pop() {
+            Some(ref entry) if entry.to_string() == line => {}
+            Some(entry) => return Err(format!("logged {:?}, expected {:?}", entry.to_string(), line)),
+            None => return Err(format!("nothing logged, expected {:?}", line)),
+        }
+    }
+    match LOG.pop() {
+        Some(entry) => Err(format!("unexpected {:?}", entry.to_string())),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,230 @@
+//! Interactive simulator
+//!
+//! Draws the OLED in the terminal and maps keys to the buttons and the
+//! accelerometer, see `KEYS`. With `--frames DIR` every changed screen is
+//! additionally written to `DIR` as PNG, named after the simulated time.
+//! `--trace FILE` records the control loop like RTT on the iron, decode it
+//! with `ts100-trace`.
+
+extern crate ts100_core;
+extern crate ts100_simulator;
+
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::process::{self, Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+use ts100_core::accel::Gesture;
+use ts100_core::input::Button;
+use ts100_simulator::board::Board;
+use ts100_simulator::display;
+use ts100_simulator::plant::{Plant, JOINT_LOAD};
+
+const USAGE: &str = "usage: ts100-sim [--frames DIR] [--trace FILE] [--scale N] [--speed N] \
+                     [--ambient C] [--supply V] [--no-accel]";
+
+const KEYS: &str = "a/b click A/B   A/B hold A/B   x hold both   m move   t tap   \
+                    d double tap\nj tip on joint   +/- supply voltage   q quit";
+
+/// Wall clock time between screen updates in ms
+const FRAME_MS: u32 = 20;
+/// Durations of simulated presses and movements in ms
+const CLICK: u32 = 100;
+const HOLD: u32 = 1500;
+const MOVE: u32 = 3000;
+
+struct Options {
+    frames: Option<PathBuf>,
+    trace: Option<PathBuf>,
+    scale: usize,
+    speed: u32,
+    ambient: f32,
+    supply: f32,
+    accel: bool,
+}
+
+fn parse_args() -> Result<Options, String> {
+    let mut options = Options {
+        frames: None,
+        trace: None,
+        scale: 4,
+        speed: 1,
+        ambient: 25.0,
+        supply: 19.0,
+        accel: true,
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "--frames" => options.frames = Some(PathBuf::from(value()?)),
+            "--trace" => options.trace = Some(PathBuf::from(value()?)),
+            "--scale" => options.scale = value()?.parse().map_err(|e| format!("--scale: {}", e))?,
+            "--speed" => options.speed = value()?.parse().map_err(|e| format!("--speed: {}", e))?,
+            "--ambient" => {
+                options.ambient = value()?.parse().map_err(|e| format!("--ambient: {}", e))?
+            }
+            "--supply" => options.supply = value()?.parse().map_err(|e| format!("--supply: {}", e))?,
+            "--no-accel" => options.accel = false,
+            "-h" | "--help" => {
+                println!("{}\n\n{}", USAGE, KEYS);
+                process::exit(0);
+            }
+            _ => return Err(format!("unknown argument {}", arg)),
+        }
+    }
+
+    if options.scale == 0 || options.speed == 0 {
+        return Err("--scale and --speed must be at least 1".into());
+    }
+    Ok(options)
+}
+
+/// Switches the terminal to unbuffered input without echo, the state is
+/// restored by `restore_terminal`
+fn raw_terminal() {
+    let _ = Command::new("stty").args(["-icanon", "-echo", "min", "1"])
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::null())
+        .status();
+    print!("\x1b[2J\x1b[?25l");
+}
+
+fn restore_terminal() {
+    let _ = Command::new("stty").args(["icanon", "echo"])
+        .stdin(Stdio::inherit())
+        .stderr(Stdio::null())
+        .status();
+    println!("\x1b[?25h");
+}
+
+/// Applies key `key`, returns `false` to quit
+fn handle_key(board: &mut Board, key: u8) -> bool {
+    match key {
+        b'a' => board.press(Button::A, CLICK),
+        b'b' => board.press(Button::B, CLICK),
+        b'A' => board.press(Button::A, HOLD),
+        b'B' => board.press(Button::B, HOLD),
+        b'x' => {
+            board.press(Button::A, HOLD);
+            board.press(Button::B, HOLD);
+        }
+        b'm' => board.accelerometer.shake(MOVE),
+        b't' => board.gesture(Gesture::Tap),
+        b'd' => board.gesture(Gesture::DoubleTap),
+        b'j' => board.plant.load = if board.plant.load > 0.0 { 0.0 } else { JOINT_LOAD },
+        b'+' => board.plant.supply += 1.0,
+        b'-' => board.plant.supply = (board.plant.supply - 1.0).max(0.0),
+        b'q' | 3 | 4 => return false,
+        _ => {}
+    }
+    true
+}
+
+fn status(board: &Board) -> String {
+    let setpoint = match board.setpoint() {
+        Some(setpoint) => format!("{:3} °C", setpoint),
+        None => "   off".to_string(),
+    };
+
+    format!(
+        "{:>8.1} s  {:<20}\n\
+         tip {:6.1} °C  measured {:4} °C  setpoint {}\n\
+         heater {:3}  duty {:3} %  supply {:4.1} V  {:<7}  {:<6}",
+        board.now() as f32 / 1000.0,
+        format!("{:?}", board.current_state()),
+        board.plant.tip,
+        board.tip_celsius(),
+        setpoint,
+        if board.is_heating() { "on" } else { "off" },
+        board.duty(),
+        board.plant.supply,
+        if board.accelerometer.is_moving() { "moving" } else { "still" },
+        if board.plant.load > 0.0 { "joint" } else { "" },
+    )
+}
+
+fn framebuffer_bytes(board: &Board) -> Vec<u8> {
+    let fb = &board.framebuffer;
+    fb.page(0).iter().chain(fb.page(1)).cloned().collect()
+}
+
+fn main() {
+    let options = match parse_args() {
+        Ok(options) => options,
+        Err(error) => {
+            eprintln!("{}\n{}", error, USAGE);
+            process::exit(2);
+        }
+    };
+
+    let mut board = Board::new();
+    board.plant = Plant::new(options.ambient);
+    board.plant.supply = options.supply;
+    if !options.accel {
+        board.disconnect_accelerometer();
+    }
+    if options.trace.is_some() {
+        board.trace = Some(Vec::new());
+    }
+
+    let (keys, pressed) = mpsc::channel();
+    thread::spawn(move || {
+        let mut buffer = [0; 16];
+        while let Ok(count) = io::stdin().read(&mut buffer) {
+            if count == 0 || buffer[..count].iter().any(|&key| keys.send(key).is_err()) {
+                break;
+            }
+        }
+    });
+
+    raw_terminal();
+
+    let mut last_frame = Vec::new();
+    let mut next = Instant::now();
+    'run: loop {
+        while let Ok(key) = pressed.try_recv() {
+            if !handle_key(&mut board, key) {
+                break 'run;
+            }
+        }
+
+        board.run(FRAME_MS * options.speed);
+
+        let frame = framebuffer_bytes(&board);
+        if frame != last_frame {
+            if let Some(ref dir) = options.frames {
+                let path = dir.join(format!("frame-{:08}.png", board.now()));
+                if let Err(error) = display::write_png(&board.framebuffer, &path, options.scale) {
+                    restore_terminal();
+                    eprintln!("{}: {}", path.display(), error);
+                    process::exit(1);
+                }
+            }
+            last_frame = frame;
+        }
+
+        print!("\x1b[H{}\n{}\n\n{}\n", display::terminal(&board.framebuffer), status(&board), KEYS);
+        let _ = io::stdout().flush();
+
+        next += Duration::from_millis(u64::from(FRAME_MS));
+        let now = Instant::now();
+        if next > now {
+            thread::sleep(next - now);
+        } else {
+            next = now;
+        }
+    }
+
+    restore_terminal();
+
+    if let (Some(path), Some(trace)) = (options.trace, board.trace) {
+        if let Err(error) = File::create(&path).and_then(|mut file| file.write_all(&trace)) {
+            eprintln!("{}: {}", path.display(), error);
+            process::exit(1);
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! Thermal model of the tip
+//!
+//! The tip is a single heat capacity driven by the heater and losing heat
+//! to the ambient air proportionally to the temperature difference. A joint
+//! being soldered is modeled as an additional loss. The numbers are rough
+//! estimates for a stock TS100 tip, it heats from room temperature to 300 °C
+//! in about ten seconds at 19 V.
+
+use ts100_core::temperature::Sample;
+
+/// Resistance of the heater coil
+const HEATER_OHMS: f32 = 8.0;
+/// Heat capacity of the tip in J/K
+const HEAT_CAPACITY: f32 = 1.6;
+/// Loss to the air in W/K, about 5 W at 320 °C
+const AIR_LOSS: f32 = 0.017;
+
+/// Additional loss of a typical joint in W/K
+pub const JOINT_LOAD: f32 = 0.08;
+
+#[derive(Clone, Copy)]
+pub struct Plant {
+    /// Tip temperature in °C
+    pub tip: f32,
+    /// Temperature of the handle and the cold junction in °C
+    pub ambient: f32,
+    /// Supply voltage in V
+    pub supply: f32,
+    /// Additional loss in W/K while the tip touches a joint
+    pub load: f32,
+}
+
+impl Plant {
+    pub fn new(ambient: f32) -> Self {
+        Plant {
+            tip: ambient,
+            ambient,
+            supply: 19.0,
+            load: 0.0,
+        }
+    }
+
+    /// Heater power in W while it's on
+    pub fn heater_power(&self) -> f32 {
+        self.supply * self.supply / HEATER_OHMS
+    }
+
+    /// Advances the model by `ms` milliseconds
+    pub fn step(&mut self, ms: u32, heating: bool) {
+        let dt = ms as f32 / 1000.0;
+        let power = if heating { self.heater_power() } else { 0.0 };
+        let loss = (AIR_LOSS + self.load) * (self.tip - self.ambient);
+        self.tip += (power - loss) * dt / HEAT_CAPACITY;
+    }
+
+    /// Raw ADC readings of the current temperatures and supply voltage
+    pub fn sample(&self) -> Sample {
+        Sample::from_readings(self.tip.round() as i16,
+                              self.ambient.round() as i16,
+                              (self.supply * 1000.0).round() as u16)
+    }
+}
@@ -0,0 +1,87 @@
+//! Terminal plot of the telemetry
+//!
+//! The tip temperature is drawn as `*` and the setpoint as `-` over the
+//! last `WIDTH` records, newest on the right, with a status line of the
+//! latest record below.
+
+use std::collections::VecDeque;
+use ts100_core::console::Telemetry;
+use ts100_core::control::TIP_MAX;
+
+/// Records shown
+pub const WIDTH: usize = 72;
+/// Rows of the temperature axis
+pub const HEIGHT: usize = 19;
+/// Temperature of the top row in °C
+const TOP: i32 = TIP_MAX as i32;
+/// Rows between two axis labels
+const LABEL_EVERY: usize = 3;
+
+pub struct Plot {
+    records: VecDeque<Telemetry>,
+}
+
+impl Default for Plot {
+    fn default() -> Self {
+        Plot::new()
+    }
+}
+
+impl Plot {
+    pub fn new() -> Self {
+        Plot { records: VecDeque::new() }
+    }
+
+    pub fn push(&mut self, record: Telemetry) {
+        if self.records.len() == WIDTH {
+            self.records.pop_front();
+        }
+        self.records.push_back(record);
+    }
+
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+
+        for row in 0..HEIGHT {
+            let label = row_celsius(row);
+            if row % LABEL_EVERY == 0 {
+                text.push_str(&format!("{:>4} |", label));
+            } else {
+                text.push_str("     |");
+            }
+
+            for record in &self.records {
+                let tip = row_of(i32::from(record.tip)) == row;
+                let setpoint = record.setpoint.is_some_and(|c| row_of(i32::from(c)) == row);
+                text.push(match (tip, setpoint) {
+                    (true, _) => '*',
+                    (false, true) => '-',
+                    _ => ' ',
+                });
+            }
+            text.push('\n');
+        }
+        text.push_str(&format!("     +{}\n", "-".repeat(WIDTH)));
+
+        if let Some(last) = self.records.back() {
+            let setpoint = last.setpoint.map_or("off".to_string(), |c| format!("{} °C", c));
+            text.push_str(&format!("{:>8.1} s   tip {} °C   setpoint {}   duty {} %\n",
+                                   f64::from(last.time) / 1000.0,
+                                   last.tip,
+                                   setpoint,
+                                   last.duty));
+        }
+        text
+    }
+}
+
+/// Lowest temperature shown in `row`, row 0 is the top
+fn row_celsius(row: usize) -> i32 {
+    TOP - TOP * row as i32 / (HEIGHT as i32 - 1)
+}
+
+/// Row showing `celsius`, clamped to the plot
+fn row_of(celsius: i32) -> usize {
+    let clamped = celsius.clamp(0, TOP);
+    ((TOP - clamped) * (HEIGHT as i32 - 1) / TOP) as usize
+}
@@ -0,0 +1,148 @@
+//! Serial ports and pseudo terminals on Linux
+//!
+//! `SimulatedPort` runs a simulated board behind a pseudo terminal, so
+//! `ts100ctl` or any terminal program can talk to the console as if an iron
+//! was connected over USB. The ports are configured with `stty`, like the
+//! terminal of the interactive simulator.
+
+use board::Board;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::os::raw::{c_char, c_int};
+use std::os::unix::io::FromRawFd;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+extern "C" {
+    fn posix_openpt(flags: c_int) -> c_int;
+    fn grantpt(fd: c_int) -> c_int;
+    fn unlockpt(fd: c_int) -> c_int;
+    fn ptsname_r(fd: c_int, buf: *mut c_char, len: usize) -> c_int;
+}
+
+const O_RDWR: c_int = 0o2;
+const O_NOCTTY: c_int = 0o400;
+
+/// Wall clock time per simulation step in ms
+const STEP_MS: u32 = 10;
+
+/// Puts a serial port into raw mode, reads return after 100 ms without data
+pub fn raw_mode(path: &Path) -> io::Result<()> {
+    let status = Command::new("stty")
+        .arg("-F")
+        .arg(path)
+        .args(["raw", "-echo", "min", "0", "time", "1"])
+        .stderr(Stdio::null())
+        .status()?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!("can't configure {}", path.display())))
+    }
+}
+
+/// Opens a new pseudo terminal, returns its master and the path of the
+/// terminal side
+pub fn open_pty() -> io::Result<(File, PathBuf)> {
+    unsafe {
+        let fd = posix_openpt(O_RDWR | O_NOCTTY);
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let master = File::from_raw_fd(fd);
+
+        let mut name = [0u8; 64];
+        if grantpt(fd) != 0 || unlockpt(fd) != 0
+            || ptsname_r(fd, name.as_mut_ptr() as *mut c_char, name.len()) != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+
+        let len = name.iter().position(|&byte| byte == 0).unwrap_or(name.len());
+        let path = String::from_utf8_lossy(&name[..len]).into_owned();
+        Ok((master, PathBuf::from(path)))
+    }
+}
+
+/// A simulated board answering on a pseudo terminal
+pub struct SimulatedPort {
+    path: PathBuf,
+    stop: Arc<AtomicBool>,
+    device: Option<JoinHandle<Board>>,
+    /// Keeps the terminal open between clients
+    terminal: Option<File>,
+}
+
+impl SimulatedPort {
+    /// Runs `board` behind a new pseudo terminal, the simulated time passes
+    /// `speed` times faster than the real one
+    pub fn start(board: Board, speed: u32) -> io::Result<SimulatedPort> {
+        let (master, path) = open_pty()?;
+        let terminal = OpenOptions::new().read(true).write(true).open(&path)?;
+        raw_mode(&path)?;
+
+        // the master blocks on reads until the terminal is closed
+        let mut reader = master.try_clone()?;
+        let (sender, received) = mpsc::channel();
+        thread::spawn(move || {
+            let mut buffer = [0; 256];
+            while let Ok(count) = reader.read(&mut buffer) {
+                if count == 0 || sender.send(buffer[..count].to_vec()).is_err() {
+                    return;
+                }
+            }
+        });
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let stopped = stop.clone();
+        let mut writer = master;
+        let device = thread::spawn(move || {
+            let mut board = board;
+            while !stopped.load(Ordering::Relaxed) {
+                while let Ok(data) = received.try_recv() {
+                    board.console_write(&data);
+                }
+                board.run(STEP_MS * speed);
+
+                let output = board.console_read();
+                if !output.is_empty() && writer.write_all(&output).is_err() {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(u64::from(STEP_MS)));
+            }
+            board
+        });
+
+        Ok(SimulatedPort {
+            path,
+            stop,
+            device: Some(device),
+            terminal: Some(terminal),
+        })
+    }
+
+    /// Path of the terminal to connect to
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Stops the simulation and returns the board for inspection
+    pub fn stop(mut self) -> Board {
+        self.stop.store(true, Ordering::Relaxed);
+        self.terminal = None;
+        let device = self.device.take().expect("device runs until stopped");
+        device.join().expect("simulation panicked")
+    }
+}
+
+impl Drop for SimulatedPort {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
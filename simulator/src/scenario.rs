@@ -0,0 +1,544 @@
+//! Scripted scenarios checked against the simulated board
+//!
+//! A scenario is a text file with one step per line, `#` starts a comment.
+//! Steps either act on the board or check it, time only advances with
+//! `wait` and the presses which last a while.
+//!
+//! ```text
+//! # press A for 100 ms, then check that the tip heats up
+//! click A
+//! wait 15000
+//! expect state Soldering
+//! expect tip 315..325
+//! snapshot soldering
+//! ```
+//!
+//! | Step                        | Effect                                          |
+//! |-----------------------------|-------------------------------------------------|
+//! | `wait MS`                   | advances the simulation                         |
+//! | `click A\|B`                | presses the button for 100 ms and waits         |
+//! | `hold A\|B\|AB MS`          | presses the button(s) for `MS` and waits        |
+//! |                             | until the release has been handled              |
+//! | `press A\|B`, `release A\|B`| changes the button level                        |
+//! | `move MS`                   | moves the iron for `MS`, doesn't wait           |
+//! | `tap`, `double-tap`         | reports the gesture                             |
+//! | `no-accel`                  | removes the accelerometer                       |
+//! | `ambient C`, `tip C`        | sets the temperature of the handle or the tip   |
+//! | `supply V`                  | sets the supply voltage                         |
+//! | `joint on\|off`             | puts the tip on a joint or lifts it             |
+//! | `expect state STATE`        | e.g. `Soldering` or `Config(Save)`              |
+//! | `expect heater on\|off`     | heater output right now                         |
+//! | `expect duty RANGE`         | duty cycle of the last second in percent        |
+//! | `expect tip RANGE`          | measured tip temperature                        |
+//! | `expect setpoint C\|off`    | setpoint of the current state                   |
+//! | `expect saved yes\|no`      | settings written to the flash                   |
+//! | `snapshot NAME`             | compares the screen with the golden `NAME.pbm`  |
+//! | `send TEXT`                 | sends a line to the serial console              |
+//! | `reply TEXT`                | expects `TEXT` as the next console reply line,  |
+//! |                             | a trailing `...` only compares the start        |
+//!
+//! A `RANGE` is either a single number or `MIN..MAX` including both ends.
+//! Golden images are plain PBM files, black pixels are lit.
+
+use board::Board;
+use console;
+use ctl;
+use display;
+use log;
+use plant::JOINT_LOAD;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::Path;
+use ts100_core::accel::Gesture;
+use ts100_core::input::Button;
+use trace;
+use transitions;
+use ts100_core::ui::{Framebuffer, WIDTH};
+
+/// Duration of a `click` in ms
+const CLICK: u32 = 100;
+/// Time after a release until it's debounced and handled in ms
+const SETTLE: u32 = 50;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Keys {
+    A,
+    B,
+    AB,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Range {
+    pub min: i32,
+    pub max: i32,
+}
+
+impl Range {
+    pub fn contains(&self, value: i32) -> bool {
+        self.min <= value && value <= self.max
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.min == self.max {
+            write!(f, "{}", self.min)
+        } else {
+            write!(f, "{}..{}", self.min, self.max)
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Step {
+    Wait(u32),
+    Hold(Keys, u32),
+    Button(Button, bool),
+    Move(u32),
+    Gesture(Gesture),
+    NoAccel,
+    Ambient(f32),
+    Tip(f32),
+    Supply(f32),
+    Joint(bool),
+    ExpectState(String),
+    ExpectHeater(bool),
+    ExpectDuty(Range),
+    ExpectTip(Range),
+    ExpectSetpoint(Option<i16>),
+    ExpectSaved(bool),
+    Snapshot(String),
+    Send(String),
+    Reply(String),
+}
+
+/// A step which can't be parsed, `line` starts at 1
+#[derive(Debug)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+/// A failed check, `line` starts at 1
+#[derive(Debug)]
+pub struct Failure {
+    pub line: usize,
+    /// Simulated time of the check in ms
+    pub time: u32,
+    pub message: String,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {} at {} ms: {}", self.line, self.time, self.message)
+    }
+}
+
+pub struct Scenario {
+    /// Steps with their line numbers
+    pub steps: Vec<(usize, Step)>,
+}
+
+impl Scenario {
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let mut steps = Vec::new();
+
+        for (index, line) in text.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or("");
+            let words: Vec<&str> = line.split_whitespace().collect();
+            if words.is_empty() {
+                continue;
+            }
+
+            let step = parse_step(&words).map_err(|message| ParseError {
+                line: index + 1,
+                message,
+            })?;
+            steps.push((index + 1, step));
+        }
+
+        Ok(Scenario { steps })
+    }
+
+    /// Runs the scenario on a fresh board
+    ///
+    /// Snapshots are compared with the images in `golden`, or written there
+    /// if `bless` is set.
+    pub fn run(&self, golden: &Path, bless: bool) -> Result<Vec<Failure>, io::Error> {
+        let mut board = Board::new();
+        let mut failures = Vec::new();
+        // console output not expected by a `reply` yet
+        let mut replies = String::new();
+
+        for &(line, ref step) in &self.steps {
+            let time = board.now();
+            let fail = |message: String| Failure { line, time, message };
+
+            match *step {
+                Step::Wait(ms) => board.run(ms),
+                Step::Hold(keys, ms) => {
+                    if keys != Keys::B {
+                        board.press(Button::A, ms);
+                    }
+                    if keys != Keys::A {
+                        board.press(Button::B, ms);
+                    }
+                    board.run(ms + SETTLE);
+                }
+                Step::Button(button, pressed) => board.set_button(button, pressed),
+                Step::Move(ms) => board.accelerometer.shake(ms),
+                Step::Gesture(gesture) => board.gesture(gesture),
+                Step::NoAccel => board.disconnect_accelerometer(),
+                Step::Ambient(celsius) => board.plant.ambient = celsius,
+                Step::Tip(celsius) => board.plant.tip = celsius,
+                Step::Supply(volts) => board.plant.supply = volts,
+                Step::Joint(on) => board.plant.load = if on { JOINT_LOAD } else { 0.0 },
+                Step::ExpectState(ref expected) => {
+                    let state = format!("{:?}", board.current_state());
+                    if state != *expected {
+                        failures.push(fail(format!("state is {}, expected {}", state, expected)));
+                    }
+                }
+                Step::ExpectHeater(expected) => {
+                    if board.is_heating() != expected {
+                        failures.push(fail(format!("heater is {}", on_off(board.is_heating()))));
+                    }
+                }
+                Step::ExpectDuty(range) => {
+                    let duty = board.duty() as i32;
+                    if !range.contains(duty) {
+                        failures.push(fail(format!("duty is {} %, expected {}", duty, range)));
+                    }
+                }
+                Step::ExpectTip(range) => {
+                    let tip = i32::from(board.tip_celsius());
+                    if !range.contains(tip) {
+                        failures.push(fail(format!("tip is {} °C, expected {}", tip, range)));
+                    }
+                }
+                Step::ExpectSetpoint(expected) => {
+                    if board.setpoint() != expected {
+                        failures.push(fail(format!("setpoint is {:?}, expected {:?}",
+                                                   board.setpoint(), expected)));
+                    }
+                }
+                Step::ExpectSaved(expected) => {
+                    if board.flash.is_some() != expected {
+                        failures.push(fail(format!("settings saved: {}", board.flash.is_some())));
+                    }
+                }
+                Step::Send(ref text) => board.console_write(format!("{}\r\n", text).as_bytes()),
+                Step::Reply(ref expected) => {
+                    replies.push_str(&String::from_utf8_lossy(&board.console_read()));
+                    let line = match replies.find("\r\n") {
+                        Some(end) => {
+                            let line = replies[..end].to_string();
+                            replies.drain(..end + 2);
+                            line
+                        }
+                        None => {
+                            failures.push(fail(format!("no reply, expected {}", expected)));
+                            continue;
+                        }
+                    };
+
+                    let matches = match expected.strip_suffix("...") {
+                        Some(start) => line.starts_with(start),
+                        None => line == *expected,
+                    };
+                    if !matches {
+                        failures.push(fail(format!("reply is {:?}, expected {:?}", line, expected)));
+                    }
+                }
+                Step::Snapshot(ref name) => {
+                    let path = golden.join(format!("{}.pbm", name));
+                    let actual = pbm(&board.framebuffer);
+                    if bless {
+                        File::create(&path)?.write_all(actual.as_bytes())?;
+                        continue;
+                    }
+
+                    match read_text(&path) {
+                        Ok(ref expected) if *expected == actual => {}
+                        Ok(_) => failures.push(fail(format!(
+                            "screen differs from {}\n{}",
+                            path.display(),
+                            display::terminal(&board.framebuffer)
+                        ))),
+                        Err(error) => {
+                            failures.push(fail(format!("{}: {}", path.display(), error)))
+                        }
+                    }
+                }
+            }
+        }
+
+        Ok(failures)
+    }
+}
+
+fn on_off(on: bool) -> &'static str {
+    if on {
+        "on"
+    } else {
+        "off"
+    }
+}
+
+fn parse_step(words: &[&str]) -> Result<Step, String> {
+    let arg = |index: usize| {
+        words.get(index).cloned().ok_or_else(|| format!("{} needs an argument", words[0]))
+    };
+    let number = |index: usize| -> Result<f32, String> {
+        let word = arg(index)?;
+        word.parse().map_err(|_| format!("{} isn't a number", word))
+    };
+    let ms = |index: usize| -> Result<u32, String> {
+        let word = arg(index)?;
+        word.parse().map_err(|_| format!("{} isn't a duration in ms", word))
+    };
+
+    let step = match (words[0], words.get(1).cloned()) {
+        ("wait", _) => Step::Wait(ms(1)?),
+        ("click", _) => Step::Hold(parse_keys(arg(1)?)?, CLICK),
+        ("hold", _) => Step::Hold(parse_keys(arg(1)?)?, ms(2)?),
+        ("press", _) => Step::Button(parse_button(arg(1)?)?, true),
+        ("release", _) => Step::Button(parse_button(arg(1)?)?, false),
+        ("move", _) => Step::Move(ms(1)?),
+        ("tap", _) => Step::Gesture(Gesture::Tap),
+        ("double-tap", _) => Step::Gesture(Gesture::DoubleTap),
+        ("no-accel", _) => Step::NoAccel,
+        ("ambient", _) => Step::Ambient(number(1)?),
+        ("tip", _) => Step::Tip(number(1)?),
+        ("supply", _) => Step::Supply(number(1)?),
+        ("joint", _) => Step::Joint(parse_flag(arg(1)?, "on", "off")?),
+        ("expect", Some("state")) => Step::ExpectState(arg(2)?.to_string()),
+        ("expect", Some("heater")) => Step::ExpectHeater(parse_flag(arg(2)?, "on", "off")?),
+        ("expect", Some("duty")) => Step::ExpectDuty(parse_range(arg(2)?)?),
+        ("expect", Some("tip")) => Step::ExpectTip(parse_range(arg(2)?)?),
+        ("expect", Some("setpoint")) => Step::ExpectSetpoint(match arg(2)? {
+            "off" => None,
+            word => Some(word.parse().map_err(|_| format!("{} isn't a setpoint", word))?),
+        }),
+        ("expect", Some("saved")) => Step::ExpectSaved(parse_flag(arg(2)?, "yes", "no")?),
+        ("expect", Some(other)) => return Err(format!("can't expect {}", other)),
+        ("snapshot", _) => Step::Snapshot(arg(1)?.to_string()),
+        ("send", _) => Step::Send(words[1..].join(" ")),
+        ("reply", _) => {
+            arg(1)?;
+            Step::Reply(words[1..].join(" "))
+        }
+        (other, _) => return Err(format!("unknown step {}", other)),
+    };
+
+    Ok(step)
+}
+
+fn parse_keys(word: &str) -> Result<Keys, String> {
+    match word {
+        "A" => Ok(Keys::A),
+        "B" => Ok(Keys::B),
+        "AB" => Ok(Keys::AB),
+        _ => Err(format!("{} isn't a key", word)),
+    }
+}
+
+fn parse_button(word: &str) -> Result<Button, String> {
+    match word {
+        "A" => Ok(Button::A),
+        "B" => Ok(Button::B),
+        _ => Err(format!("{} isn't a button", word)),
+    }
+}
+
+fn parse_flag(word: &str, yes: &str, no: &str) -> Result<bool, String> {
+    if word == yes {
+        Ok(true)
+    } else if word == no {
+        Ok(false)
+    } else {
+        Err(format!("expected {} or {}, got {}", yes, no, word))
+    }
+}
+
+fn parse_range(word: &str) -> Result<Range, String> {
+    let parse = |text: &str| text.parse().map_err(|_| format!("{} isn't a range", word));
+    let mut parts = word.splitn(2, "..");
+    let min = parse(parts.next().unwrap_or(""))?;
+    let max = match parts.next() {
+        Some(max) => parse(max)?,
+        None => min,
+    };
+
+    if min > max {
+        return Err(format!("{} is empty", word));
+    }
+    Ok(Range { min, max })
+}
+
+/// Encodes `fb` as plain PBM with one pixel row per line
+pub fn pbm(fb: &Framebuffer) -> String {
+    let mut text = format!("P1\n{} {}\n", WIDTH, display::HEIGHT);
+    for y in 0..display::HEIGHT {
+        for x in 0..WIDTH {
+            text.push(if fb.pixel(x, y) { '1' } else { '0' });
+        }
+        text.push('\n');
+    }
+    text
+}
+
+fn read_text(path: &Path) -> io::Result<String> {
+    let mut text = String::new();
+    File::open(path)?.read_to_string(&mut text)?;
+    Ok(text)
+}
+
+/// Fuzzing runs of the console and their length in received packets
+const FUZZ_RUNS: u32 = 500;
+const FUZZ_CHUNKS: usize = 200;
+/// Random records framed and decoded by the trace check
+const TRACE_RECORDS: usize = 5000;
+
+/// Checks run after the scenario files, selected by name like them
+pub const CHECKS: [&str; 5] = ["transitions", "console-fuzz", "ctl", "trace", "log"];
+
+/// Runs the `*.scenario` files in `dir` named in `filter`, or all of them,
+/// and the checks of `CHECKS` named in `filter`, or all of them, reports the
+/// results on stdout and returns the number of failures
+pub fn run_all(dir: &Path, filter: &[String], bless: bool, seed: u32) -> io::Result<usize> {
+    let selected = |name: &str| filter.is_empty() || filter.iter().any(|wanted| wanted == name);
+    let (mut total, mut failed) = run_scenarios(dir, selected, bless)?;
+
+    for name in CHECKS.iter().filter(|name| selected(name)) {
+        total += 1;
+        if !run_check(dir, name, bless, seed)? {
+            failed += 1;
+        }
+    }
+
+    println!("\n{} scenarios, {} failed", total, failed);
+    Ok(failed)
+}
+
+/// Runs the `*.scenario` files in `dir` whose name is `selected` against
+/// the golden images in `dir/golden`, returns how many ran and failed
+pub fn run_scenarios<F>(dir: &Path, selected: F, bless: bool) -> io::Result<(usize, usize)>
+    where F: Fn(&str) -> bool
+{
+    let mut paths: Vec<_> = fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| path.extension() == Some("scenario".as_ref()))
+        .filter(|path| selected(path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("")))
+        .collect();
+    paths.sort();
+
+    let golden = dir.join("golden");
+    if bless {
+        fs::create_dir_all(&golden)?;
+    }
+
+    let mut failed = 0;
+    for path in &paths {
+        let name = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or("?");
+        let mut text = String::new();
+        File::open(path)?.read_to_string(&mut text)?;
+
+        let failures = match Scenario::parse(&text) {
+            Ok(scenario) => scenario.run(&golden, bless)?,
+            Err(error) => {
+                println!("{} ... invalid, {}", name, error);
+                failed += 1;
+                continue;
+            }
+        };
+
+        if failures.is_empty() {
+            println!("{} ... ok", name);
+        } else {
+            println!("{} ... FAILED", name);
+            for failure in &failures {
+                println!("    {}", failure);
+            }
+            failed += 1;
+        }
+    }
+
+    Ok((paths.len(), failed))
+}
+
+/// Runs the check `name` of `CHECKS` with the random ones starting at
+/// `seed`, reports the result on stdout and returns whether it passed
+///
+/// `transitions` compares the transition table with `dir/golden`, `bless`
+/// rewrites it instead.
+pub fn run_check(dir: &Path, name: &str, bless: bool, seed: u32) -> io::Result<bool> {
+    let result = match name {
+        "transitions" => {
+            let golden = dir.join("golden");
+            if bless {
+                fs::create_dir_all(&golden)?;
+            }
+            return check_transitions(&golden.join("transitions.txt"), bless);
+        }
+        "console-fuzz" => {
+            console::fuzz(seed, FUZZ_RUNS, FUZZ_CHUNKS).map_err(|failure| failure.to_string())
+        }
+        "ctl" => ctl::check(),
+        "trace" => trace::check(seed, TRACE_RECORDS),
+        "log" => log::check(),
+        _ => Err(format!("there's no check {}", name)),
+    };
+
+    match result {
+        Ok(()) => {
+            println!("{} ... ok", name);
+            Ok(true)
+        }
+        Err(message) => {
+            println!("{} ... FAILED\n    {}", name, message);
+            Ok(false)
+        }
+    }
+}
+
+/// Compares the table of all transitions with the golden one at `path` and
+/// checks the rules of the hierarchy, see `transitions`
+fn check_transitions(path: &Path, bless: bool) -> io::Result<bool> {
+    let (actual, problems) = transitions::table();
+    if bless {
+        File::create(path)?.write_all(actual.as_bytes())?;
+    }
+
+    let expected = if bless { actual.clone() } else { read_text(path).unwrap_or_default() };
+    let changed: Vec<_> = expected.lines()
+        .zip(actual.lines())
+        .filter(|&(expected, actual)| expected != actual)
+        .collect();
+    let ok = problems.is_empty() && changed.is_empty()
+        && expected.lines().count() == actual.lines().count();
+
+    if ok {
+        println!("transitions ... ok");
+    } else {
+        println!("transitions ... FAILED");
+        for problem in &problems {
+            println!("    {}", problem);
+        }
+        for &(expected, actual) in &changed {
+            println!("    - {}\n    + {}", expected, actual);
+        }
+        if expected.lines().count() != actual.lines().count() {
+            println!("    {} differs in length", path.display());
+        }
+    }
+
+    Ok(ok)
+}
@@ -0,0 +1,193 @@
+//! Control loop records as CSV
+//!
+//! The firmware streams `ts100_core::trace` frames over RTT, the simulator
+//! records the same stream with `ts100-sim --trace FILE`. `ts100-trace`
+//! turns a recording into one CSV line per record for plotting or a
+//! spreadsheet, damaged frames are skipped and counted.
+
+use board::Board;
+use rng::Rng;
+use std::io::{self, Write};
+use ts100_core::state::STATES;
+use ts100_core::trace::{Decoder, Record, FRAME_MAX};
+
+pub const HEADER: &str = "time_ms,tip,tip_raw,cold_junction,setpoint,error,duty,heating,state";
+
+/// Records decoded from a recording and frames skipped
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stats {
+    pub records: usize,
+    pub invalid: usize,
+}
+
+/// CSV line of `record` without line break, the setpoint is empty while
+/// the heater has to be off
+pub fn to_csv(record: &Record) -> String {
+    let setpoint = record.setpoint.map_or(String::new(), |c| c.to_string());
+    let state = match record.state() {
+        Some(state) => format!("{:?}", state),
+        None => format!("#{}", record.state),
+    };
+    format!("{},{},{},{},{},{},{},{},{}",
+            record.time,
+            record.tip,
+            record.tip_raw,
+            record.cold_junction,
+            setpoint,
+            record.error,
+            record.duty,
+            u8::from(record.heating),
+            state)
+}
+
+/// Parses a line written by `to_csv`
+pub fn parse_csv(line: &str) -> Option<Record> {
+    let fields: Vec<&str> = line.trim_end().split(',').collect();
+    if fields.len() != 9 {
+        return None;
+    }
+
+    let state = match fields[8].strip_prefix('#') {
+        Some(index) => index.parse().ok()?,
+        None => STATES.iter().position(|state| format!("{:?}", state) == fields[8])? as u8,
+    };
+    Some(Record {
+        time: fields[0].parse().ok()?,
+        tip: fields[1].parse().ok()?,
+        tip_raw: fields[2].parse().ok()?,
+        cold_junction: fields[3].parse().ok()?,
+        setpoint: if fields[4].is_empty() { None } else { Some(fields[4].parse().ok()?) },
+        error: fields[5].parse().ok()?,
+        duty: fields[6].parse().ok()?,
+        heating: match fields[7] {
+            "0" => false,
+            "1" => true,
+            _ => return None,
+        },
+        state,
+    })
+}
+
+/// Decodes the recording `data` and writes it to `out` as CSV with header
+pub fn write_csv<W: Write>(data: &[u8], out: &mut W) -> io::Result<Stats> {
+    let mut decoder = Decoder::new();
+    let mut stats = Stats::default();
+
+    writeln!(out, "{}", HEADER)?;
+    for &byte in data {
+        match decoder.push(byte) {
+            Some(Ok(record)) => {
+                writeln!(out, "{}", to_csv(&record))?;
+                stats.records += 1;
+            }
+            Some(Err(_)) => stats.invalid += 1,
+            None => {}
+        }
+    }
+    Ok(stats)
+}
+
+/// Reads the CSV written by `write_csv` back
+pub fn read_csv(text: &str) -> Result<Vec<Record>, String> {
+    let mut lines = text.lines();
+    if lines.next() != Some(HEADER) {
+        return Err("missing header".to_string());
+    }
+    lines.enumerate()
+        .map(|(index, line)| {
+            parse_csv(line).ok_or_else(|| format!("line {}: invalid record {:?}", index + 2, line))
+        })
+        .collect()
+}
+
+/// Random records, framed, mixed with damaged frames and noise, have to
+/// come out of the decoder and the CSV unchanged; a recording of the
+/// simulated board has to decode without a damaged frame
+pub fn check(seed: u32, count: usize) -> Result<(), String> {
+    let mut rng = Rng(seed | 1);
+    let mut data = Vec::new();
+    let mut expected = Vec::new();
+
+    for _ in 0..count {
+        let record = random_record(&mut rng);
+        let mut frame = [0; FRAME_MAX];
+        let len = record.encode(&mut frame);
+
+        match rng.below(8) {
+            // a flipped data bit, the CRC has to catch it
+            0 => {
+                let data_bytes = data_bytes(&frame[..len - 1]);
+                let index = rng.pick(&data_bytes);
+                frame[index] ^= 1 << rng.below(8);
+                if frame[index] == 0 {
+                    frame[index] = 0xff;
+                }
+                data.extend_from_slice(&frame[..len]);
+            }
+            // noise without delimiter joins the next frame, which is lost
+            1 => {
+                data.extend((0..1 + rng.below(40)).map(|_| 1 + rng.below(255) as u8));
+                data.extend_from_slice(&frame[..len]);
+            }
+            // a frame cut short by a reconnect
+            2 => {
+                data.extend_from_slice(&frame[..rng.below(len as u32 - 1) as usize]);
+                data.push(0);
+            }
+            _ => {
+                data.extend_from_slice(&frame[..len]);
+                expected.push(record);
+            }
+        }
+    }
+
+    let mut csv = Vec::new();
+    write_csv(&data, &mut csv).map_err(|error| error.to_string())?;
+    let decoded = read_csv(&String::from_utf8_lossy(&csv))?;
+    if decoded != expected {
+        let position = decoded.iter().zip(&expected).position(|(a, b)| a != b);
+        return Err(format!("seed {}: {} records decoded, {} expected, first difference at {:?}",
+                           seed,
+                           decoded.len(),
+                           expected.len(),
+                           position));
+    }
+
+    let mut board = Board::new();
+    board.trace = Some(Vec::new());
+    board.press(ts100_core::input::Button::A, 100);
+    board.run(5000);
+    let recording = board.trace.take().unwrap_or_default();
+    let stats = write_csv(&recording, &mut io::sink()).map_err(|error| error.to_string())?;
+    if stats.invalid > 0 || stats.records < 240 {
+        return Err(format!("simulated recording: {:?}", stats));
+    }
+    Ok(())
+}
+
+/// Positions of the payload bytes in a COBS frame, the others are codes
+fn data_bytes(frame: &[u8]) -> Vec<usize> {
+    let mut positions = Vec::new();
+    let mut code = 0;
+    while code < frame.len() {
+        let next = code + usize::from(frame[code]);
+        positions.extend(code + 1..next.min(frame.len()));
+        code = next;
+    }
+    positions
+}
+
+fn random_record(rng: &mut Rng) -> Record {
+    let word = |rng: &mut Rng| rng.next_u32() as u16;
+    Record {
+        time: rng.next_u32(),
+        tip: word(rng) as i16,
+        tip_raw: word(rng),
+        cold_junction: word(rng) as i16,
+        setpoint: if rng.below(2) == 0 { None } else { Some(word(rng) as i16) },
+        error: word(rng) as i16,
+        duty: rng.below(101) as u8,
+        heating: rng.below(2) == 0,
+        state: rng.below(STATES.len() as u32 + 2) as u8,
+    }
+}
@@ -0,0 +1,131 @@
+//! Exhaustive check of the state machine over all states and triggers
+//!
+//! Every trigger is fed to a fresh machine in every state. The resulting
+//! table of next states and actions is compared with the checked-in golden
+//! `transitions.txt`, so any change to the behavior shows up in review. The
+//! timeouts are covered by feeding `Second` until they are due. Besides the
+//! golden table the rules of the hierarchy are checked for every pair:
+//!
+//! - a change of state is recorded as the last entry of the transition log
+//! - the heater is switched off whenever `Heating` is left
+//! - the menu timeout is started whenever the menu is entered
+//! - a trigger which doesn't change the state leaves no actions behind,
+//!   except for keys in the menu which restart its timeout and the actions
+//!   of the menu pages
+//! - the journal is read whenever its page is entered
+
+use std::fmt::Write;
+use ts100_core::event::Event;
+use ts100_core::input::{KeyAction, KeyEvent};
+use ts100_core::state::{self, Action, ConfigPage, State, StateMachine, Trigger, STATES, TRIGGERS};
+use ts100_core::time::TimerId;
+
+/// Result of feeding triggers to a machine in `from`
+struct Outcome {
+    to: State,
+    actions: Vec<Action>,
+    problems: Vec<String>,
+}
+
+fn event(trigger: Trigger) -> Event {
+    match trigger {
+        Trigger::Click(key) => Event::Key(KeyEvent { key, action: KeyAction::Click }),
+        Trigger::LongPress(key) => Event::Key(KeyEvent { key, action: KeyAction::LongPress }),
+        Trigger::Gesture(gesture) => Event::Gesture(gesture),
+        Trigger::Motion(motion) => Event::Motion(motion),
+        Trigger::Second => Event::Timer(TimerId::Second),
+        Trigger::MenuTimeout => Event::Timer(TimerId::MenuTimeout),
+    }
+}
+
+fn heating(state: State) -> bool {
+    matches!(state, State::Soldering | State::Boost | State::TemperatureControl | State::Sleep)
+}
+
+fn menu(state: State) -> bool {
+    matches!(state, State::Config(_))
+}
+
+/// Feeds `trigger` `count` times, stops at the first change of state
+fn feed(from: State, trigger: Trigger, count: u32) -> Outcome {
+    let mut machine = StateMachine::starting_in(from);
+    machine.set_accel_present(true);
+
+    let mut actions = Vec::new();
+    let mut problems = Vec::new();
+    for _ in 0..count {
+        machine.update_state(event(trigger));
+        while let Some(action) = machine.take_action() {
+            actions.push(action);
+        }
+        if machine.current_state() != from {
+            break;
+        }
+    }
+
+    let to = machine.current_state();
+    let logged = machine.log().iter().last();
+    if to != from {
+        match logged {
+            Some(entry) if entry.from == from && entry.to == to && entry.trigger == trigger => {}
+            _ => problems.push(format!("transition missing in the log, last entry {:?}", logged)),
+        }
+        if heating(from) && !heating(to) && !actions.contains(&Action::HeaterOff) {
+            problems.push("left Heating without turning the heater off".to_string());
+        }
+        if !menu(from) && menu(to) && !actions.contains(&Action::StartMenuTimeout) {
+            problems.push("entered the menu without starting its timeout".to_string());
+        }
+        if to == State::Config(ConfigPage::Events) && !actions.contains(&Action::ShowEvents) {
+            problems.push("entered the events page without reading the journal".to_string());
+        }
+    } else {
+        let key = matches!(trigger, Trigger::Click(_) | Trigger::LongPress(_));
+        let expected = actions.iter().all(|&action| {
+            action == Action::CalibrateAccel || action == Action::OlderEvent
+                || (menu(from) && key && action == Action::StartMenuTimeout)
+        });
+        if !expected {
+            problems.push(format!("unexpected actions {:?} without a transition", actions));
+        }
+        if logged.is_some() {
+            problems.push(format!("logged {:?} without a transition", logged));
+        }
+    }
+
+    Outcome { to, actions, problems }
+}
+
+/// Builds the table of all transitions, returns it with the violated rules
+pub fn table() -> (String, Vec<String>) {
+    let mut cases: Vec<(Trigger, u32, String)> = TRIGGERS.iter()
+        .map(|&trigger| (trigger, 1, format!("{:?}", trigger)))
+        .collect();
+    cases.push((Trigger::Second, state::BOOST_TIMEOUT, format!("Second x{}", state::BOOST_TIMEOUT)));
+    cases.push((Trigger::Second, state::SLEEP_TIMEOUT, format!("Second x{}", state::SLEEP_TIMEOUT)));
+
+    let mut text = String::new();
+    let mut problems = Vec::new();
+    for &from in STATES.iter() {
+        for &(trigger, count, ref name) in &cases {
+            let outcome = feed(from, trigger, count);
+            let to = if outcome.to == from {
+                "-".to_string()
+            } else {
+                format!("{:?}", outcome.to)
+            };
+            let _ = write!(text, "{:<24} {:<20} {:<24}", format!("{:?}", from), name, to);
+            if !outcome.actions.is_empty() {
+                let _ = write!(text, " {:?}", outcome.actions);
+            }
+            text = text.trim_end().to_string();
+            text.push('\n');
+
+            for problem in outcome.problems {
+                problems.push(format!("{:?} {}: {}", from, name, problem));
+            }
+        }
+    }
+
+    (text, problems)
+}
@@ -0,0 +1,56 @@
+//! Runs every scenario in `scenarios/` and every check of
+//! `scenario::CHECKS` as part of `cargo test`, the same as `ts100-scenario`
+//! without arguments
+
+extern crate ts100_simulator;
+
+use std::path::PathBuf;
+use ts100_simulator::scenario;
+
+/// Seed `ts100-scenario` starts the random checks at
+const SEED: u32 = 1;
+
+fn dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("scenarios")
+}
+
+fn check(name: &str) {
+    assert!(scenario::run_check(&dir(), name, false, SEED).unwrap(), "{} failed, see above", name);
+}
+
+#[test]
+fn scenarios() {
+    let (total, failed) = scenario::run_scenarios(&dir(), |_| true, false).unwrap();
+    assert!(total > 0);
+    assert_eq!(failed, 0, "{} of {} scenarios failed, see above", failed, total);
+}
+
+#[test]
+fn transitions() {
+    check("transitions");
+}
+
+#[test]
+fn console_fuzz() {
+    check("console-fuzz");
+}
+
+#[test]
+fn ctl() {
+    check("ctl");
+}
+
+#[test]
+fn trace() {
+    check("trace");
+}
+
+#[test]
+fn log() {
+    check("log");
+}
+
+#[test]
+fn every_check_has_a_test() {
+    assert_eq!(scenario::CHECKS, ["transitions", "console-fuzz", "ctl", "trace", "log"]);
+}